@@ -0,0 +1,31 @@
+#!/bin/sh
+# Refresh the copy of the chip-8-database that is built into the interpreter, trimmed
+# to the fields it reads, or download the full database into the given directory, for
+# the `directory` setting of the ROM database. Needs curl, and jq for the trimming.
+set -e
+base=https://raw.githubusercontent.com/chip-8/chip-8-database/master/database
+if [ $# -gt 1 ]; then
+    echo "usage: $0 [DIRECTORY]" >&2
+    exit 1
+fi
+if [ $# -eq 1 ]; then
+    mkdir -p "$1"
+    cd "$1"
+    for file in programs.json sha1-hashes.json; do
+        curl -fsSL -o "$file" "$base/$file"
+    done
+    exit 0
+fi
+cd "$(dirname "$0")/chip8-database"
+curl -fsSL "$base/programs.json" | jq -c 'map({
+    title,
+    authors: (.authors // []),
+    roms: ((.roms // {}) | map_values({
+        platforms: (.platforms // []),
+        quirkyPlatforms: (.quirkyPlatforms // {}),
+        tickrate,
+        colors: (if .colors.pixels then {pixels: .colors.pixels} else null end),
+        keys: (.keys // {})
+    } | with_entries(select(.value != null))))
+})' > programs.json
+curl -fsSL "$base/sha1-hashes.json" | jq -c . > sha1-hashes.json
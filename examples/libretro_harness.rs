@@ -0,0 +1,282 @@
+//! Loads the libretro core like a libretro frontend would, runs a program for a number
+//! of frames and checks that save states restore the same frames. The last frame is
+//! printed as text.
+//!
+//! cargo build --release --no-default-features --features libretro
+//! cargo run --example libretro_harness -- target/release/libchip8.so ROM_FILE [FRAMES] [KEY=VALUE...]
+//!
+//! KEY=VALUE sets a core option, e.g. chip8_speed=2. The system directory of the
+//! frontend is taken from the environment variable LIBRETRO_SYSTEM_DIRECTORY.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{c_void, CStr, CString};
+use std::fs;
+use std::os::raw::{c_char, c_uint};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use libloading::{Library, Symbol};
+
+const RETRO_ENVIRONMENT_SHUTDOWN: c_uint = 7;
+const RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY: c_uint = 9;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS: c_uint = 11;
+const RETRO_ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const RETRO_ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+
+#[repr(C)]
+struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct Variable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[repr(C)]
+struct SystemAvInfo {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+    fps: f64,
+    sample_rate: f64,
+}
+
+/// The last frame shown by the core, with its width.
+static FRAME: Mutex<(Vec<u32>, usize)> = Mutex::new((Vec::new(), 0));
+/// The number of audio frames played by the core.
+static AUDIO_FRAMES: AtomicUsize = AtomicUsize::new(0);
+/// The core options set on the command line.
+static OPTIONS: OnceLock<HashMap<CString, CString>> = OnceLock::new();
+/// The system directory, if one is set.
+static SYSTEM_DIRECTORY: OnceLock<Option<CString>> = OnceLock::new();
+
+unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => {
+            *(data as *const c_uint) == RETRO_PIXEL_FORMAT_XRGB8888
+        }
+        RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS => true,
+        RETRO_ENVIRONMENT_SHUTDOWN => {
+            println!("The core requested to shut down");
+            true
+        }
+        RETRO_ENVIRONMENT_SET_VARIABLES => {
+            let mut variable = data as *const Variable;
+            while !(*variable).key.is_null() {
+                println!(
+                    "Option {}: {}",
+                    CStr::from_ptr((*variable).key).to_string_lossy(),
+                    CStr::from_ptr((*variable).value).to_string_lossy()
+                );
+                variable = variable.add(1);
+            }
+            true
+        }
+        RETRO_ENVIRONMENT_GET_VARIABLE => {
+            let variable = &mut *(data as *mut Variable);
+            let key = CStr::from_ptr(variable.key);
+            match OPTIONS.get().and_then(|options| options.get(key)) {
+                Some(value) => {
+                    variable.value = value.as_ptr();
+                    true
+                }
+                None => false,
+            }
+        }
+        RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY => {
+            let directory = SYSTEM_DIRECTORY.get_or_init(|| {
+                env::var("LIBRETRO_SYSTEM_DIRECTORY")
+                    .ok()
+                    .and_then(|directory| CString::new(directory).ok())
+            });
+            match directory {
+                Some(directory) => {
+                    *(data as *mut *const c_char) = directory.as_ptr();
+                    true
+                }
+                None => false,
+            }
+        }
+        RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE => {
+            *(data as *mut bool) = false;
+            true
+        }
+        _ => false,
+    }
+}
+
+unsafe extern "C" fn video_refresh(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+) {
+    if data.is_null() {
+        return;
+    }
+    let mut frame = FRAME.lock().unwrap();
+    frame.0.clear();
+    for y in 0..height as usize {
+        let row = (data as *const u8).add(y * pitch) as *const u32;
+        frame
+            .0
+            .extend_from_slice(std::slice::from_raw_parts(row, width as usize));
+    }
+    frame.1 = width as usize;
+}
+
+unsafe extern "C" fn audio_sample(_left: i16, _right: i16) {}
+
+unsafe extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+    AUDIO_FRAMES.fetch_add(frames, Ordering::Relaxed);
+    frames
+}
+
+unsafe extern "C" fn input_poll() {}
+
+unsafe extern "C" fn input_state(
+    _port: c_uint,
+    _device: c_uint,
+    _index: c_uint,
+    _id: c_uint,
+) -> i16 {
+    0
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 3 {
+        eprintln!("Usage: {} CORE ROM_FILE [FRAMES] [KEY=VALUE...]", args[0]);
+        std::process::exit(1);
+    }
+    let frames = args.get(3).map_or(120, |frames| {
+        frames.parse().expect("Invalid number of frames")
+    });
+    let options = args
+        .iter()
+        .skip(4)
+        .map(|option| {
+            let (key, value) = option
+                .split_once('=')
+                .expect("Options are given as KEY=VALUE");
+            (CString::new(key).unwrap(), CString::new(value).unwrap())
+        })
+        .collect();
+    OPTIONS.set(options).unwrap();
+    let program = fs::read(&args[2]).expect("Could not read program");
+
+    let core = Library::new(&args[1]).expect("Could not load core");
+    unsafe {
+        let api_version: Symbol<unsafe extern "C" fn() -> c_uint> =
+            core.get(b"retro_api_version\0").unwrap();
+        println!("API version {}", api_version());
+
+        let set_environment: Symbol<
+            unsafe extern "C" fn(unsafe extern "C" fn(c_uint, *mut c_void) -> bool),
+        > = core.get(b"retro_set_environment\0").unwrap();
+        set_environment(environment);
+        let set_video_refresh: Symbol<
+            unsafe extern "C" fn(unsafe extern "C" fn(*const c_void, c_uint, c_uint, usize)),
+        > = core.get(b"retro_set_video_refresh\0").unwrap();
+        set_video_refresh(video_refresh);
+        let set_audio_sample: Symbol<unsafe extern "C" fn(unsafe extern "C" fn(i16, i16))> =
+            core.get(b"retro_set_audio_sample\0").unwrap();
+        set_audio_sample(audio_sample);
+        let set_audio_sample_batch: Symbol<
+            unsafe extern "C" fn(unsafe extern "C" fn(*const i16, usize) -> usize),
+        > = core.get(b"retro_set_audio_sample_batch\0").unwrap();
+        set_audio_sample_batch(audio_sample_batch);
+        let set_input_poll: Symbol<unsafe extern "C" fn(unsafe extern "C" fn())> =
+            core.get(b"retro_set_input_poll\0").unwrap();
+        set_input_poll(input_poll);
+        let set_input_state: Symbol<
+            unsafe extern "C" fn(unsafe extern "C" fn(c_uint, c_uint, c_uint, c_uint) -> i16),
+        > = core.get(b"retro_set_input_state\0").unwrap();
+        set_input_state(input_state);
+
+        let init: Symbol<unsafe extern "C" fn()> = core.get(b"retro_init\0").unwrap();
+        init();
+        let load_game: Symbol<unsafe extern "C" fn(*const GameInfo) -> bool> =
+            core.get(b"retro_load_game\0").unwrap();
+        let game = GameInfo {
+            path: ptr::null(),
+            data: program.as_ptr() as *const c_void,
+            size: program.len(),
+            meta: ptr::null(),
+        };
+        assert!(load_game(&game), "The core did not load the program");
+
+        let get_system_av_info: Symbol<unsafe extern "C" fn(*mut SystemAvInfo)> =
+            core.get(b"retro_get_system_av_info\0").unwrap();
+        let mut av_info = std::mem::zeroed::<SystemAvInfo>();
+        get_system_av_info(&mut av_info);
+        println!(
+            "{}x{} at {} fps, {} Hz audio",
+            av_info.base_width, av_info.base_height, av_info.fps, av_info.sample_rate
+        );
+
+        let run: Symbol<unsafe extern "C" fn()> = core.get(b"retro_run\0").unwrap();
+        for _ in 0..frames {
+            run();
+        }
+
+        let serialize_size: Symbol<unsafe extern "C" fn() -> usize> =
+            core.get(b"retro_serialize_size\0").unwrap();
+        let serialize: Symbol<unsafe extern "C" fn(*mut c_void, usize) -> bool> =
+            core.get(b"retro_serialize\0").unwrap();
+        let unserialize: Symbol<unsafe extern "C" fn(*const c_void, usize) -> bool> =
+            core.get(b"retro_unserialize\0").unwrap();
+        let mut state = vec![0u8; serialize_size()];
+        assert!(
+            serialize(state.as_mut_ptr() as *mut c_void, state.len()),
+            "Could not save state"
+        );
+        for _ in 0..frames {
+            run();
+        }
+        let expected = FRAME.lock().unwrap().0.clone();
+        assert!(
+            unserialize(state.as_ptr() as *const c_void, state.len()),
+            "Could not load state"
+        );
+        for _ in 0..frames {
+            run();
+        }
+        assert!(
+            FRAME.lock().unwrap().0 == expected,
+            "The save state did not restore the same frames"
+        );
+        println!(
+            "Save state of {} bytes restores the same frames",
+            state.len()
+        );
+        println!("{} audio frames", AUDIO_FRAMES.load(Ordering::Relaxed));
+
+        let deinit: Symbol<unsafe extern "C" fn()> = core.get(b"retro_deinit\0").unwrap();
+        let unload_game: Symbol<unsafe extern "C" fn()> = core.get(b"retro_unload_game\0").unwrap();
+        unload_game();
+        deinit();
+    }
+
+    let frame = FRAME.lock().unwrap();
+    let background = frame.0.first().copied().unwrap_or(0);
+    for row in frame.0.chunks(frame.1.max(1)) {
+        let line = row
+            .iter()
+            .map(|pixel| if *pixel == background { '.' } else { '#' })
+            .collect::<String>();
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,202 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+
+use crate::config::BUZZER_RAMP_SECONDS;
+
+/// The shape of the buzzer tone.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+    /// Random values, changing twice per period of the tone frequency.
+    Noise,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            "noise" => Ok(Waveform::Noise),
+            _ => Err(format!(
+                "Unknown waveform: {} (available: square, sine, triangle, noise)",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Waveform {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Waveform::Square => write!(f, "square"),
+            Waveform::Sine => write!(f, "sine"),
+            Waveform::Triangle => write!(f, "triangle"),
+            Waveform::Noise => write!(f, "noise"),
+        }
+    }
+}
+
+/// The sound of the buzzer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tone {
+    /// Frequency of the tone in Hz.
+    pub frequency: f32,
+    pub waveform: Waveform,
+    /// Volume from 0 (silent) to 1.
+    pub volume: f32,
+}
+
+/// Generates the tone of the buzzer. Switching the buzzer on and off fades the tone
+/// in and out over a few milliseconds, to avoid clicks.
+pub struct Buzzer {
+    tone: Tone,
+    phase_inc: f32,
+    phase: f32,
+    envelope: f32,
+    envelope_inc: f32,
+    on: bool,
+    noise: f32,
+    noise_state: u32,
+}
+
+impl Buzzer {
+    /// Create a buzzer that produces samples at the given rate (in Hz).
+    pub fn new(sample_rate: i32, tone: Tone) -> Self {
+        Buzzer {
+            tone,
+            phase_inc: tone.frequency / sample_rate as f32,
+            phase: 0.0,
+            envelope: 0.0,
+            envelope_inc: 1.0 / (BUZZER_RAMP_SECONDS * sample_rate as f32),
+            on: false,
+            noise: 0.0,
+            noise_state: 0x2545_F491,
+        }
+    }
+
+    /// Switch the tone on or off, starting with the next sample.
+    pub fn set_on(&mut self, on: bool) {
+        self.on = on;
+    }
+
+    /// Fill the buffer with the next samples of the tone.
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            if self.on {
+                self.envelope = (self.envelope + self.envelope_inc).min(1.0);
+            } else {
+                self.envelope = (self.envelope - self.envelope_inc).max(0.0);
+            }
+            if self.envelope == 0.0 {
+                *x = 0.0;
+                continue;
+            }
+
+            *x = self.wave() * self.tone.volume * self.envelope;
+            let previous_phase = self.phase;
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+            if (previous_phase < 0.5) != (self.phase < 0.5) {
+                self.next_noise();
+            }
+        }
+    }
+
+    /// Fill the buffer with the samples of a frame, switching the tone on and off at
+    /// the given changes. The position of a change is its share of the frame, from 0 to 1.
+    pub fn render(&mut self, out: &mut [f32], changes: &[(f32, bool)]) {
+        let mut start = 0;
+        for (position, on) in changes {
+            let end = ((position * out.len() as f32) as usize)
+                .max(start)
+                .min(out.len());
+            self.fill(&mut out[start..end]);
+            self.set_on(*on);
+            start = end;
+        }
+        self.fill(&mut out[start..]);
+    }
+
+    /// Value of the waveform at the current phase, from -1 to 1.
+    fn wave(&self) -> f32 {
+        match self.tone.waveform {
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (2.0 * PI * self.phase).sin(),
+            Waveform::Triangle => {
+                if self.phase < 0.5 {
+                    4.0 * self.phase - 1.0
+                } else {
+                    3.0 - 4.0 * self.phase
+                }
+            }
+            Waveform::Noise => self.noise,
+        }
+    }
+
+    /// Advance the xorshift generator of the noise waveform.
+    fn next_noise(&mut self) {
+        self.noise_state ^= self.noise_state << 13;
+        self.noise_state ^= self.noise_state >> 17;
+        self.noise_state ^= self.noise_state << 5;
+        self.noise = self.noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0;
+    }
+}
+
+/// Collects the emulated times at which the sound timer switches the buzzer on and off.
+#[derive(Default)]
+pub struct SoundChanges {
+    on: bool,
+    changes: Vec<(u128, bool)>,
+}
+
+impl SoundChanges {
+    pub fn new() -> Self {
+        SoundChanges {
+            on: false,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Note whether the buzzer is on at the given emulated time (in microseconds).
+    pub fn update(&mut self, time: u128, on: bool) {
+        if on != self.on {
+            self.changes.push((time, on));
+            self.on = on;
+        }
+    }
+
+    /// Take the changes of the frame between the given emulated times, as positions
+    /// relative to the frame as expected by `Buzzer::render`.
+    pub fn take_frame(&mut self, start: u128, end: u128) -> Vec<(f32, bool)> {
+        let frame_length = end.saturating_sub(start).max(1) as f32;
+        self.changes
+            .drain(..)
+            .map(|(time, on)| {
+                let position = time.saturating_sub(start) as f32 / frame_length;
+                (position.min(1.0), on)
+            })
+            .collect()
+    }
+}
@@ -1,27 +1,161 @@
-use sdl2::keyboard::Keycode;
-
 pub const DEFAULT_REFRESH_RATE: u32 = 60;
 
 pub const SCREEN_X: usize = 64;
 pub const SCREEN_Y: usize = 32;
+/// Default address that programs are loaded at and start running at.
+pub const DEFAULT_PROGRAM_START: u16 = 0x200;
+/// Default address of the font in memory.
+pub const DEFAULT_FONT_ADDRESS: u16 = 0;
+/// Default size of the memory in bytes.
+pub const DEFAULT_MEMORY_SIZE: usize = 4096;
+/// Default number of return addresses that the call stack holds.
+pub const DEFAULT_STACK_DEPTH: usize = 16;
+
+/// Default size of a CHIP-8 pixel in the window.
+#[cfg(feature = "sdl")]
+pub const DEFAULT_WINDOW_SCALE: u32 = 10;
+/// How much darker the lines of the scanline and grid effects are than the pixels.
+#[cfg(feature = "sdl")]
+pub const EFFECT_DARKENING: f32 = 0.6;
+
+pub const AUDIO_SAMPLE_RATE: i32 = 44100;
+/// Time (in seconds) of sound that is queued ahead, to bridge irregular frame times.
+#[cfg(feature = "sdl")]
+pub const AUDIO_LATENCY_SECONDS: f32 = 0.05;
+pub const DEFAULT_BUZZER_FREQUENCY: f32 = 440.0;
+pub const DEFAULT_BUZZER_VOLUME: f32 = 0.25;
+/// Time (in seconds) that the buzzer takes to fade in or out.
+pub const BUZZER_RAMP_SECONDS: f32 = 0.005;
+
+/// Default share of its brightness that a pixel keeps per frame with the decay filter.
+pub const DEFAULT_DECAY: f32 = 0.6;
+/// Brightness below which a fading pixel of the decay filter turns off.
+pub const DECAY_MIN_INTENSITY: f32 = 1.0 / 256.0;
+/// Default number of frames that the blend filter averages.
+pub const DEFAULT_BLEND_FRAMES: usize = 3;
+pub const MAX_BLEND_FRAMES: usize = 8;
+/// Number of frames that drawing on vertical blank waits at most for the display to settle.
+pub const VBLANK_MAX_SKIPPED_FRAMES: u32 = 4;
+
+/// Default bindings of the hex keypad keys 0 to F. The keypad is bound by physical
+/// key position, so that it keeps its shape on any keyboard layout.
+pub const DEFAULT_KEYPAD: [&str; 16] = [
+    "scancode:C",
+    "scancode:2",
+    "scancode:3",
+    "scancode:4",
+    "scancode:W",
+    "scancode:E",
+    "scancode:R",
+    "scancode:S",
+    "scancode:D",
+    "scancode:F",
+    "scancode:X",
+    "scancode:V",
+    "scancode:5",
+    "scancode:T",
+    "scancode:G",
+    "scancode:B",
+];
+
+/// Default bindings of the interpreter controls.
+pub const DEFAULT_CONTROLS: [(&str, &str); 12] = [
+    ("reset", "Backspace"),
+    ("step", "Space"),
+    ("exit", "Escape"),
+    ("palette", "P"),
+    ("screenshot", "F12"),
+    ("record", "F9"),
+    ("fullscreen", "F11"),
+    ("pause", "F6"),
+    ("frame_advance", "F7"),
+    ("step_mode", "F8"),
+    ("fast_forward", "Tab"),
+    ("launcher", "F1"),
+];
+
+/// Default speed of the instructions, as multiple of their timing on the COSMAC VIP.
+pub const DEFAULT_SPEED: f32 = 1.0;
+/// Lowest speed of the instructions, so that the interpreter does not stall.
+pub const MIN_SPEED: f32 = 0.01;
+
+/// Default speed of the interpreter while fast-forwarding, as multiple of the normal speed.
+pub const DEFAULT_FAST_FORWARD: f32 = 4.0;
+
+/// Default directory that screenshots are saved in.
+pub const DEFAULT_SCREENSHOT_DIRECTORY: &str = "screenshots";
+/// Default directory that recordings are saved in.
+pub const DEFAULT_RECORDING_DIRECTORY: &str = "recordings";
+/// Default size of a CHIP-8 pixel in recordings.
+pub const DEFAULT_RECORDING_SCALE: u32 = 4;
+/// Largest size of a CHIP-8 pixel in recordings, so that a GIF is at most 65535
+/// pixels wide.
+pub const MAX_RECORDING_SCALE: u32 = u16::MAX as u32 / SCREEN_X as u32;
+/// Shortest time (in hundredths of a second) that a frame of a GIF recording is shown.
+pub const GIF_MIN_FRAME_DELAY: u64 = 2;
+
+/// Default time in seconds after which a keypad key counts as released in the
+/// terminal, unless the terminal repeats it.
+pub const DEFAULT_KEY_HOLD_SECONDS: f32 = 0.2;
+/// Longest wall time that the browser frontend catches up on at once, e.g. after the
+/// page was in the background.
+#[cfg(feature = "web")]
+pub const WEB_MAX_CATCH_UP_MICROS: u128 = 100_000;
+
+/// Depth of the call stack of the libretro core if it is set to be unlimited, as
+/// save states of the core have a fixed size.
+#[cfg(feature = "libretro")]
+pub const LIBRETRO_MAX_STACK_DEPTH: usize = 1024;
+/// Directory in the system directory of a libretro frontend that a full copy of the
+/// chip-8-database is read from, instead of the built-in one.
+#[cfg(feature = "libretro")]
+pub const LIBRETRO_DATABASE_DIRECTORY: &str = "chip-8-database";
+
+/// Size of a CHIP-8 pixel in the sixel and kitty images of the terminal frontend.
+#[cfg(not(target_arch = "wasm32"))]
+pub const TERMINAL_IMAGE_SCALE: usize = 4;
+
+/// Default bindings of game controller buttons to the keypad keys that most games
+/// use for movement and action.
+pub const DEFAULT_CONTROLLER_KEYPAD: [(u8, &str); 6] = [
+    (0x2, "dpup"),
+    (0x4, "dpleft"),
+    (0x6, "dpright"),
+    (0x8, "dpdown"),
+    (0x5, "a"),
+    (0xA, "b"),
+];
+
+/// Whether the left analog stick of game controllers acts as the D-pad by default.
+pub const DEFAULT_ANALOG_STICK: bool = false;
+
+/// Deflection of an analog stick axis below which it counts as centered.
+#[cfg(feature = "sdl")]
+pub const ANALOG_STICK_DEAD_ZONE: i16 = 16000;
 
-pub const KEYPAD_1: Keycode = Keycode::Num2;
-pub const KEYPAD_2: Keycode = Keycode::Num3;
-pub const KEYPAD_3: Keycode = Keycode::Num4;
-pub const KEYPAD_C: Keycode = Keycode::Num5;
-pub const KEYPAD_4: Keycode = Keycode::W;
-pub const KEYPAD_5: Keycode = Keycode::E;
-pub const KEYPAD_6: Keycode = Keycode::R;
-pub const KEYPAD_D: Keycode = Keycode::T;
-pub const KEYPAD_7: Keycode = Keycode::S;
-pub const KEYPAD_8: Keycode = Keycode::D;
-pub const KEYPAD_9: Keycode = Keycode::F;
-pub const KEYPAD_E: Keycode = Keycode::G;
-pub const KEYPAD_A: Keycode = Keycode::X;
-pub const KEYPAD_0: Keycode = Keycode::C;
-pub const KEYPAD_B: Keycode = Keycode::V;
-pub const KEYPAD_F: Keycode = Keycode::B;
-
-pub const RESET_KEY: Keycode = Keycode::Backspace;
-pub const INSTRUCTION_STEP_KEY: Keycode = Keycode::Space;
-pub const EXIT_KEY: Keycode = Keycode::Escape;
+/// Default directory that the launcher lists the ROMs of.
+pub const DEFAULT_ROM_DIRECTORY: &str = ".";
+/// File extensions of ROMs and Octo cartridges, which the launcher lists and archives
+/// are searched for.
+pub const ROM_EXTENSIONS: [&str; 4] = ["ch8", "sc8", "xo8", "gif"];
+/// Largest Octo cartridge that is read, as the image is larger than the program it
+/// holds.
+pub const MAX_CARTRIDGE_SIZE: usize = 1 << 20;
+/// Number of recently played ROMs that the launcher remembers.
+#[cfg(feature = "sdl")]
+pub const MAX_RECENT_ROMS: usize = 10;
+/// Height of a line of text in the launcher, in window pixels before scaling.
+#[cfg(feature = "sdl")]
+pub const LAUNCHER_LINE_HEIGHT: i32 = 12;
+/// Number of lines that the launcher shows at least, which sets the scale of its text.
+#[cfg(feature = "sdl")]
+pub const LAUNCHER_MIN_LINES: u32 = 20;
+/// Number of ROMs that Page Up and Page Down move the selection of the launcher by.
+#[cfg(feature = "sdl")]
+pub const LAUNCHER_PAGE: isize = 10;
+/// Interval in milliseconds in which a watched ROM file is checked for changes.
+#[cfg(feature = "sdl")]
+pub const WATCH_INTERVAL_MILLIS: u64 = 500;
+/// Time in seconds that a notice is shown over the display of the window.
+#[cfg(feature = "sdl")]
+pub const NOTICE_SECONDS: u64 = 2;
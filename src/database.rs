@@ -0,0 +1,382 @@
+//! Lookup of programs in the community chip-8-database
+//! (https://github.com/chip-8/chip-8-database) by the SHA-1 hash of the ROM. A copy
+//! of its `programs.json` and `sha1-hashes.json`, trimmed to the fields used here, is
+//! built into the interpreter, and `data/update-database.sh` refreshes it. A newer
+//! copy can also be read from a configured directory.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use log::{info, warn};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use crate::interpreter::Quirks;
+use crate::palette::Palette;
+use crate::settings::Settings;
+
+const BUNDLED_PROGRAMS: &str = include_str!("../data/chip8-database/programs.json");
+const BUNDLED_HASHES: &str = include_str!("../data/chip8-database/sha1-hashes.json");
+
+/// The known programs, by the hashes of their ROMs.
+#[derive(Default)]
+pub struct RomDatabase {
+    programs: Vec<Program>,
+    /// Index into `programs` by the SHA-1 hash of a ROM, in lower case hex.
+    hashes: HashMap<String, usize>,
+}
+
+impl RomDatabase {
+    /// The database as configured: the copy in the configured directory, the built-in
+    /// copy, or an empty database if it is disabled or can not be read.
+    pub fn load(settings: &Settings) -> RomDatabase {
+        if !settings.database.enabled {
+            return RomDatabase::default();
+        }
+        let database = match &settings.database.directory {
+            Some(directory) => RomDatabase::from_directory(directory),
+            None => RomDatabase::bundled(),
+        };
+        database.unwrap_or_else(|e| {
+            warn!("{}", e);
+            RomDatabase::default()
+        })
+    }
+
+    /// The copy of the database built into the interpreter.
+    pub fn bundled() -> Result<RomDatabase, String> {
+        RomDatabase::parse(BUNDLED_PROGRAMS, BUNDLED_HASHES)
+            .map_err(|e| format!("Could not parse built-in ROM database: {}", e))
+    }
+
+    /// Read `programs.json` and `sha1-hashes.json` of the chip-8-database from the directory.
+    pub fn from_directory(directory: &Path) -> Result<RomDatabase, String> {
+        let read = |name: &str| {
+            let path = directory.join(name);
+            fs::read_to_string(&path)
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+        };
+        RomDatabase::parse(&read("programs.json")?, &read("sha1-hashes.json")?).map_err(|e| {
+            format!(
+                "Could not parse ROM database in {}: {}",
+                directory.display(),
+                e
+            )
+        })
+    }
+
+    fn parse(programs: &str, hashes: &str) -> Result<RomDatabase, String> {
+        Ok(RomDatabase {
+            programs: serde_json::from_str(programs).map_err(|e| e.to_string())?,
+            hashes: serde_json::from_str(hashes).map_err(|e| e.to_string())?,
+        })
+    }
+
+    /// What the database knows about the program, if it is known.
+    pub fn lookup(&self, program: &[u8]) -> Option<RomInfo> {
+        let hash = format!("{:x}", Sha1::digest(program));
+        let entry = self.programs.get(*self.hashes.get(&hash)?)?;
+        let rom = entry.roms.get(&hash)?;
+        Some(RomInfo::new(entry, rom))
+    }
+}
+
+/// The platforms of the database whose quirks are known.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Platform {
+    OriginalChip8,
+    HybridVip,
+    ModernChip8,
+    Chip48,
+    Superchip1,
+    Superchip,
+    XoChip,
+}
+
+impl Platform {
+    fn from_id(id: &str) -> Option<Platform> {
+        match id {
+            "originalChip8" => Some(Platform::OriginalChip8),
+            "hybridVIP" => Some(Platform::HybridVip),
+            "modernChip8" => Some(Platform::ModernChip8),
+            "chip48" => Some(Platform::Chip48),
+            "superchip1" => Some(Platform::Superchip1),
+            "superchip" => Some(Platform::Superchip),
+            "xochip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    /// Whether programs for the platform only use the instructions of the CHIP-8,
+    /// which are all this interpreter implements.
+    pub fn is_chip8(self) -> bool {
+        matches!(
+            self,
+            Platform::OriginalChip8 | Platform::HybridVip | Platform::ModernChip8
+        )
+    }
+
+    /// The quirks of the platform.
+    pub fn quirks(self) -> Quirks {
+        let chip8 = Quirks {
+            shift: false,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: false,
+            wrap: false,
+            jump: false,
+            vblank: false,
+            logic: false,
+        };
+        match self {
+            Platform::OriginalChip8 | Platform::HybridVip => Quirks {
+                vblank: true,
+                logic: true,
+                ..chip8
+            },
+            Platform::ModernChip8 => chip8,
+            Platform::Chip48 | Platform::Superchip1 => Quirks {
+                shift: true,
+                memory_increment_by_x: true,
+                jump: true,
+                ..chip8
+            },
+            Platform::Superchip => Quirks {
+                shift: true,
+                memory_leave_i_unchanged: true,
+                jump: true,
+                ..chip8
+            },
+            Platform::XoChip => Quirks {
+                wrap: true,
+                ..chip8
+            },
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Platform::OriginalChip8 => "CHIP-8",
+            Platform::HybridVip => "CHIP-8 with machine code",
+            Platform::ModernChip8 => "modern CHIP-8",
+            Platform::Chip48 => "CHIP-48",
+            Platform::Superchip1 => "SUPER-CHIP 1.0",
+            Platform::Superchip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The CHIP-8 keys that a program uses for the directions and its two action
+/// buttons, as far as the database knows them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameKeys {
+    pub up: Option<u8>,
+    pub down: Option<u8>,
+    pub left: Option<u8>,
+    pub right: Option<u8>,
+    pub a: Option<u8>,
+    pub b: Option<u8>,
+}
+
+/// What the database knows about a program.
+#[derive(Clone, Debug)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    /// The platform the program runs on, preferring CHIP-8 if it runs on several.
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub instructions_per_frame: Option<u32>,
+    pub palette: Option<Palette>,
+    pub keys: GameKeys,
+}
+
+impl RomInfo {
+    fn new(program: &Program, rom: &Rom) -> RomInfo {
+        let platforms = rom
+            .platforms
+            .iter()
+            .filter_map(|id| Platform::from_id(id).map(|platform| (id, platform)))
+            .collect::<Vec<_>>();
+        let chosen = platforms
+            .iter()
+            .find(|(_, platform)| platform.is_chip8())
+            .or_else(|| platforms.first());
+        let quirks = chosen.map(|(id, platform)| {
+            let mut quirks = platform.quirks();
+            for (name, value) in rom.quirky_platforms.get(*id).into_iter().flatten() {
+                if !quirks.set(name, *value) {
+                    warn!("Unknown quirk in ROM database: {}", name);
+                }
+            }
+            quirks
+        });
+        let palette = rom
+            .colors
+            .as_ref()
+            .filter(|colors| colors.pixels.len() == 2 || colors.pixels.len() == 4)
+            .and_then(|colors| colors.pixels.join(",").parse().ok());
+        let key = |name: &str| rom.keys.get(name).copied().filter(|key| *key < 16);
+
+        RomInfo {
+            title: program.title.clone(),
+            authors: program.authors.clone(),
+            platform: chosen.map(|(_, platform)| *platform),
+            quirks,
+            instructions_per_frame: rom.tickrate.filter(|tickrate| *tickrate > 0),
+            palette,
+            keys: GameKeys {
+                up: key("up"),
+                down: key("down"),
+                left: key("left"),
+                right: key("right"),
+                a: key("a"),
+                b: key("b"),
+            },
+        }
+    }
+
+    /// Take the settings that are not set explicitly from the database: the quirks,
+    /// the instructions per frame and the colours.
+    pub fn apply(&self, settings: &mut Settings) {
+        info!("Found in ROM database: {}", self);
+        if let Some(platform) = self.platform.filter(|platform| !platform.is_chip8()) {
+            warn!(
+                "{} is made for {}, whose additional instructions this interpreter does not run",
+                self.title, platform
+            );
+        }
+        if settings.quirks.is_none() {
+            settings.quirks = self.quirks;
+        }
+        if settings.emulation.instructions_per_frame.is_none() {
+            settings.emulation.instructions_per_frame = self.instructions_per_frame;
+        }
+        if settings.display.palette.is_none() {
+            settings.display.palette = self.palette.clone();
+        }
+    }
+}
+
+impl fmt::Display for RomInfo {
+    /// The title and the authors of the program.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.title)?;
+        if !self.authors.is_empty() {
+            write!(f, " by {}", self.authors.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A program in `programs.json`, of which only the fields used here are read.
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    /// The known ROMs of the program, by their SHA-1 hash.
+    #[serde(default)]
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    /// Quirks that differ from those of the platform, by platform.
+    #[serde(default)]
+    quirky_platforms: HashMap<String, HashMap<String, bool>>,
+    tickrate: Option<u32>,
+    colors: Option<Colors>,
+    /// The CHIP-8 keys for `up`, `down`, `left`, `right`, `a` and `b`.
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    /// The colours of the pixels as hex colours, for the combinations of bitplanes.
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAMS: &str = r##"[{
+        "title": "Game",
+        "authors": ["Someone"],
+        "roms": {
+            "a9993e364706816aba3e25717850c26c9cd0d89d": {
+                "platforms": ["superchip", "originalChip8"],
+                "quirkyPlatforms": {"originalChip8": {"logic": false}},
+                "tickrate": 30,
+                "colors": {"pixels": ["#112233", "#DDEEFF"]},
+                "keys": {"up": 5, "a": 6, "b": 16}
+            }
+        }
+    }]"##;
+    /// The SHA-1 hash of the program `abc`.
+    const HASHES: &str = r#"{"a9993e364706816aba3e25717850c26c9cd0d89d": 0}"#;
+
+    fn game() -> RomInfo {
+        RomDatabase::parse(PROGRAMS, HASHES)
+            .unwrap()
+            .lookup(b"abc")
+            .unwrap()
+    }
+
+    #[test]
+    fn bundled_copy_parses() {
+        assert!(RomDatabase::bundled().is_ok());
+    }
+
+    #[test]
+    fn looks_up_roms_by_hash() {
+        let database = RomDatabase::parse(PROGRAMS, HASHES).unwrap();
+        assert!(database.lookup(b"abd").is_none());
+        let info = game();
+        assert_eq!(info.to_string(), "Game by Someone");
+        assert_eq!(info.platform, Some(Platform::OriginalChip8));
+        assert_eq!(info.instructions_per_frame, Some(30));
+        assert_eq!(info.keys.up, Some(5));
+        assert_eq!(info.keys.a, Some(6));
+        assert_eq!(info.keys.b, None);
+        let quirks = info.quirks.unwrap();
+        assert!(quirks.vblank);
+        assert!(!quirks.logic);
+    }
+
+    #[test]
+    fn fills_only_settings_that_are_not_set() {
+        let mut settings = Settings::default();
+        game().apply(&mut settings);
+        assert_eq!(settings.display.palette, game().palette);
+        assert_eq!(settings.emulation.instructions_per_frame, Some(30));
+
+        let mut settings = Settings::default();
+        let classic = Palette::default();
+        settings.display.palette = Some(classic.clone());
+        settings.emulation.instructions_per_frame = Some(7);
+        settings.quirks = Some(Quirks::default());
+        game().apply(&mut settings);
+        assert_eq!(settings.display.palette, Some(classic));
+        assert_eq!(settings.emulation.instructions_per_frame, Some(7));
+        assert_eq!(settings.quirks, Some(Quirks::default()));
+    }
+
+    #[test]
+    fn disabled_database_knows_no_roms() {
+        let mut settings = Settings::default();
+        settings.database.enabled = false;
+        assert!(RomDatabase::load(&settings).hashes.is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+use std::fmt;
+
+use log::{info, warn};
+
+use crate::buzzer::SoundChanges;
+use crate::config::{MIN_SPEED, VBLANK_MAX_SKIPPED_FRAMES};
+use crate::frontend::{Frontend, FRAME_MICROS};
+use crate::interpreter::{DirtyRect, Fault, Framebuffer, Interpreter, StackFaultAction};
+use crate::keymap::Action;
+use crate::settings::Settings;
+
+/// Whether the interpreter runs on its own.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum RunState {
+    Running,
+    Paused,
+    /// Instructions are only executed one at a time on request.
+    Stepping,
+}
+
+impl fmt::Display for RunState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunState::Running => write!(f, "Running"),
+            RunState::Paused => write!(f, "Paused"),
+            RunState::Stepping => write!(f, "Step mode"),
+        }
+    }
+}
+
+/// Run the interpreter through the frontend until it requests to exit. The
+/// interpreter keeps pace with the wall time of the frontend, and its timers tick
+/// once per emulated frame of the display.
+pub fn run<F: Frontend>(
+    interpreter: &mut Interpreter,
+    frontend: &mut F,
+    settings: &Settings,
+    step_mode: bool,
+) -> Result<(), String> {
+    let mut emulator = Emulator::new(interpreter, step_mode);
+    while emulator.frame(interpreter, frontend, settings)? {
+        frontend.wait();
+    }
+    Ok(())
+}
+
+/// Schedules the instructions and timers of the interpreter frame by frame, for
+/// frontends that drive the interpreter themselves instead of through `run`.
+pub struct Emulator {
+    run_state: RunState,
+    fast_forward: bool,
+    interpreter_time: u128,
+    target_time: u128,
+    next_tick_time: u128,
+    /// Instructions executed since the timers last ticked.
+    frame_instructions: u64,
+    last_wall_time: u128,
+    sound_changes: SoundChanges,
+    shown_framebuffer: Framebuffer,
+    /// The area of the display that changed since it was last shown.
+    dirty: Option<DirtyRect>,
+    skipped_frames: u32,
+}
+
+impl Emulator {
+    /// The wall time of the frontend is counted from 0, when the interpreter starts.
+    pub fn new(interpreter: &Interpreter, step_mode: bool) -> Self {
+        Emulator {
+            run_state: if step_mode {
+                RunState::Stepping
+            } else {
+                RunState::Running
+            },
+            fast_forward: false,
+            interpreter_time: 0,
+            target_time: 0,
+            next_tick_time: FRAME_MICROS,
+            frame_instructions: 0,
+            last_wall_time: 0,
+            sound_changes: SoundChanges::new(),
+            shown_framebuffer: *interpreter.framebuffer(),
+            dirty: Some(DirtyRect::full()),
+            skipped_frames: 0,
+        }
+    }
+
+    /// Handle the input of the frontend, run the interpreter up to the wall time of
+    /// the frontend and present the result. Return value is false if the frontend
+    /// requested to exit.
+    pub fn frame<F: Frontend>(
+        &mut self,
+        interpreter: &mut Interpreter,
+        frontend: &mut F,
+        settings: &Settings,
+    ) -> Result<bool, String> {
+        let mut advance_frame = false;
+        for (action, pressed) in frontend.poll_input()? {
+            match (action, pressed) {
+                (Action::Exit, true) => return Ok(false),
+                (Action::Reset, true) => interpreter.reset(),
+                (Action::InstructionStep, true) if self.run_state == RunState::Stepping => {
+                    self.instruction_step(interpreter, settings)?;
+                }
+                (Action::Pause, true) => {
+                    self.run_state = match self.run_state {
+                        RunState::Running => RunState::Paused,
+                        _ => RunState::Running,
+                    };
+                    log_run_state(self.run_state, interpreter);
+                }
+                (Action::ToggleStepMode, true) => {
+                    self.run_state = match self.run_state {
+                        RunState::Stepping => RunState::Running,
+                        _ => RunState::Stepping,
+                    };
+                    log_run_state(self.run_state, interpreter);
+                }
+                (Action::FrameAdvance, true) => advance_frame = true,
+                (Action::FastForward, pressed) => self.fast_forward = pressed,
+                (Action::Keypad(key), true) => interpreter.key_pressed(key),
+                (Action::Keypad(key), false) => interpreter.key_released(key),
+                _ => {}
+            }
+        }
+
+        let frame_start_time = self.interpreter_time;
+        let sound_on_at_start = interpreter.sound_on();
+        let wall_time = frontend.wall_time();
+        let elapsed = wall_time.saturating_sub(self.last_wall_time);
+        self.last_wall_time = wall_time;
+        match self.run_state {
+            RunState::Running if self.fast_forward => {
+                self.target_time +=
+                    (elapsed as f32 * settings.emulation.fast_forward.max(0.0)) as u128
+            }
+            RunState::Running => self.target_time += elapsed,
+            RunState::Paused | RunState::Stepping if advance_frame => {
+                self.target_time = self.next_tick_time.max(self.interpreter_time)
+            }
+            RunState::Paused | RunState::Stepping => self.target_time = self.interpreter_time,
+        }
+
+        // Timers tick once per emulated frame, so that they keep pace with the
+        // instructions while fast-forwarding.
+        let mut frames_run = 0;
+        'frames: while self.next_tick_time <= self.target_time {
+            while self.interpreter_time < self.next_tick_time {
+                if !self.instruction_step(interpreter, settings)? {
+                    break 'frames;
+                }
+            }
+            interpreter.frequency_step();
+            self.sound_changes
+                .update(self.interpreter_time, interpreter.sound_on());
+            self.next_tick_time += FRAME_MICROS;
+            self.frame_instructions = 0;
+            frames_run += 1;
+        }
+
+        if let Some(rect) = interpreter.take_dirty() {
+            self.dirty = Some(self.dirty.map_or(rect, |dirty| dirty.union(rect)));
+        }
+        let mut shown_dirty = None;
+        if !settings.display.draw_on_vblank
+            || interpreter.display_settled()
+            || self.skipped_frames >= VBLANK_MAX_SKIPPED_FRAMES
+        {
+            if self.dirty.is_some() {
+                self.shown_framebuffer = *interpreter.framebuffer();
+                shown_dirty = self.dirty.take();
+            }
+            self.skipped_frames = 0;
+        } else {
+            self.skipped_frames += 1;
+        }
+
+        let frame_sound_changes = self
+            .sound_changes
+            .take_frame(frame_start_time, self.interpreter_time);
+        if self.run_state == RunState::Running || frames_run > 0 {
+            let mut changes = vec![(0.0, sound_on_at_start)];
+            changes.extend_from_slice(&frame_sound_changes);
+            frontend.set_buzzer(&changes);
+        } else {
+            // The buzzer is silent while the interpreter is halted.
+            frontend.set_buzzer(&[(0.0, false)]);
+        }
+
+        frontend.present(&self.shown_framebuffer, shown_dirty, frames_run > 0)?;
+        Ok(true)
+    }
+
+    /// Execute one instruction. The time it takes is divided by the speed, so that
+    /// more instructions fit into a frame at a higher speed. Return value is false if
+    /// the interpreter broke into step mode on a fault of the stack, which fails
+    /// unless the stack is set to break. Invalid instructions always fail.
+    fn instruction_step(
+        &mut self,
+        interpreter: &mut Interpreter,
+        settings: &Settings,
+    ) -> Result<bool, String> {
+        let step = interpreter.instruction_step();
+        if let Some(fault) = step.fault() {
+            let pc = interpreter.program_counter();
+            let (message, break_on_fault) = match fault {
+                Fault::Stack(_) => (
+                    format!(
+                        "{} at {:#05X} with {} return addresses on the stack",
+                        fault,
+                        pc,
+                        interpreter.stack_depth()
+                    ),
+                    interpreter.stack_config().on_fault == StackFaultAction::Break,
+                ),
+                Fault::InvalidInstruction(_) => (format!("{} at {:#05X}", fault, pc), false),
+            };
+            if !break_on_fault {
+                return Err(message);
+            }
+            warn!("{}", message);
+            self.run_state = RunState::Stepping;
+            self.target_time = self.interpreter_time;
+            log_run_state(self.run_state, interpreter);
+            return Ok(false);
+        }
+        // The time is computed in 64 bits, as 128-bit arithmetic is slow enough to
+        // bound the speed of headless runs.
+        let speed = settings.emulation.speed.max(MIN_SPEED);
+        self.frame_instructions += 1;
+        match settings.emulation.instructions_per_frame {
+            Some(instructions) => {
+                // Every instruction ends at its share of the frame, so that exactly this
+                // many fit into it however small the share is.
+                let per_frame = ((instructions as f32 * speed).round() as u64).max(1);
+                let frame_start = (self.next_tick_time - FRAME_MICROS) as u64;
+                let end = frame_start + self.frame_instructions * FRAME_MICROS as u64 / per_frame;
+                self.interpreter_time = self.interpreter_time.max(end as u128);
+            }
+            None => {
+                let time_passed = step.time_passed() as u64;
+                self.interpreter_time += ((time_passed as f32 / speed) as u64).max(1) as u128;
+            }
+        }
+        self.sound_changes
+            .update(self.interpreter_time, interpreter.sound_on());
+        Ok(true)
+    }
+}
+
+/// Log the run state, with the stack depth while the interpreter is halted.
+fn log_run_state(run_state: RunState, interpreter: &Interpreter) {
+    match run_state {
+        RunState::Running => info!("{}", run_state),
+        _ => info!(
+            "{} at {:#05X}, stack depth {} (at most {} so far)",
+            run_state,
+            interpreter.program_counter(),
+            interpreter.stack_depth(),
+            interpreter.max_stack_depth()
+        ),
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+
+use crate::config::{
+    DECAY_MIN_INTENSITY, DEFAULT_BLEND_FRAMES, DEFAULT_DECAY, MAX_BLEND_FRAMES, SCREEN_X, SCREEN_Y,
+};
+use crate::interpreter::{DirtyRect, Framebuffer};
+
+/// The brightness of every pixel in row-major order, from 0 (off) to 1 (fully on).
+pub type Intensities = [[f32; SCREEN_X]; SCREEN_Y];
+
+/// A filter that softens the flicker caused by programs erasing and redrawing sprites.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum Filter {
+    /// Show the pixels as they are.
+    #[default]
+    None,
+    /// Pixels that turn off fade out like the phosphor of a CRT. The value is the
+    /// share of its brightness that a pixel keeps each frame.
+    Decay(f32),
+    /// Every pixel shows its average over the given number of most recent frames.
+    Blend(usize),
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    /// Parse a filter as `none`, `decay`, `blend`, or with a strength as e.g. `decay=0.8`
+    /// or `blend=4`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        let name = parts.next().unwrap_or_default().trim();
+        let strength = parts.next().map(str::trim);
+        let invalid_strength = || format!("Invalid strength for filter {}: {}", name, s);
+
+        match name {
+            "none" => Ok(Filter::None),
+            "decay" => {
+                let decay = match strength {
+                    Some(strength) => strength.parse().map_err(|_| invalid_strength())?,
+                    None => DEFAULT_DECAY,
+                };
+                if !(0.0..1.0).contains(&decay) {
+                    return Err(invalid_strength());
+                }
+                Ok(Filter::Decay(decay))
+            }
+            "blend" => {
+                let frames = match strength {
+                    Some(strength) => strength.parse().map_err(|_| invalid_strength())?,
+                    None => DEFAULT_BLEND_FRAMES,
+                };
+                if !(1..=MAX_BLEND_FRAMES).contains(&frames) {
+                    return Err(invalid_strength());
+                }
+                Ok(Filter::Blend(frames))
+            }
+            _ => Err(format!(
+                "Unknown filter: {} (available: none, decay, blend)",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Filter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::None => write!(f, "none"),
+            Filter::Decay(decay) => write!(f, "decay={}", decay),
+            Filter::Blend(frames) => write!(f, "blend={}", frames),
+        }
+    }
+}
+
+/// Applies a filter to consecutive frames of the display.
+pub struct DisplayFilter {
+    filter: Filter,
+    intensities: Intensities,
+    /// The recent frames of the blend filter, with the area that changed in each.
+    history: VecDeque<(Framebuffer, Option<DirtyRect>)>,
+    /// The area of the decay filter in which pixels are still fading out.
+    fading: Option<DirtyRect>,
+}
+
+impl DisplayFilter {
+    pub fn new(filter: Filter) -> Self {
+        DisplayFilter {
+            filter,
+            intensities: [[0.0; SCREEN_X]; SCREEN_Y],
+            history: VecDeque::new(),
+            fading: None,
+        }
+    }
+
+    /// Feed the next frame into the filter, and get the brightness every pixel
+    /// should be shown with. `dirty` is the area of the frame that changed since the
+    /// previous one, and the returned area is where the brightness changed.
+    pub fn apply(
+        &mut self,
+        framebuffer: &Framebuffer,
+        dirty: Option<DirtyRect>,
+    ) -> (&Intensities, Option<DirtyRect>) {
+        let changed = match self.filter {
+            Filter::None => {
+                for (row, pixels) in self.intensities.iter_mut().zip(framebuffer.pixel_rows()) {
+                    for (intensity, on) in row.iter_mut().zip(pixels) {
+                        *intensity = if on { 1.0 } else { 0.0 };
+                    }
+                }
+                dirty
+            }
+            Filter::Decay(decay) => {
+                let mut fading = false;
+                for (row, pixels) in self.intensities.iter_mut().zip(framebuffer.pixel_rows()) {
+                    for (intensity, on) in row.iter_mut().zip(pixels) {
+                        *intensity = if on { 1.0 } else { *intensity * decay };
+                        if *intensity < DECAY_MIN_INTENSITY {
+                            *intensity = 0.0;
+                        }
+                        fading |= *intensity > 0.0 && *intensity < 1.0;
+                    }
+                }
+                let changed = union(dirty, self.fading);
+                self.fading = changed.filter(|_| fading);
+                changed
+            }
+            Filter::Blend(frames) => {
+                // The first frame changes every pixel from the empty display.
+                let dirty = if self.history.is_empty() {
+                    Some(DirtyRect::full())
+                } else {
+                    dirty
+                };
+                self.history.push_back((*framebuffer, dirty));
+                while self.history.len() > frames {
+                    self.history.pop_front();
+                }
+                let share = 1.0 / self.history.len() as f32;
+                self.intensities = [[0.0; SCREEN_X]; SCREEN_Y];
+                for (frame, _) in &self.history {
+                    for (row, pixels) in self.intensities.iter_mut().zip(frame.pixel_rows()) {
+                        for (intensity, on) in row.iter_mut().zip(pixels) {
+                            if on {
+                                *intensity += share;
+                            }
+                        }
+                    }
+                }
+                // A pixel's average only changes while it differs between the frames.
+                self.history
+                    .iter()
+                    .fold(None, |changed, (_, dirty)| union(changed, *dirty))
+            }
+        };
+        (&self.intensities, changed)
+    }
+}
+
+fn union(a: Option<DirtyRect>, b: Option<DirtyRect>) -> Option<DirtyRect> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.union(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A display with only the top left pixel on, or none.
+    fn frame(on: bool) -> Framebuffer {
+        let mut rows = [0; SCREEN_Y];
+        if on {
+            rows[0] = 1 << (SCREEN_X - 1);
+        }
+        Framebuffer::from_rows(rows)
+    }
+
+    const CORNER: DirtyRect = DirtyRect {
+        x: 0,
+        y: 0,
+        width: 1,
+        height: 1,
+    };
+
+    #[test]
+    fn parses_filters() {
+        assert_eq!("none".parse(), Ok(Filter::None));
+        assert_eq!("decay".parse(), Ok(Filter::Decay(DEFAULT_DECAY)));
+        assert_eq!("decay = 0.8".parse(), Ok(Filter::Decay(0.8)));
+        assert_eq!("blend".parse(), Ok(Filter::Blend(DEFAULT_BLEND_FRAMES)));
+        assert_eq!("blend=4".parse(), Ok(Filter::Blend(4)));
+        assert_eq!(
+            Filter::Decay(0.8).to_string().parse(),
+            Ok(Filter::Decay(0.8))
+        );
+        for invalid in &["decay=1", "decay=-0.1", "decay=x", "blend=0", "blend=9"] {
+            let error = invalid.parse::<Filter>().unwrap_err();
+            assert!(error.starts_with("Invalid strength"), "{}", error);
+        }
+        assert!("blur".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn decay_filter_fades_pixels_that_turn_off() {
+        let mut filter = DisplayFilter::new(Filter::Decay(0.5));
+        assert_eq!(filter.apply(&frame(true), Some(CORNER)).0[0][0], 1.0);
+        assert_eq!(filter.apply(&frame(false), Some(CORNER)).0[0][0], 0.5);
+        assert_eq!(filter.apply(&frame(false), None).0[0][0], 0.25);
+        // Pixels that turn on again are at full brightness at once.
+        assert_eq!(filter.apply(&frame(true), Some(CORNER)).0[0][0], 1.0);
+        assert_eq!(filter.apply(&frame(true), None).0[0][1], 0.0);
+    }
+
+    #[test]
+    fn blend_filter_averages_the_recent_frames() {
+        let mut filter = DisplayFilter::new(Filter::Blend(4));
+        // The average is over the frames so far until there are enough of them.
+        assert_eq!(filter.apply(&frame(true), None).0[0][0], 1.0);
+        assert_eq!(filter.apply(&frame(false), Some(CORNER)).0[0][0], 0.5);
+        filter.apply(&frame(false), None);
+        assert_eq!(filter.apply(&frame(true), Some(CORNER)).0[0][0], 0.5);
+        assert_eq!(filter.apply(&frame(true), None).0[0][0], 0.5);
+        assert_eq!(filter.apply(&frame(true), None).0[0][0], 0.75);
+    }
+
+    #[test]
+    fn none_filter_changes_the_dirty_area() {
+        let mut filter = DisplayFilter::new(Filter::None);
+        assert_eq!(filter.apply(&frame(true), Some(CORNER)).1, Some(CORNER));
+        assert_eq!(filter.apply(&frame(true), None).1, None);
+    }
+
+    #[test]
+    fn decay_filter_changes_the_area_until_pixels_have_faded() {
+        let mut filter = DisplayFilter::new(Filter::Decay(0.5));
+        filter.apply(&frame(true), Some(CORNER));
+        assert_eq!(filter.apply(&frame(false), Some(CORNER)).1, Some(CORNER));
+        let mut frames = 1;
+        while filter.apply(&frame(false), None).1.is_some() {
+            frames += 1;
+        }
+        // The pixel turns off when its brightness would fall below 1/256, at 0.5^9.
+        assert_eq!(frames, 9);
+        assert_eq!(filter.apply(&frame(false), None).0[0][0], 0.0);
+    }
+
+    #[test]
+    fn blend_filter_changes_the_area_while_it_is_in_the_history() {
+        let mut filter = DisplayFilter::new(Filter::Blend(2));
+        assert_eq!(filter.apply(&frame(false), None).1, Some(DirtyRect::full()));
+        filter.apply(&frame(false), None);
+        assert_eq!(filter.apply(&frame(true), Some(CORNER)).1, Some(CORNER));
+        assert_eq!(filter.apply(&frame(true), None).1, Some(CORNER));
+        assert_eq!(filter.apply(&frame(true), None).1, None);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::config::DEFAULT_REFRESH_RATE;
+use crate::interpreter::{DirtyRect, Framebuffer};
+use crate::keymap::Action;
+
+/// Length of a frame of the display in microseconds.
+pub const FRAME_MICROS: u128 = 1_000_000 / DEFAULT_REFRESH_RATE as u128;
+
+/// The video, audio and input of the interpreter, as driven by `emulator::run`.
+/// Actions that only concern the frontend itself, like taking screenshots, are
+/// handled by the frontend and not reported by `poll_input`.
+pub trait Frontend {
+    /// Show the display. `dirty` is the area that changed since the display was last
+    /// shown, if any, and `advanced` is whether the interpreter ran since the last
+    /// frame, rather than being paused.
+    fn present(
+        &mut self,
+        framebuffer: &Framebuffer,
+        dirty: Option<DirtyRect>,
+        advanced: bool,
+    ) -> Result<(), String>;
+
+    /// Switch the buzzer on and off during the frame that is presented next. Every
+    /// change is the position in the frame, from 0 to 1, and whether the buzzer is on
+    /// from then on.
+    fn set_buzzer(&mut self, changes: &[(f32, bool)]);
+
+    /// The actions triggered since the last call, and whether they were pressed or released.
+    fn poll_input(&mut self) -> Result<Vec<(Action, bool)>, String>;
+
+    /// The time in microseconds since the frontend started.
+    fn wall_time(&self) -> u128;
+
+    /// Wait until the next frame is due.
+    fn wait(&mut self) {}
+}
+
+/// A frontend that shows and plays nothing, and runs the interpreter as fast as
+/// possible for the given number of frames. Frames that the interpreter does not run
+/// in count as well, so that the run ends if it breaks into step mode. Input can be
+/// queued to drive the emulator in tests.
+pub struct NullFrontend {
+    frames: u64,
+    frames_run: u64,
+    frames_presented: u64,
+    input: Vec<(Action, bool)>,
+}
+
+impl NullFrontend {
+    pub fn new(frames: u64) -> Self {
+        NullFrontend {
+            frames,
+            frames_run: 0,
+            frames_presented: 0,
+            input: Vec::new(),
+        }
+    }
+
+    /// Report the action on the next call of `poll_input`, as if its key had been
+    /// pressed or released.
+    pub fn queue_input(&mut self, action: Action, pressed: bool) {
+        self.input.push((action, pressed));
+    }
+
+    /// The number of frames the interpreter ran.
+    pub fn frames_run(&self) -> u64 {
+        self.frames_run
+    }
+}
+
+impl Frontend for NullFrontend {
+    fn present(
+        &mut self,
+        _framebuffer: &Framebuffer,
+        _dirty: Option<DirtyRect>,
+        advanced: bool,
+    ) -> Result<(), String> {
+        self.frames_presented += 1;
+        if advanced {
+            self.frames_run += 1;
+        }
+        Ok(())
+    }
+
+    fn set_buzzer(&mut self, _changes: &[(f32, bool)]) {}
+
+    fn poll_input(&mut self) -> Result<Vec<(Action, bool)>, String> {
+        if self.frames_presented >= self.frames {
+            Ok(vec![(Action::Exit, true)])
+        } else {
+            Ok(std::mem::take(&mut self.input))
+        }
+    }
+
+    /// Every frame takes exactly one refresh of the display, so that the interpreter
+    /// runs one frame each time it is presented.
+    fn wall_time(&self) -> u128 {
+        (self.frames_presented as u128 + 1) * FRAME_MICROS
+    }
+}
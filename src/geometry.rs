@@ -0,0 +1,38 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Position and size of the window, remembered between runs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+}
+
+impl WindowGeometry {
+    /// The geometry that the window had when it was last closed, if any.
+    pub fn load() -> Option<WindowGeometry> {
+        let contents = fs::read_to_string(geometry_file()?).ok()?;
+        toml::from_str(&contents).ok()
+    }
+
+    /// Remember this geometry for the next run.
+    pub fn save(&self) -> Result<(), String> {
+        let path = geometry_file().ok_or("Could not find configuration directory")?;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| {
+                format!("Could not create directory {}: {}", directory.display(), e)
+            })?;
+        }
+        let contents = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(&path, contents).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
+
+fn geometry_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("chip8").join("window.toml"))
+}
@@ -0,0 +1,99 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use log::info;
+
+use crate::buzzer::Buzzer;
+use crate::config::{AUDIO_SAMPLE_RATE, DEFAULT_REFRESH_RATE};
+use crate::emulator;
+use crate::frontend::{Frontend, NullFrontend};
+use crate::interpreter::{DirtyRect, Framebuffer, Interpreter};
+use crate::keymap::Action;
+use crate::settings::Settings;
+
+/// Run the interpreter without a window for the given number of frames, as fast as
+/// possible. If a WAV file is given, the sound of the buzzer is written into it.
+pub fn run(
+    interpreter: &mut Interpreter,
+    settings: &Settings,
+    frames: u64,
+    wav_file: Option<&Path>,
+) -> Result<(), String> {
+    let frames_run = match wav_file {
+        Some(path) => {
+            let mut frontend = WavFrontend {
+                inner: NullFrontend::new(frames),
+                writer: create_wav(path)?,
+                buzzer: Buzzer::new(AUDIO_SAMPLE_RATE, settings.audio.tone()),
+                samples: vec![0.0; (AUDIO_SAMPLE_RATE as u32 / DEFAULT_REFRESH_RATE) as usize],
+                changes: Vec::new(),
+            };
+            emulator::run(interpreter, &mut frontend, settings, false)?;
+            frontend
+                .writer
+                .finalize()
+                .map_err(|e| format!("Could not write WAV file: {}", e))?;
+            frontend.inner.frames_run()
+        }
+        None => {
+            let mut frontend = NullFrontend::new(frames);
+            emulator::run(interpreter, &mut frontend, settings, false)?;
+            frontend.frames_run()
+        }
+    };
+    info!("Ran {} frames", frames_run);
+    Ok(())
+}
+
+/// Writes the sound of the buzzer into a WAV file, for every frame that the
+/// interpreter ran.
+struct WavFrontend {
+    inner: NullFrontend,
+    writer: hound::WavWriter<BufWriter<File>>,
+    buzzer: Buzzer,
+    samples: Vec<f32>,
+    changes: Vec<(f32, bool)>,
+}
+
+impl Frontend for WavFrontend {
+    fn present(
+        &mut self,
+        framebuffer: &Framebuffer,
+        dirty: Option<DirtyRect>,
+        advanced: bool,
+    ) -> Result<(), String> {
+        if advanced {
+            self.buzzer.render(&mut self.samples, &self.changes);
+            for sample in &self.samples {
+                self.writer
+                    .write_sample((sample * i16::MAX as f32) as i16)
+                    .map_err(|e| format!("Could not write WAV file: {}", e))?;
+            }
+        }
+        self.inner.present(framebuffer, dirty, advanced)
+    }
+
+    fn set_buzzer(&mut self, changes: &[(f32, bool)]) {
+        self.changes = changes.to_vec();
+    }
+
+    fn poll_input(&mut self) -> Result<Vec<(Action, bool)>, String> {
+        self.inner.poll_input()
+    }
+
+    fn wall_time(&self) -> u128 {
+        self.inner.wall_time()
+    }
+}
+
+fn create_wav(path: &Path) -> Result<hound::WavWriter<BufWriter<File>>, String> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: AUDIO_SAMPLE_RATE as u32,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    hound::WavWriter::create(path, spec)
+        .map_err(|e| format!("Could not create WAV file {}: {}", path.display(), e))
+}
@@ -1,47 +1,71 @@
 extern crate rand;
 
-use log::{debug, trace};
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
+#[cfg(feature = "observer")]
+use std::sync::Mutex;
 
-use rand::Rng;
+#[cfg(feature = "observer")]
+use log::{log_enabled, Level};
 
-use std::fs::File;
-use std::io::Read;
+use rand::Rng;
 
 use display::Display;
 use keyboard::Keyboard;
 use memory::Memory;
 use stack::Stack;
 
-use crate::config::{SCREEN_X, SCREEN_Y};
 use crate::instruction::Instruction;
 use crate::interpreter::keyboard::NUMBER_OF_KEYS;
-use crate::interpreter::register::{Register, Register16Bit, Register8Bit};
 
+pub use cpu::Cpu;
+pub use font::{Font, FontSet};
+pub use framebuffer::{DirtyRect, Framebuffer};
+pub use memory_map::MemoryMap;
+#[cfg(feature = "observer")]
+pub use observer::{LogObserver, Observer};
+pub use quirks::Quirks;
+pub use stack::{StackConfig, StackFault, StackFaultAction};
+
+mod cpu;
 mod display;
+mod font;
+mod framebuffer;
 mod keyboard;
 mod memory;
-mod register;
+mod memory_map;
+#[cfg(feature = "observer")]
+mod observer;
+mod quirks;
 mod stack;
+mod state;
 
-/// Holds the full state of an interpreter.
+/// Holds the full state of an interpreter. Clones are cheap, e.g. for snapshots to
+/// rewind to; they share the loaded program, the font and the observer.
+#[derive(Clone)]
 pub struct Interpreter {
+    cpu: Cpu,
     memory: Memory,
     stack: Stack,
     display: Display,
     keyboard: Keyboard,
-    v_registers: [Register8Bit; 16],
-    i_register: Register16Bit,
-    delay_register: Register8Bit,
-    sound_register: Register8Bit,
-    program_counter: Register16Bit,
     waiting_for_key_press: bool,
     key_press_result_register: u8,
-    loaded_program: Vec<u8>,
+    /// Whether a sprite was drawn that the program waits for the next frame after.
+    waiting_for_vblank: bool,
+    loaded_program: Arc<[u8]>,
+    quirks: Quirks,
+    memory_map: MemoryMap,
+    font: Font,
+    #[cfg(feature = "observer")]
+    observer: Option<Arc<Mutex<dyn Observer>>>,
 }
 
 /// The result of a single command execution.
 pub struct StepResult {
     time_passed: u128,
+    fault: Option<Fault>,
 }
 
 impl StepResult {
@@ -49,45 +73,139 @@ impl StepResult {
     pub fn time_passed(&self) -> u128 {
         self.time_passed
     }
+
+    /// The fault that kept the instruction from being executed, if any. The program
+    /// counter is left at the instruction.
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why an instruction could not be executed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    Stack(StackFault),
+    /// An opcode that is not a CHIP-8 instruction.
+    InvalidInstruction(u16),
+}
+
+impl From<StackFault> for Fault {
+    fn from(fault: StackFault) -> Self {
+        Fault::Stack(fault)
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::Stack(fault) => write!(f, "{}", fault),
+            Fault::InvalidInstruction(opcode) => write!(f, "Invalid instruction {:04X}", opcode),
+        }
+    }
 }
 
 impl Interpreter {
     /// Create a new interpreter with all memory locations and registers in
     /// their default state.
     pub fn new() -> Self {
+        let memory_map = MemoryMap::default();
+        let font = Font::default();
         Interpreter {
-            memory: Memory::new(),
-            stack: Stack::new(),
+            cpu: Cpu::default(),
+            memory: Memory::new(memory_map.size, memory_map.font_address, font.clone()),
+            stack: Stack::new(StackConfig::default()),
             display: Display::new(),
             keyboard: Keyboard::new(),
-            v_registers: [
-                Register8Bit::new("V0"),
-                Register8Bit::new("V1"),
-                Register8Bit::new("V2"),
-                Register8Bit::new("V3"),
-                Register8Bit::new("V4"),
-                Register8Bit::new("V5"),
-                Register8Bit::new("V6"),
-                Register8Bit::new("V7"),
-                Register8Bit::new("V8"),
-                Register8Bit::new("V9"),
-                Register8Bit::new("VA"),
-                Register8Bit::new("VB"),
-                Register8Bit::new("VC"),
-                Register8Bit::new("VD"),
-                Register8Bit::new("VE"),
-                Register8Bit::new("VF"),
-            ],
-            i_register: Register16Bit::new("I"),
-            delay_register: Register8Bit::new("Delay"),
-            sound_register: Register8Bit::new("Sound"),
-            program_counter: Register16Bit::new("PC"),
             waiting_for_key_press: false,
             key_press_result_register: 0,
-            loaded_program: Vec::new(),
+            waiting_for_vblank: false,
+            loaded_program: Arc::new([]),
+            quirks: Quirks::default(),
+            memory_map,
+            font,
+            #[cfg(feature = "observer")]
+            observer: if log_enabled!(Level::Debug) {
+                Some(Arc::new(Mutex::new(LogObserver)))
+            } else {
+                None
+            },
         }
     }
 
+    /// Set the behaviours of the platform that the program was written for.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Set the depth of the call stack and what happens when a program exceeds it.
+    /// This empties the stack, so it is meant to be done before the program runs.
+    pub fn set_stack_config(&mut self, config: StackConfig) {
+        self.stack = Stack::new(config);
+    }
+
+    pub fn stack_config(&self) -> StackConfig {
+        self.stack.config()
+    }
+
+    /// The number of return addresses on the call stack.
+    pub fn stack_depth(&self) -> usize {
+        self.stack.depth()
+    }
+
+    /// The largest number of return addresses on the call stack since the last reset.
+    pub fn max_stack_depth(&self) -> usize {
+        self.stack.max_depth()
+    }
+
+    /// The address of the next instruction.
+    pub fn program_counter(&self) -> u16 {
+        self.cpu.pc
+    }
+
+    /// The registers and timers.
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Set what watches the executed instructions, or nothing to run without
+    /// instrumentation.
+    #[cfg(feature = "observer")]
+    pub fn set_observer(&mut self, observer: Option<Arc<Mutex<dyn Observer>>>) {
+        self.observer = observer;
+    }
+
+    /// Set where the program and the font are placed in memory, and how large it is.
+    /// This resets the interpreter, so it is meant to be done before the program runs.
+    /// Fails if the font or the loaded program do not fit into the memory.
+    pub fn set_memory_map(&mut self, memory_map: MemoryMap) -> Result<(), String> {
+        self.replace_memory(memory_map, self.font.clone())
+    }
+
+    pub fn memory_map(&self) -> MemoryMap {
+        self.memory_map
+    }
+
+    /// Set the font that `FX29` points I to. This resets the interpreter, so it is
+    /// meant to be done before the program runs. Fails if the font does not fit into
+    /// the memory.
+    pub fn set_font(&mut self, font: Font) -> Result<(), String> {
+        self.replace_memory(self.memory_map, font)
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
     /// Indicate to the interpreter that a key has been pressed.
     pub fn key_pressed(&mut self, key: u8) {
         self.keyboard.key_pressed(key);
@@ -99,21 +217,86 @@ impl Interpreter {
     }
 
     /// Get the current state of all pixels of the screen.
-    pub fn pixel_states(&self) -> [[bool; SCREEN_Y]; SCREEN_X] {
-        self.display.state()
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.display.framebuffer()
+    }
+
+    /// The area of the screen that has changed since the last call, if any.
+    pub fn take_dirty(&mut self) -> Option<DirtyRect> {
+        self.display.take_dirty()
+    }
+
+    /// Whether the display is in a consistent state. Programs move sprites by drawing
+    /// them again to erase them, then drawing them at the new location, so the display
+    /// is only settled if the last sprite drawn did not erase any pixels.
+    pub fn display_settled(&self) -> bool {
+        !self.display.last_draw_erased()
     }
 
     /// Whether sound should  currently be on.
     pub fn sound_on(&self) -> bool {
-        self.sound_register.value() > 0
+        self.cpu.sound > 0
+    }
+
+    /// Load a program from its bytes into memory. Fails if it does not fit into memory.
+    pub fn load_program_bytes(&mut self, program: &[u8]) -> Result<(), String> {
+        self.write_program(program)?;
+        self.loaded_program = program.into();
+        Ok(())
+    }
+
+    /// Load a program from a reader, e.g. a file or standard input. Reading stops
+    /// after the largest program that fits into memory.
+    pub fn load_program<R: Read>(&mut self, reader: R) -> Result<(), String> {
+        let mut program = Vec::new();
+        let limit = self.memory_map.max_program_size() as u64 + 1;
+        reader
+            .take(limit)
+            .read_to_end(&mut program)
+            .map_err(|e| format!("Could not read program: {}", e))?;
+        self.load_program_bytes(&program)
+    }
+
+    /// Replace the loaded program with a changed version of it, e.g. after it was
+    /// rebuilt. With `keep_state`, the new program is written over the old one and the
+    /// registers, stack, display and the rest of memory are kept, as long as the
+    /// program counter and the return addresses on the stack lie within the new
+    /// program. Otherwise the interpreter is reset. Return value is whether the state
+    /// was kept.
+    pub fn reload_program(&mut self, program: &[u8], keep_state: bool) -> Result<bool, String> {
+        if !keep_state || !self.fits_running_state(program) {
+            self.load_program_bytes(program)?;
+            self.reset();
+            return Ok(false);
+        }
+
+        let program_counter = self.cpu.pc;
+        self.write_program(program)?;
+        self.cpu.pc = program_counter;
+        // What is left of a longer old program is cleared, as if the new one was loaded.
+        let leftover = self.loaded_program.len().saturating_sub(program.len());
+        self.memory
+            .bytes_write(
+                self.memory_map.program_start as usize + program.len(),
+                &vec![0; leftover],
+            )
+            .expect("The old program fit into memory");
+        self.loaded_program = program.into();
+        Ok(true)
     }
 
-    /// Load a program via given file into memory.
-    pub fn load_program_file(&mut self, file: &mut File) {
-        let mut data = Vec::new();
-        file.read_to_end(&mut data).expect("Could not read file");
-        self.loaded_program = data;
-        self.load_program(&self.loaded_program.clone());
+    /// Whether the program counter and the return addresses on the stack point into
+    /// the program, so that it can continue where the loaded program was.
+    fn fits_running_state(&self, program: &[u8]) -> bool {
+        let start = self.memory_map.program_start as usize;
+        let in_program =
+            |address: u16| (start..start + program.len()).contains(&(address as usize));
+        in_program(self.cpu.pc)
+            && self
+                .stack
+                .entries()
+                .iter()
+                .all(|address| in_program(*address))
     }
 
     /// Reset the interpreter to its original state, then load the program that was
@@ -121,219 +304,245 @@ impl Interpreter {
     pub fn reset(&mut self) {
         self.memory.reset();
         self.stack.reset();
-        self.v_registers.iter_mut().for_each(|r| r.reset());
-        self.i_register.reset();
-        self.delay_register.reset();
-        self.sound_register.reset();
-        self.program_counter.reset();
+        self.cpu = Cpu::default();
         self.display.reset();
         self.keyboard.reset();
         self.waiting_for_key_press = false;
         self.key_press_result_register = 0;
+        self.waiting_for_vblank = false;
 
-        self.load_program(&self.loaded_program.clone());
+        self.write_program(&self.loaded_program.clone())
+            .expect("The loaded program fits into memory");
     }
 
     /// This should be called with 60HZ frequency to set the sound and delay registers
     /// to their correct states.
     pub fn frequency_step(&mut self) {
-        if self.sound_register.value() > 0 {
-            self.sound_register.decrement_value_by(1);
-        }
-
-        if self.delay_register.value() > 0 {
-            self.delay_register.decrement_value_by(1);
-        }
+        self.waiting_for_vblank = false;
+        self.cpu.sound = self.cpu.sound.saturating_sub(1);
+        self.cpu.delay = self.cpu.delay.saturating_sub(1);
     }
 
     /// Process the next instruction. The result details how long this clock cycle should take.
     pub fn instruction_step(&mut self) -> StepResult {
         if self.waiting_for_key_press {
-            debug!("Executing: Wait for key press");
             for i in 0..NUMBER_OF_KEYS {
                 if self.keyboard.key_state(i) {
-                    debug!(
-                        "Key {:X} is pressed! Writing value to V_{:X}",
-                        i, self.key_press_result_register
-                    );
-                    self.v_registers[self.key_press_result_register as usize].write_value(i as u8);
+                    self.cpu.v[self.key_press_result_register as usize] = i;
                     self.waiting_for_key_press = false;
                 }
             }
-            return StepResult { time_passed: 100 };
+            return StepResult {
+                time_passed: 100,
+                fault: None,
+            };
+        }
+        if self.waiting_for_vblank {
+            return StepResult {
+                time_passed: 100,
+                fault: None,
+            };
         }
 
-        let instruction =
-            Instruction::parse(self.memory.two_byte_read(self.program_counter.value()));
-        debug!(
-            "{:4X} - Executing: {}",
-            self.program_counter.value(),
-            instruction
-        );
-        self.program_counter.increment_value_by(2);
-        trace!("\t---");
+        let address = self.cpu.pc;
+        let instruction = Instruction::parse(self.memory.two_byte_read(address));
+        #[cfg(feature = "observer")]
+        let before = self.cpu;
+        self.cpu.pc = self.cpu.pc.wrapping_add(2);
+        let time_passed = match self.execute(instruction) {
+            Ok(time_passed) => time_passed,
+            Err(fault) => {
+                // The instruction is tried again when the program continues.
+                self.cpu.pc = address;
+                return StepResult {
+                    time_passed: 0,
+                    fault: Some(fault),
+                };
+            }
+        };
+        #[cfg(feature = "observer")]
+        if let Some(observer) = &self.observer {
+            observer
+                .lock()
+                .expect("The observer is not poisoned")
+                .instruction(address, instruction, &before, &self.cpu);
+        }
+        StepResult {
+            time_passed,
+            fault: None,
+        }
+    }
 
+    /// Execute an instruction, with the program counter already past it. Return
+    /// value is the time in microseconds that it takes on the COSMAC VIP, or the fault
+    /// that kept it from being executed.
+    fn execute(&mut self, instruction: Instruction) -> Result<u128, Fault> {
         let time_passed = match instruction {
             Instruction::ClearDisplay => {
                 self.display.reset();
                 109
             }
             Instruction::Return => {
-                self.program_counter.write_value(self.stack.pop());
+                self.cpu.pc = self.stack.pop()?;
                 105
             }
             Instruction::JumpToAddress(nnn) => {
-                self.program_counter.write_value(nnn);
+                self.cpu.pc = nnn;
                 105
             }
             Instruction::CallAddress(nnn) => {
-                self.stack.push(self.program_counter.value());
-                self.program_counter.write_value(nnn);
+                self.stack.push(self.cpu.pc)?;
+                self.cpu.pc = nnn;
                 105
             }
             Instruction::SkipIfVxEqualKk(x, kk) => {
-                if self.v_registers[x as usize].value() == kk {
-                    self.program_counter.increment_value_by(2);
+                if self.cpu.v[x as usize] == kk {
+                    self.cpu.pc = self.cpu.pc.wrapping_add(2);
                 }
                 55
             }
             Instruction::SkipIfVxNotEqualKk(x, kk) => {
-                if self.v_registers[x as usize].value() != kk {
-                    self.program_counter.increment_value_by(2);
+                if self.cpu.v[x as usize] != kk {
+                    self.cpu.pc = self.cpu.pc.wrapping_add(2);
                 }
                 55
             }
             Instruction::SkipIfVxEqualVy(x, y) => {
-                if self.v_registers[x as usize].value() == self.v_registers[y as usize].value() {
-                    self.program_counter.increment_value_by(2);
+                if self.cpu.v[x as usize] == self.cpu.v[y as usize] {
+                    self.cpu.pc = self.cpu.pc.wrapping_add(2);
                 }
                 73
             }
             Instruction::LoadVxKk(x, kk) => {
-                self.v_registers[x as usize].write_value(kk);
+                self.cpu.v[x as usize] = kk;
                 27
             }
             Instruction::AddVxKk(x, kk) => {
-                let x_value = self.v_registers[x as usize].value();
+                let x_value = self.cpu.v[x as usize];
                 let (new_value, _) = x_value.overflowing_add(kk);
-                self.v_registers[x as usize].write_value(new_value);
+                self.cpu.v[x as usize] = new_value;
                 45
             }
             Instruction::LoadVxVy(x, y) => {
-                self.v_registers[x as usize].write_value(self.v_registers[y as usize].value());
+                self.cpu.v[x as usize] = self.cpu.v[y as usize];
                 200
             }
             Instruction::OrVxVy(x, y) => {
-                let result =
-                    self.v_registers[x as usize].value() | self.v_registers[y as usize].value();
-                self.v_registers[x as usize].write_value(result);
+                let result = self.cpu.v[x as usize] | self.cpu.v[y as usize];
+                self.cpu.v[x as usize] = result;
+                self.logic_quirk();
                 200
             }
             Instruction::AndVxVy(x, y) => {
-                let result =
-                    self.v_registers[x as usize].value() & self.v_registers[y as usize].value();
-                self.v_registers[x as usize].write_value(result);
+                let result = self.cpu.v[x as usize] & self.cpu.v[y as usize];
+                self.cpu.v[x as usize] = result;
+                self.logic_quirk();
                 200
             }
             Instruction::XorVxVy(x, y) => {
-                let result =
-                    self.v_registers[x as usize].value() ^ self.v_registers[y as usize].value();
-                self.v_registers[x as usize].write_value(result);
+                let result = self.cpu.v[x as usize] ^ self.cpu.v[y as usize];
+                self.cpu.v[x as usize] = result;
+                self.logic_quirk();
                 200
             }
             Instruction::AddVxVy(x, y) => {
-                let (result, carry_bit) = self.v_registers[x as usize]
-                    .value()
-                    .overflowing_add(self.v_registers[y as usize].value());
-                self.v_registers[0xF].write_value(carry_bit as u8);
-                self.v_registers[x as usize].write_value(result);
+                let (result, carry_bit) =
+                    self.cpu.v[x as usize].overflowing_add(self.cpu.v[y as usize]);
+                self.cpu.v[0xF] = carry_bit as u8;
+                self.cpu.v[x as usize] = result;
                 200
             }
             Instruction::SubVxVy(x, y) => {
-                let x_value = self.v_registers[x as usize].value();
-                let y_value = self.v_registers[y as usize].value();
+                let x_value = self.cpu.v[x as usize];
+                let y_value = self.cpu.v[y as usize];
                 let (result, _) = x_value.overflowing_sub(y_value);
                 if x_value > y_value {
-                    self.v_registers[0xF].write_value(1);
+                    self.cpu.v[0xF] = 1;
                 } else {
-                    self.v_registers[0xF].write_value(0);
+                    self.cpu.v[0xF] = 0;
                 }
-                self.v_registers[x as usize].write_value(result);
+                self.cpu.v[x as usize] = result;
                 200
             }
-            Instruction::ShiftRight(x, _) => {
-                let value = self.v_registers[x as usize].value();
-                self.v_registers[0xF].write_value(value & 1);
-                self.v_registers[x as usize].write_value(value >> 1);
+            Instruction::ShiftRight(x, y) => {
+                let value = self.shift_source(x, y);
+                self.cpu.v[0xF] = value & 1;
+                self.cpu.v[x as usize] = value >> 1;
                 200
             }
             Instruction::SubNVxVy(x, y) => {
-                let value =
-                    self.v_registers[x as usize].value() - self.v_registers[y as usize].value();
+                let value = self.cpu.v[x as usize] - self.cpu.v[y as usize];
                 if value > 0 {
-                    self.v_registers[0xF].write_value(1);
+                    self.cpu.v[0xF] = 1;
                 } else {
-                    self.v_registers[0xF].write_value(0);
+                    self.cpu.v[0xF] = 0;
                 }
-                self.v_registers[x as usize].write_value(value);
+                self.cpu.v[x as usize] = value;
                 200
             }
-            Instruction::ShiftLeft(x, _) => {
-                let value = self.v_registers[x as usize].value();
-                self.v_registers[0xF].write_value(value >> 7);
-                self.v_registers[x as usize].write_value(value << 1);
+            Instruction::ShiftLeft(x, y) => {
+                let value = self.shift_source(x, y);
+                self.cpu.v[0xF] = value >> 7;
+                self.cpu.v[x as usize] = value << 1;
                 200
             }
             Instruction::SkipIfVxNotEqualVy(x, y) => {
-                if self.v_registers[x as usize].value() != self.v_registers[y as usize].value() {
-                    self.program_counter.increment_value_by(2);
+                if self.cpu.v[x as usize] != self.cpu.v[y as usize] {
+                    self.cpu.pc = self.cpu.pc.wrapping_add(2);
                 }
                 73
             }
             Instruction::LoadAddr(nnn) => {
-                self.i_register.write_value(nnn);
+                self.cpu.i = nnn;
                 55
             }
             Instruction::JumpToAddressPlusV0(nnn) => {
-                let value = nnn + self.v_registers[0].value() as u16;
-                self.program_counter.write_value(value);
+                let offset_register = if self.quirks.jump { nnn >> 8 } else { 0 };
+                let value = nnn + self.cpu.v[offset_register as usize] as u16;
+                self.cpu.pc = value;
                 105
             }
             Instruction::RandomAnd(x, kk) => {
                 let value: u8 = kk & rand::thread_rng().gen::<u8>();
-                self.v_registers[x as usize].write_value(value);
+                self.cpu.v[x as usize] = value;
                 164
             }
             Instruction::DrawVxVyN(x, y, n) => {
-                let mut sprite = Vec::with_capacity(n as usize);
-                let memory_start = self.i_register.value();
+                let mut sprite = [0; 15];
+                let memory_start = self.cpu.i;
                 for i in 0..n {
-                    sprite.push(self.memory.byte_read(memory_start + i as u16));
+                    sprite[i as usize] = self.memory.byte_read(memory_start.wrapping_add(i as u16));
                 }
 
-                let x_coord = self.v_registers[x as usize].value();
-                let y_coord = self.v_registers[y as usize].value();
-                let collision = self.display.draw_sprite(x_coord, y_coord, &sprite);
-                self.v_registers[0xF].write_value(collision as u8);
+                let x_coord = self.cpu.v[x as usize];
+                let y_coord = self.cpu.v[y as usize];
+                let collision = self.display.draw_sprite(
+                    x_coord,
+                    y_coord,
+                    &sprite[..n as usize],
+                    self.quirks.wrap,
+                );
+                self.cpu.v[0xF] = collision as u8;
+                self.waiting_for_vblank = self.quirks.vblank;
                 10000 + 1000 * n as u128
             }
             Instruction::SkipIfKeyPressed(x) => {
-                let key = self.v_registers[x as usize].value();
+                let key = self.cpu.v[x as usize];
                 if self.keyboard.key_state(key) {
-                    self.program_counter.increment_value_by(2);
+                    self.cpu.pc = self.cpu.pc.wrapping_add(2);
                 }
                 73
             }
             Instruction::SkipIfKeyNotPressed(x) => {
-                let key = self.v_registers[x as usize].value();
+                let key = self.cpu.v[x as usize];
                 if !self.keyboard.key_state(key) {
-                    self.program_counter.increment_value_by(2);
+                    self.cpu.pc = self.cpu.pc.wrapping_add(2);
                 }
                 73
             }
             Instruction::LoadDelayTimer(x) => {
-                let value = self.delay_register.value();
-                self.v_registers[x as usize].write_value(value);
+                let value = self.cpu.delay;
+                self.cpu.v[x as usize] = value;
                 45
             }
             Instruction::WaitForKey(x) => {
@@ -342,68 +551,119 @@ impl Interpreter {
                 0
             }
             Instruction::SetDelayTimer(x) => {
-                let value = self.v_registers[x as usize].value();
-                self.delay_register.write_value(value);
+                let value = self.cpu.v[x as usize];
+                self.cpu.delay = value;
                 45
             }
             Instruction::SetSoundTimer(x) => {
-                let value = self.v_registers[x as usize].value();
-                self.sound_register.write_value(value);
+                let value = self.cpu.v[x as usize];
+                self.cpu.sound = value;
                 45
             }
             Instruction::AddVxToI(x) => {
-                let (value, _) = (self.v_registers[x as usize].value() as u16)
-                    .overflowing_add(self.i_register.value());
-                self.i_register.write_value(value);
+                let (value, _) = (self.cpu.v[x as usize] as u16).overflowing_add(self.cpu.i);
+                self.cpu.i = value;
                 86
             }
             Instruction::LoadSpriteLocationToI(x) => {
-                let digit = self.v_registers[x as usize].value();
-                self.i_register.write_value((5 * digit) as u16);
+                // Only the low nibble selects the digit.
+                let digit = self.cpu.v[x as usize] & 0xF;
+                let location = self.memory_map.font_address + 5 * digit as u16;
+                self.cpu.i = location;
                 91
             }
             Instruction::LoadBcdToI(x) => {
-                let location = self.i_register.value();
-                let value = self.v_registers[x as usize].value();
+                let location = self.cpu.i;
+                let value = self.cpu.v[x as usize];
                 let hundreds = (value / 100) % 10;
                 let tens = (value / 10) % 10;
                 let ones = value % 10;
                 self.memory.byte_write(location, hundreds);
-                self.memory.byte_write(location + 1, tens);
-                self.memory.byte_write(location + 2, ones);
+                self.memory.byte_write(location.wrapping_add(1), tens);
+                self.memory.byte_write(location.wrapping_add(2), ones);
                 927
             }
             Instruction::LoadV0ThroughVxToI(x) => {
-                let mut location = self.i_register.value();
+                let mut location = self.cpu.i;
                 for i in 0..x + 1 {
-                    let value = self.v_registers[i as usize].value();
+                    let value = self.cpu.v[i as usize];
                     self.memory.byte_write(location, value);
-                    location = location + 1;
+                    location = location.wrapping_add(1);
                 }
+                self.memory_quirk(x);
                 605
             }
             Instruction::LoadIToV0ThroughVx(x) => {
-                let mut location = self.i_register.value();
+                let mut location = self.cpu.i;
                 for i in 0..x + 1 {
                     let value = self.memory.byte_read(location);
-                    self.v_registers[i as usize].write_value(value);
-                    location = location + 1;
+                    self.cpu.v[i as usize] = value;
+                    location = location.wrapping_add(1);
                 }
+                self.memory_quirk(x);
                 605
             }
-            Instruction::Invalid(a, b, c, d) => panic!(format!(
-                "Invalid instruction! Tried to execute: {:X}-{:X}-{:X}-{:X}",
-                a, b, c, d
-            )),
+            Instruction::Invalid(a, b, c, d) => {
+                let opcode = (a as u16) << 12 | (b as u16) << 8 | (c as u16) << 4 | d as u16;
+                return Err(Fault::InvalidInstruction(opcode));
+            }
         };
-        trace!("\t---");
-        trace!("---");
-        StepResult { time_passed }
+        Ok(time_passed)
+    }
+
+    /// The value that `8XY6` and `8XYE` shift into VX.
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        let source = if self.quirks.shift { x } else { y };
+        self.cpu.v[source as usize]
+    }
+
+    /// Reset VF after `8XY1`, `8XY2` and `8XY3` if the platform does.
+    fn logic_quirk(&mut self) {
+        if self.quirks.logic {
+            self.cpu.v[0xF] = 0;
+        }
+    }
+
+    /// Move I past the registers that `FX55` or `FX65` stored or loaded, as far as
+    /// the platform does.
+    fn memory_quirk(&mut self, x: u8) {
+        if self.quirks.memory_leave_i_unchanged {
+            return;
+        }
+        let increment = if self.quirks.memory_increment_by_x {
+            x as u16
+        } else {
+            x as u16 + 1
+        };
+        let value = self.cpu.i.wrapping_add(increment);
+        self.cpu.i = value;
+    }
+
+    /// Create the memory anew for the memory map and the font, and reset the
+    /// interpreter with the loaded program.
+    fn replace_memory(&mut self, memory_map: MemoryMap, font: Font) -> Result<(), String> {
+        memory_map.validate(font.bytes().len())?;
+        if self.loaded_program.len() > memory_map.max_program_size() {
+            return Err(format!(
+                "The program of {} bytes does not fit into memory at {:#X} (at most {} bytes)",
+                self.loaded_program.len(),
+                memory_map.program_start,
+                memory_map.max_program_size()
+            ));
+        }
+        self.memory = Memory::new(memory_map.size, memory_map.font_address, font.clone());
+        self.memory_map = memory_map;
+        self.font = font;
+        self.reset();
+        Ok(())
     }
 
-    /// Load a program given as byte-vector into memory.
-    fn load_program(&mut self, program: &Vec<u8>) {
-        self.memory.bytes_write(0x200, &program);
-        self.program_counter.write_value(0x200);
+    /// Write a program into memory and start it.
+    fn write_program(&mut self, program: &[u8]) -> Result<(), String> {
+        self.memory
+            .bytes_write(self.memory_map.program_start as usize, program)
+            .map_err(|e| format!("Could not load program: {}", e))?;
+        self.cpu.pc = self.memory_map.entry_point();
+        Ok(())
     }
 }
@@ -0,0 +1,16 @@
+/// The registers and timers of the interpreter, as plain values that are cheap to
+/// copy, e.g. to compare the state before and after an instruction.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cpu {
+    /// The registers V0 to VF.
+    pub v: [u8; 16],
+    /// The address register I.
+    pub i: u16,
+    /// The address of the next instruction.
+    pub pc: u16,
+    /// The delay timer, which counts down at 60 Hz.
+    pub delay: u8,
+    /// The sound timer, which counts down at 60 Hz and sounds the buzzer while it
+    /// is not 0.
+    pub sound: u8,
+}
@@ -1,57 +1,111 @@
 use crate::config::{SCREEN_X, SCREEN_Y};
-use log::trace;
+use crate::interpreter::framebuffer::{DirtyRect, Framebuffer, Row};
 
+#[derive(Clone)]
 pub struct Display {
-    pixel_states: [[bool; SCREEN_Y]; SCREEN_X],
+    framebuffer: Framebuffer,
+    dirty: Option<DirtyRect>,
+    last_draw_erased: bool,
 }
 
 impl Display {
     pub fn new() -> Self {
         Display {
-            pixel_states: [[false; SCREEN_Y]; SCREEN_X],
+            framebuffer: Framebuffer::new(),
+            dirty: None,
+            last_draw_erased: false,
         }
     }
 
     /// Draw the sprite starting on top left x and y coordinates. Return value is whether there was a collision.
-    pub fn draw_sprite(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
+    /// Pixels that fall outside the display are not drawn, unless `wrap` is set, in
+    /// which case the sprite wraps around to the opposite edges.
+    pub fn draw_sprite(&mut self, x: u8, y: u8, sprite: &[u8], wrap: bool) -> bool {
+        if wrap {
+            return self.draw_wrapping_sprite(x, y, sprite);
+        }
         let mut collision = false;
+        let (x, y) = (x as usize, y as usize);
 
-        for i in 0..sprite.len() as u8 {
-            trace!("\tSPRITE - {:8b}", sprite[i as usize]);
-            for j in 0..8 as u8 {
-                if sprite[i as usize] & (1 << (7 - j)) != 0 {
-                    collision = collision | self.toggle_pixel(j.checked_add(x), i.checked_add(y));
-                }
+        if x < SCREEN_X {
+            for (i, line) in sprite.iter().enumerate().take(SCREEN_Y.saturating_sub(y)) {
+                let bits = ((*line as Row) << (SCREEN_X - 8)) >> x;
+                collision |= self.framebuffer.xor_row(y + i, bits);
+            }
+            let width = (SCREEN_X - x).min(8);
+            let height = sprite.len().min(SCREEN_Y.saturating_sub(y));
+            if height > 0 {
+                self.mark_dirty(DirtyRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                });
             }
         }
 
+        self.last_draw_erased = collision;
         collision
     }
 
-    /// Toggle a pixel at x and y coordinates. Return value is whether there was a collision.
-    fn toggle_pixel(&mut self, x_option: Option<u8>, y_option: Option<u8>) -> bool {
-        if x_option.is_none() || y_option.is_none() {
-            return false;
+    fn draw_wrapping_sprite(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
+        let mut collision = false;
+        let (x, y) = (x as usize % SCREEN_X, y as usize % SCREEN_Y);
+        for (i, line) in sprite.iter().enumerate() {
+            let bits = ((*line as Row) << (SCREEN_X - 8)).rotate_right(x as u32);
+            collision |= self.framebuffer.xor_row((y + i) % SCREEN_Y, bits);
         }
-        let x = x_option.expect("Error getting x value");
-        let y = y_option.expect("Error getting y value");
-        if x as usize >= self.pixel_states.len()
-            || y as usize >= self.pixel_states[x as usize].len()
-        {
-            return false;
+        if !sprite.is_empty() {
+            let wraps = x + 8 > SCREEN_X || y + sprite.len() > SCREEN_Y;
+            self.mark_dirty(if wraps {
+                DirtyRect::full()
+            } else {
+                DirtyRect {
+                    x,
+                    y,
+                    width: 8,
+                    height: sprite.len(),
+                }
+            });
         }
-        let previous_value = self.pixel_states[x as usize][y as usize];
-        self.pixel_states[x as usize][y as usize] = !previous_value;
-        return previous_value;
+
+        self.last_draw_erased = collision;
+        collision
     }
 
     /// Get the current state of the display.
-    pub fn state(&self) -> [[bool; SCREEN_Y]; SCREEN_X] {
-        self.pixel_states
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// The area that has changed since the last call, if any.
+    pub fn take_dirty(&mut self) -> Option<DirtyRect> {
+        self.dirty.take()
+    }
+
+    /// Whether the last sprite that was drawn erased any pixels.
+    pub fn last_draw_erased(&self) -> bool {
+        self.last_draw_erased
+    }
+
+    /// Replace the pixels of the display.
+    pub fn restore(&mut self, framebuffer: Framebuffer) {
+        self.framebuffer = framebuffer;
+        self.mark_dirty(DirtyRect::full());
+        self.last_draw_erased = false;
     }
 
     /// Reset the display to its initial state.
     pub fn reset(&mut self) {
-        self.pixel_states = [[false; SCREEN_Y]; SCREEN_X];
+        self.framebuffer.clear();
+        self.mark_dirty(DirtyRect::full());
+        self.last_draw_erased = false;
+    }
+
+    fn mark_dirty(&mut self, rect: DirtyRect) {
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(rect),
+            None => rect,
+        });
     }
 }
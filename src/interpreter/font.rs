@@ -0,0 +1,237 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::de::{self, Deserialize, Deserializer};
+
+/// Number of bytes of the font of the hex digits, five for each digit.
+pub const SMALL_FONT_SIZE: usize = 16 * 5;
+/// Number of bytes of a big font of the ten decimal digits, as of the SUPER-CHIP.
+const BIG_DECIMAL_FONT_SIZE: usize = 10 * 10;
+/// Number of bytes of a big font of the hex digits, as of Octo.
+const BIG_HEX_FONT_SIZE: usize = 16 * 10;
+
+const CHIP48_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+const VIP_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x60, 0x20, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0xA0, 0xA0, 0xF0, 0x20, 0x20, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x10, 0x10, 0x10, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xF0, 0x50, 0x70, 0x50, 0xF0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xF0, 0x50, 0x50, 0x50, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+const DREAM6800_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x40, 0x40, 0x40, 0x40, 0x40, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0xE0,
+    0x20, 0xE0, 0x20, 0xE0, 0x80, 0xA0, 0xA0, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0, 0xE0, 0x80,
+    0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xA0, 0xE0, 0xE0, 0xA0, 0xE0,
+    0x20, 0xE0, 0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0xC0, 0xA0, 0xE0, 0xA0, 0xC0, 0xE0, 0x80, 0x80, 0x80,
+    0xE0, 0xC0, 0xA0, 0xA0, 0xA0, 0xC0, 0xE0, 0x80, 0xE0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80,
+];
+
+const ETI660_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0x20, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0xE0,
+    0x20, 0xE0, 0x20, 0xE0, 0xA0, 0xA0, 0xE0, 0x20, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0, 0xE0, 0x80,
+    0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xA0, 0xE0, 0xE0, 0xA0, 0xE0,
+    0x20, 0xE0, 0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0x80, 0x80, 0xE0, 0xA0, 0xE0, 0xE0, 0x80, 0x80, 0x80,
+    0xE0, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xE0, 0x80, 0xE0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80,
+];
+
+const OCTO_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0xA0, 0xA0, 0xF0, 0x20, 0x20, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x10, 0x10, 0x10, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xF0, 0x50, 0x70, 0x50, 0xF0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xF0, 0x50, 0x50, 0x50, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+const SCHIP_BIG_FONT: [u8; BIG_DECIMAL_FONT_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, 0x18, 0x38, 0x58, 0x18, 0x18, 0x18,
+    0x18, 0x18, 0x18, 0x3C, 0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, 0x3C, 0x7E,
+    0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, 0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF,
+    0x06, 0x06, 0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, 0x3E, 0x7C, 0xE0, 0xC0,
+    0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, 0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, 0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F,
+    0x03, 0x03, 0x3E, 0x7C,
+];
+
+const OCTO_BIG_FONT: [u8; BIG_HEX_FONT_SIZE] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x18, 0x78, 0x78, 0x18, 0x18, 0x18,
+    0x18, 0x18, 0xFF, 0xFF, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF,
+    0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03,
+    0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, 0xC0,
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18,
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,
+    0x03, 0x03, 0xFF, 0xFF, 0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xFC, 0xFC,
+    0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3,
+    0xFF, 0x3C, 0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, 0xFF, 0xFF, 0xC0, 0xC0,
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0,
+];
+
+/// The built-in fonts, as of the platforms that they come from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum FontSet {
+    /// The font of CHIP-48, which most interpreters use.
+    #[default]
+    Chip48,
+    Vip,
+    Dream6800,
+    Eti660,
+    /// The font of CHIP-48, followed by the big decimal digits of the SUPER-CHIP.
+    Schip,
+    /// The fonts of Octo, with big hex digits.
+    Octo,
+}
+
+impl FontSet {
+    /// The bytes of the font, with the big font after the small one if there is one.
+    pub fn font(self) -> Font {
+        let (small, big): (&[u8], &[u8]) = match self {
+            FontSet::Chip48 => (&CHIP48_FONT, &[]),
+            FontSet::Vip => (&VIP_FONT, &[]),
+            FontSet::Dream6800 => (&DREAM6800_FONT, &[]),
+            FontSet::Eti660 => (&ETI660_FONT, &[]),
+            FontSet::Schip => (&CHIP48_FONT, &SCHIP_BIG_FONT),
+            FontSet::Octo => (&OCTO_FONT, &OCTO_BIG_FONT),
+        };
+        Font {
+            bytes: [small, big].concat().into(),
+        }
+    }
+}
+
+impl FromStr for FontSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chip48" => Ok(FontSet::Chip48),
+            "vip" => Ok(FontSet::Vip),
+            "dream6800" => Ok(FontSet::Dream6800),
+            "eti660" => Ok(FontSet::Eti660),
+            "schip" => Ok(FontSet::Schip),
+            "octo" => Ok(FontSet::Octo),
+            _ => Err(format!(
+                "Unknown font: {} (available: chip48, vip, dream6800, eti660, schip, octo)",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FontSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for FontSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontSet::Chip48 => write!(f, "chip48"),
+            FontSet::Vip => write!(f, "vip"),
+            FontSet::Dream6800 => write!(f, "dream6800"),
+            FontSet::Eti660 => write!(f, "eti660"),
+            FontSet::Schip => write!(f, "schip"),
+            FontSet::Octo => write!(f, "octo"),
+        }
+    }
+}
+
+/// The sprites of the hex digits that `FX29` points I to, five bytes each, optionally
+/// followed by a big font of ten bytes for each decimal or hex digit. The bytes are
+/// shared between clones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Font {
+    bytes: Arc<[u8]>,
+}
+
+impl Font {
+    /// A font from its bytes, which are the small font, optionally followed by a big font.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Font, String> {
+        match bytes.len().checked_sub(SMALL_FONT_SIZE) {
+            Some(0) | Some(BIG_DECIMAL_FONT_SIZE) | Some(BIG_HEX_FONT_SIZE) => Ok(Font {
+                bytes: bytes.into(),
+            }),
+            _ => Err(format!(
+                "A font has {} bytes, or {} or {} bytes with a big font, not {}",
+                SMALL_FONT_SIZE,
+                SMALL_FONT_SIZE + BIG_DECIMAL_FONT_SIZE,
+                SMALL_FONT_SIZE + BIG_HEX_FONT_SIZE,
+                bytes.len()
+            )),
+        }
+    }
+
+    /// Read a font from a binary file.
+    pub fn from_file(path: &Path) -> Result<Font, String> {
+        let bytes =
+            fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Font::from_bytes(bytes).map_err(|e| format!("Invalid font {}: {}", path.display(), e))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        FontSet::default().font()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT_SETS: [FontSet; 6] = [
+        FontSet::Chip48,
+        FontSet::Vip,
+        FontSet::Dream6800,
+        FontSet::Eti660,
+        FontSet::Schip,
+        FontSet::Octo,
+    ];
+
+    #[test]
+    fn font_sets_have_sixteen_small_glyphs() {
+        for &font_set in &FONT_SETS {
+            let font = font_set.font();
+            assert!(
+                Font::from_bytes(font.bytes().to_vec()).is_ok(),
+                "{}",
+                font_set
+            );
+            for glyph in font.bytes()[..SMALL_FONT_SIZE].chunks(5) {
+                // Every glyph is drawn in the four leftmost pixels.
+                assert!(glyph.iter().any(|&row| row != 0), "{}", font_set);
+                assert!(glyph.iter().all(|&row| row & 0x0F == 0), "{}", font_set);
+            }
+            assert_eq!(font_set.to_string().parse(), Ok(font_set));
+        }
+    }
+
+    #[test]
+    fn reads_fonts_from_files() {
+        let path = std::env::temp_dir().join(format!("chip8-font-{}.bin", std::process::id()));
+        fs::write(&path, FontSet::Octo.font().bytes()).unwrap();
+        assert_eq!(Font::from_file(&path), Ok(FontSet::Octo.font()));
+
+        fs::write(&path, &CHIP48_FONT[..SMALL_FONT_SIZE - 1]).unwrap();
+        let error = Font::from_file(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(error.starts_with("Invalid font"), "{}", error);
+        assert!(error.ends_with("not 79"), "{}", error);
+    }
+}
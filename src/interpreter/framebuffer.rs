@@ -0,0 +1,96 @@
+use crate::config::{SCREEN_X, SCREEN_Y};
+
+/// One row of pixels, with the leftmost pixel in the highest bit.
+pub type Row = u64;
+
+const _: () = assert!(Row::BITS as usize == SCREEN_X);
+
+/// The pixels of the display, stored as one bit per pixel in row-major order.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Framebuffer {
+    rows: [Row; SCREEN_Y],
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Framebuffer {
+            rows: [0; SCREEN_Y],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        SCREEN_X
+    }
+
+    pub fn height(&self) -> usize {
+        SCREEN_Y
+    }
+
+    /// The rows of pixels, from top to bottom.
+    pub fn rows(&self) -> &[Row; SCREEN_Y] {
+        &self.rows
+    }
+
+    pub(crate) fn from_rows(rows: [Row; SCREEN_Y]) -> Self {
+        Framebuffer { rows }
+    }
+
+    /// The pixels of the row at y, from left to right.
+    pub fn row_pixels(&self, y: usize) -> impl Iterator<Item = bool> {
+        let row = self.rows[y];
+        (0..SCREEN_X).map(move |x| row & Self::mask(x) != 0)
+    }
+
+    /// The pixels of every row, from top to bottom and left to right.
+    pub fn pixel_rows(&self) -> impl Iterator<Item = impl Iterator<Item = bool>> + '_ {
+        (0..SCREEN_Y).map(move |y| self.row_pixels(y))
+    }
+
+    /// XOR the bits into the row at y. Return value is whether any pixel was turned off.
+    pub(super) fn xor_row(&mut self, y: usize, bits: Row) -> bool {
+        let collision = self.rows[y] & bits != 0;
+        self.rows[y] ^= bits;
+        collision
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.rows = [0; SCREEN_Y];
+    }
+
+    fn mask(x: usize) -> Row {
+        1 << (SCREEN_X - 1 - x)
+    }
+}
+
+/// An area of the display whose pixels have changed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl DirtyRect {
+    /// The whole display.
+    pub fn full() -> Self {
+        DirtyRect {
+            x: 0,
+            y: 0,
+            width: SCREEN_X,
+            height: SCREEN_Y,
+        }
+    }
+
+    /// The smallest area that covers both areas.
+    pub fn union(self, other: DirtyRect) -> DirtyRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        DirtyRect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
@@ -1,7 +1,6 @@
-use log::trace;
-
 pub const NUMBER_OF_KEYS: u8 = 16;
 
+#[derive(Clone)]
 pub struct Keyboard {
     key_states: [bool; NUMBER_OF_KEYS as usize],
 }
@@ -14,11 +13,6 @@ impl Keyboard {
     }
 
     pub fn key_state(&mut self, key: u8) -> bool {
-        trace!(
-            "\tKEYBOARD - Read key {} = {}",
-            key,
-            self.key_states[key as usize]
-        );
         let state = self.key_states[key as usize];
         if state {
             self.key_states[key as usize] = false;
@@ -28,16 +22,24 @@ impl Keyboard {
 
     /// Set key as pressed.
     pub fn key_pressed(&mut self, key: u8) {
-        trace!("\tKEYBOARD - Setting key {} = pressed", key);
         self.key_states[key as usize] = true;
     }
 
     /// Set key as released.
     pub fn key_released(&mut self, key: u8) {
-        trace!("\tKEYBOARD - Read key {} = released", key);
         self.key_states[key as usize] = false;
     }
 
+    /// Whether each key is pressed.
+    pub fn key_states(&self) -> [bool; NUMBER_OF_KEYS as usize] {
+        self.key_states
+    }
+
+    /// Set whether each key is pressed.
+    pub fn restore(&mut self, key_states: [bool; NUMBER_OF_KEYS as usize]) {
+        self.key_states = key_states;
+    }
+
     /// Reset the keyboard state.
     pub fn reset(&mut self) {
         self.key_states = [false; NUMBER_OF_KEYS as usize];
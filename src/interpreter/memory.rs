@@ -1,70 +1,75 @@
-use log::trace;
+use crate::interpreter::Font;
 
+#[derive(Clone)]
 pub struct Memory {
-    ram: [u8; 4096],
+    ram: Vec<u8>,
+    /// Where the font is placed in memory.
+    font_address: usize,
+    font: Font,
 }
 
 impl Memory {
-    /// Create new memory with its initial state, including
-    /// initializing the interpreter-exclusive memory.
-    pub fn new() -> Self {
-        let mut memory = Memory { ram: [0; 4096] };
+    /// Create new memory of the given size with its initial state, including
+    /// initializing the interpreter-exclusive memory with the font.
+    pub fn new(size: usize, font_address: u16, font: Font) -> Self {
+        let mut memory = Memory {
+            ram: vec![0; size],
+            font_address: font_address as usize,
+            font,
+        };
         memory.reset();
         memory
     }
 
     /// Read the next two bytes from memory
     pub fn two_byte_read(&self, index: u16) -> (u8, u8) {
-        trace!(
-            "\tMEMORY - Two byte read from {:X} = {:X}-{:X}",
-            index,
-            self.ram[index as usize],
-            self.ram[(index + 1) as usize]
-        );
-        (self.ram[index as usize], self.ram[(index + 1) as usize])
+        (
+            self.ram[self.wrap(index as usize)],
+            self.ram[self.wrap(index as usize + 1)],
+        )
     }
 
     /// Read a byte from memory.
     pub fn byte_read(&self, index: u16) -> u8 {
-        trace!(
-            "\tMEMORY - Byte read from {:X} = {:X}",
-            index,
-            self.ram[index as usize]
-        );
-        self.ram[index as usize]
+        self.ram[self.wrap(index as usize)]
+    }
+
+    /// All bytes of memory.
+    pub fn bytes(&self) -> &[u8] {
+        &self.ram
     }
 
     /// Write to a byte in memory.
     pub fn byte_write(&mut self, index: u16, value: u8) {
-        trace!("\tMEMORY - Byte write to {:X} = {:X}", index, value);
-        self.ram[index as usize] = value
+        let index = self.wrap(index as usize);
+        self.ram[index] = value
+    }
+
+    /// Addresses past the end of a memory smaller than 64 KiB wrap around to its start.
+    fn wrap(&self, index: usize) -> usize {
+        index % self.ram.len()
     }
 
-    /// Write an array of bytes in memory, starting from start_index.
-    pub fn bytes_write(&mut self, start_index: usize, bytes: &[u8]) {
-        trace!(
-            "\tMEMORY - Bytes write to {:X} with length {:X}",
-            start_index,
-            bytes.len()
-        );
-        for i in 0..bytes.len() {
-            self.ram[i + start_index] = bytes[i];
+    /// Write an array of bytes in memory, starting from start_index. Fails without
+    /// writing anything if the bytes do not fit into memory.
+    pub fn bytes_write(&mut self, start_index: usize, bytes: &[u8]) -> Result<(), String> {
+        let available = self.ram.len().saturating_sub(start_index);
+        if bytes.len() > available {
+            return Err(format!(
+                "{} bytes do not fit into memory at {:#X} (at most {} bytes)",
+                bytes.len(),
+                start_index,
+                available
+            ));
         }
+        self.ram[start_index..start_index + bytes.len()].copy_from_slice(bytes);
+        Ok(())
     }
 
     /// Reset the memory to its initial state
     pub fn reset(&mut self) {
         self.ram.iter_mut().for_each(|m| *m = 0);
-
-        let sprites = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80,
-            0xF0, 0xF0, 0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0,
-            0x10, 0xF0, 0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90,
-            0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0, 0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0,
-            0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0,
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
-        ];
-
-        self.bytes_write(0, &sprites);
+        let font = &mut self.ram[self.font_address..self.font_address + self.font.bytes().len()];
+        font.copy_from_slice(self.font.bytes());
     }
 }
@@ -0,0 +1,136 @@
+use serde::Deserialize;
+
+use crate::config::{DEFAULT_FONT_ADDRESS, DEFAULT_MEMORY_SIZE, DEFAULT_PROGRAM_START};
+
+/// The largest memory that 16-bit addresses can reach.
+const MAX_MEMORY_SIZE: usize = 0x10000;
+
+/// Where the program and the font are placed in memory, and how large it is. The
+/// defaults are those of the COSMAC VIP; the ETI-660 for example loads programs at
+/// 0x600, and some interpreters keep the font at 0x50.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryMap {
+    /// The address that the program is loaded at.
+    pub program_start: u16,
+    /// The address that the program starts running at, if it is not the address it
+    /// is loaded at, e.g. 0x2C0 for the 64x64 high resolution programs of the VIP.
+    pub entry_point: Option<u16>,
+    /// The address of the font that `FX29` points I to.
+    pub font_address: u16,
+    /// The size of the memory in bytes, at most 64 KiB.
+    pub size: usize,
+}
+
+impl MemoryMap {
+    /// The address that the program starts running at.
+    pub fn entry_point(&self) -> u16 {
+        self.entry_point.unwrap_or(self.program_start)
+    }
+
+    /// The number of bytes of the largest program that fits into memory.
+    pub fn max_program_size(&self) -> usize {
+        self.size.saturating_sub(self.program_start as usize)
+    }
+
+    /// Check that a font of the given number of bytes and the program fit into the
+    /// memory, with the font before the program.
+    pub fn validate(&self, font_size: usize) -> Result<(), String> {
+        if self.size > MAX_MEMORY_SIZE {
+            return Err(format!(
+                "A memory of {} bytes is larger than the {} bytes that can be addressed",
+                self.size, MAX_MEMORY_SIZE
+            ));
+        }
+        if self.font_address as usize + font_size > self.size {
+            return Err(format!(
+                "The font at {:#X} does not fit into a memory of {} bytes",
+                self.font_address, self.size
+            ));
+        }
+        if self.program_start as usize >= self.size || self.entry_point() as usize >= self.size {
+            return Err(format!(
+                "The program at {:#X} does not fit into a memory of {} bytes",
+                self.program_start, self.size
+            ));
+        }
+        if self.font_address as usize + font_size > self.program_start as usize {
+            return Err(format!(
+                "The font at {:#X} overlaps the program at {:#X}",
+                self.font_address, self.program_start
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        MemoryMap {
+            program_start: DEFAULT_PROGRAM_START,
+            entry_point: None,
+            font_address: DEFAULT_FONT_ADDRESS,
+            size: DEFAULT_MEMORY_SIZE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::font::SMALL_FONT_SIZE;
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn rejects_a_program_start_past_the_end_of_memory() {
+        let memory_map = MemoryMap {
+            program_start: 0x1000,
+            ..MemoryMap::default()
+        };
+        assert_eq!(
+            memory_map.validate(SMALL_FONT_SIZE),
+            Err("The program at 0x1000 does not fit into a memory of 4096 bytes".to_string())
+        );
+        assert_eq!(memory_map.max_program_size(), 0);
+    }
+
+    #[test]
+    fn rejects_a_font_overlapping_the_program() {
+        let memory_map = MemoryMap {
+            font_address: 0x1C0,
+            ..MemoryMap::default()
+        };
+        assert_eq!(
+            memory_map.validate(SMALL_FONT_SIZE),
+            Err("The font at 0x1C0 overlaps the program at 0x200".to_string())
+        );
+        let memory_map = MemoryMap {
+            font_address: 0x1B0,
+            ..MemoryMap::default()
+        };
+        assert_eq!(memory_map.validate(SMALL_FONT_SIZE), Ok(()));
+    }
+
+    #[test]
+    fn points_to_the_font_at_its_address() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .set_memory_map(MemoryMap {
+                font_address: 0x50,
+                ..MemoryMap::default()
+            })
+            .unwrap();
+        // V0 = 0xA, I = the glyph of V0, V1 = 0, draw 5 rows at V1, V1.
+        interpreter
+            .load_program_bytes(&[0x60, 0x0A, 0xF0, 0x29, 0x61, 0x00, 0xD1, 0x15])
+            .unwrap();
+        for _ in 0..4 {
+            interpreter.instruction_step();
+        }
+        assert_eq!(interpreter.cpu().i, 0x50 + 5 * 0xA);
+        let glyph = &interpreter.font().bytes()[5 * 0xA..5 * 0xA + 5];
+        for (row, byte) in interpreter.framebuffer().rows().iter().zip(glyph) {
+            assert_eq!(row >> 56, *byte as u64);
+        }
+    }
+}
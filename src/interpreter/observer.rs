@@ -0,0 +1,45 @@
+//! Instrumentation of the interpreter, which is only compiled in with the
+//! `observer` feature, so that it costs nothing otherwise.
+
+use log::{debug, log_enabled, trace, Level};
+
+use crate::instruction::Instruction;
+use crate::interpreter::Cpu;
+
+/// Watches the instructions that the interpreter executes, e.g. to trace or profile
+/// a program.
+pub trait Observer: Send {
+    /// The instruction at the address was executed, which changed the registers and
+    /// timers from `before` to `after`.
+    fn instruction(&mut self, address: u16, instruction: Instruction, before: &Cpu, after: &Cpu);
+}
+
+/// Logs the executed instructions, and on the trace level the registers that they
+/// changed. Interpreters observe themselves with it if debug logs are enabled.
+pub struct LogObserver;
+
+impl Observer for LogObserver {
+    fn instruction(&mut self, address: u16, instruction: Instruction, before: &Cpu, after: &Cpu) {
+        debug!("{:4X} - Executing: {}", address, instruction);
+        if !log_enabled!(Level::Trace) {
+            return;
+        }
+        for (index, (old, new)) in before.v.iter().zip(after.v.iter()).enumerate() {
+            if old != new {
+                trace!("\tV{:X} = {:X}", index, new);
+            }
+        }
+        if before.i != after.i {
+            trace!("\tI = {:X}", after.i);
+        }
+        if before.delay != after.delay {
+            trace!("\tDelay = {:X}", after.delay);
+        }
+        if before.sound != after.sound {
+            trace!("\tSound = {:X}", after.sound);
+        }
+        if after.pc != address.wrapping_add(2) {
+            trace!("\tPC = {:X}", after.pc);
+        }
+    }
+}
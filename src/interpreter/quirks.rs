@@ -0,0 +1,56 @@
+use serde::Deserialize;
+
+/// Behaviours that differ between the CHIP-8 interpreters of different platforms,
+/// named like the quirks of the community chip-8-database. The defaults are the
+/// behaviour of this interpreter before they were configurable.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quirks {
+    /// `8XY6` and `8XYE` shift VX in place, instead of shifting VY into VX.
+    pub shift: bool,
+    /// `FX55` and `FX65` increase I by X, instead of by X + 1.
+    pub memory_increment_by_x: bool,
+    /// `FX55` and `FX65` leave I unchanged. Takes precedence over `memory_increment_by_x`.
+    pub memory_leave_i_unchanged: bool,
+    /// Sprites wrap around the edges of the display, instead of being clipped.
+    pub wrap: bool,
+    /// `BXNN` jumps to XNN + VX, instead of NNN + V0.
+    pub jump: bool,
+    /// `DXYN` waits for the next frame before the program continues.
+    pub vblank: bool,
+    /// `8XY1`, `8XY2` and `8XY3` reset VF to 0.
+    pub logic: bool,
+}
+
+impl Quirks {
+    /// Apply a quirk by its name in the chip-8-database, e.g. `memoryIncrementByX`.
+    /// Return value is whether the name is known.
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        let quirk = match name {
+            "shift" => &mut self.shift,
+            "memoryIncrementByX" => &mut self.memory_increment_by_x,
+            "memoryLeaveIUnchanged" => &mut self.memory_leave_i_unchanged,
+            "wrap" => &mut self.wrap,
+            "jump" => &mut self.jump,
+            "vblank" => &mut self.vblank,
+            "logic" => &mut self.logic,
+            _ => return false,
+        };
+        *quirk = value;
+        true
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift: true,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: true,
+            wrap: false,
+            jump: false,
+            vblank: false,
+            logic: false,
+        }
+    }
+}
@@ -1,31 +1,280 @@
-use crate::interpreter::register::Register;
+use std::fmt;
+use std::str::FromStr;
 
-use super::register::Register8Bit;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
 
+use crate::config::DEFAULT_STACK_DEPTH;
+
+/// The size of the call stack, and what happens when a program exceeds it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StackConfig {
+    /// The number of return addresses that the stack holds, e.g. 12 on the COSMAC VIP
+    /// and 16 on the SUPER-CHIP, or 0 for a stack without limit.
+    pub depth: usize,
+    pub on_fault: StackFaultAction,
+}
+
+impl StackConfig {
+    /// The number of return addresses that the stack holds, if it is limited.
+    pub fn limit(&self) -> Option<usize> {
+        match self.depth {
+            0 => None,
+            depth => Some(depth),
+        }
+    }
+}
+
+impl Default for StackConfig {
+    fn default() -> Self {
+        StackConfig {
+            depth: DEFAULT_STACK_DEPTH,
+            on_fault: StackFaultAction::default(),
+        }
+    }
+}
+
+/// What happens when a program calls a subroutine while the stack is full, or
+/// returns from one while it is empty.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum StackFaultAction {
+    /// The interpreter stops with an error.
+    #[default]
+    Error,
+    /// The stack pointer wraps around, overwriting the oldest return address on a
+    /// call, or returning to the deepest one on a return. A stack without limit can
+    /// not wrap, so returning with it empty is an error.
+    Wrap,
+    /// The interpreter halts in step mode before the instruction, so that its state
+    /// can be inspected.
+    Break,
+}
+
+impl FromStr for StackFaultAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(StackFaultAction::Error),
+            "wrap" => Ok(StackFaultAction::Wrap),
+            "break" => Ok(StackFaultAction::Break),
+            _ => Err(format!(
+                "Unknown stack fault action: {} (available: error, wrap, break)",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StackFaultAction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for StackFaultAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackFaultAction::Error => write!(f, "error"),
+            StackFaultAction::Wrap => write!(f, "wrap"),
+            StackFaultAction::Break => write!(f, "break"),
+        }
+    }
+}
+
+/// A call or return that the stack could not hold.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StackFault {
+    /// A subroutine was called while the stack was full.
+    Overflow,
+    /// A subroutine returned while the stack was empty.
+    Underflow,
+}
+
+impl fmt::Display for StackFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackFault::Overflow => write!(f, "Stack overflow"),
+            StackFault::Underflow => write!(f, "Stack underflow"),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Stack {
-    data: [u16; 16],
-    stack_pointer: Register8Bit,
+    /// All entries of a limited stack, or the entries that an unlimited one has
+    /// grown to so far.
+    data: Vec<u16>,
+    stack_pointer: usize,
+    /// The largest number of return addresses on the stack since the last reset.
+    max_depth: usize,
+    config: StackConfig,
 }
 
 impl Stack {
-    pub fn new() -> Self {
+    pub fn new(config: StackConfig) -> Self {
         Stack {
-            data: [0; 16],
-            stack_pointer: Register8Bit::new("SP"),
+            data: vec![0; config.limit().unwrap_or(0)],
+            stack_pointer: 0,
+            max_depth: 0,
+            config,
         }
     }
 
-    pub fn push(&mut self, val: u16) {
-        self.data[self.stack_pointer.value() as usize] = val;
-        self.stack_pointer.increment_value_by(1);
+    pub fn config(&self) -> StackConfig {
+        self.config
     }
 
-    pub fn pop(&mut self) -> u16 {
-        self.data[self.stack_pointer.decrement_value_by(1) as usize]
+    pub fn push(&mut self, val: u16) -> Result<(), StackFault> {
+        match self.config.limit() {
+            Some(depth) if self.stack_pointer >= depth => {
+                if self.config.on_fault != StackFaultAction::Wrap {
+                    return Err(StackFault::Overflow);
+                }
+                self.stack_pointer = 0;
+            }
+            None if self.stack_pointer == self.data.len() => self.data.push(0),
+            _ => {}
+        }
+        self.data[self.stack_pointer] = val;
+        self.stack_pointer += 1;
+        self.max_depth = self.max_depth.max(self.stack_pointer);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Result<u16, StackFault> {
+        if self.stack_pointer == 0 {
+            match self.config.limit() {
+                Some(depth) if self.config.on_fault == StackFaultAction::Wrap => {
+                    self.stack_pointer = depth
+                }
+                _ => return Err(StackFault::Underflow),
+            }
+        }
+        self.stack_pointer -= 1;
+        Ok(self.data[self.stack_pointer])
+    }
+
+    /// The return addresses on the stack, the oldest first.
+    pub fn entries(&self) -> &[u16] {
+        &self.data[..self.stack_pointer]
+    }
+
+    /// The number of return addresses on the stack.
+    pub fn depth(&self) -> usize {
+        self.stack_pointer
+    }
+
+    /// The largest number of return addresses on the stack since the last reset.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// All entries of the stack, the stack pointer and the largest depth.
+    pub fn state(&self) -> (&[u16], usize, usize) {
+        (&self.data, self.stack_pointer, self.max_depth)
+    }
+
+    /// Restore the entries of the stack, the stack pointer and the largest depth.
+    /// Fails if they do not fit the configured depth of the stack.
+    pub fn restore(
+        &mut self,
+        data: Vec<u16>,
+        stack_pointer: usize,
+        max_depth: usize,
+    ) -> Result<(), String> {
+        if let Some(depth) = self.config.limit() {
+            if data.len() != depth {
+                return Err(format!(
+                    "The stack of the save state holds {} entries instead of {}",
+                    data.len(),
+                    depth
+                ));
+            }
+        }
+        if stack_pointer > data.len() {
+            return Err("The stack pointer of the save state is out of bounds".to_string());
+        }
+        self.data = data;
+        self.stack_pointer = stack_pointer;
+        self.max_depth = max_depth;
+        Ok(())
     }
 
     pub fn reset(&mut self) {
-        self.data.iter_mut().for_each(|m| *m = 0);
-        self.stack_pointer.reset();
+        *self = Stack::new(self.config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(depth: usize, on_fault: StackFaultAction) -> Stack {
+        Stack::new(StackConfig { depth, on_fault })
+    }
+
+    #[test]
+    fn overflows_at_the_configured_depth() {
+        let mut stack = stack(2, StackFaultAction::Error);
+        stack.push(0x200).unwrap();
+        stack.push(0x300).unwrap();
+        assert_eq!(stack.push(0x400), Err(StackFault::Overflow));
+        assert_eq!(stack.entries(), &[0x200, 0x300]);
+    }
+
+    #[test]
+    fn underflows_on_return_with_an_empty_stack() {
+        let mut stack = stack(2, StackFaultAction::Error);
+        assert_eq!(stack.pop(), Err(StackFault::Underflow));
+        stack.push(0x200).unwrap();
+        assert_eq!(stack.pop(), Ok(0x200));
+        assert_eq!(stack.pop(), Err(StackFault::Underflow));
+    }
+
+    #[test]
+    fn wrap_overwrites_the_oldest_entry() {
+        let mut stack = stack(2, StackFaultAction::Wrap);
+        stack.push(0x200).unwrap();
+        stack.push(0x300).unwrap();
+        stack.push(0x400).unwrap();
+        assert_eq!(stack.entries(), &[0x400]);
+        assert_eq!(stack.pop(), Ok(0x400));
+        // Returning with the stack empty returns to the deepest entry.
+        assert_eq!(stack.pop(), Ok(0x300));
+        assert_eq!(stack.pop(), Ok(0x400));
+    }
+
+    #[test]
+    fn break_faults_without_changing_the_stack() {
+        let mut stack = stack(1, StackFaultAction::Break);
+        assert_eq!(stack.pop(), Err(StackFault::Underflow));
+        stack.push(0x200).unwrap();
+        assert_eq!(stack.push(0x300), Err(StackFault::Overflow));
+        assert_eq!(stack.state(), (&[0x200][..], 1, 1));
+    }
+
+    #[test]
+    fn depth_zero_is_unlimited() {
+        let mut stack = stack(0, StackFaultAction::Wrap);
+        assert_eq!(
+            StackConfig {
+                depth: 0,
+                on_fault: StackFaultAction::Wrap
+            }
+            .limit(),
+            None
+        );
+        for address in 0..1000 {
+            stack.push(address).unwrap();
+        }
+        assert_eq!(stack.depth(), 1000);
+        assert_eq!(stack.pop(), Ok(999));
+        stack.reset();
+        // An unlimited stack can not wrap.
+        assert_eq!(stack.pop(), Err(StackFault::Underflow));
     }
 }
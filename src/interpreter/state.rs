@@ -0,0 +1,231 @@
+use crate::config::SCREEN_Y;
+use crate::interpreter::framebuffer::{Framebuffer, Row};
+use crate::interpreter::keyboard::NUMBER_OF_KEYS;
+use crate::interpreter::Interpreter;
+
+/// Marks the start of a save state.
+const MAGIC: &[u8; 4] = b"C8SS";
+/// Version of the save state format, increased whenever it changes.
+const VERSION: u8 = 3;
+
+impl Interpreter {
+    /// Save the state of the interpreter into bytes. The loaded program is not part
+    /// of the state, so it can only be loaded again with the same program.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(self.state_size());
+        state.extend_from_slice(MAGIC);
+        state.push(VERSION);
+        state.extend_from_slice(self.memory.bytes());
+        let (stack, stack_pointer, max_depth) = self.stack.state();
+        state.extend_from_slice(&(stack.len() as u32).to_le_bytes());
+        stack
+            .iter()
+            .for_each(|entry| state.extend_from_slice(&entry.to_le_bytes()));
+        state.extend_from_slice(&(stack_pointer as u32).to_le_bytes());
+        state.extend_from_slice(&(max_depth as u32).to_le_bytes());
+        state.extend_from_slice(&self.cpu.v);
+        state.extend_from_slice(&self.cpu.i.to_le_bytes());
+        state.push(self.cpu.delay);
+        state.push(self.cpu.sound);
+        state.extend_from_slice(&self.cpu.pc.to_le_bytes());
+        state.push(self.waiting_for_key_press as u8);
+        state.push(self.key_press_result_register);
+        state.push(self.waiting_for_vblank as u8);
+        self.display
+            .framebuffer()
+            .rows()
+            .iter()
+            .for_each(|row| state.extend_from_slice(&row.to_le_bytes()));
+        self.keyboard
+            .key_states()
+            .iter()
+            .for_each(|pressed| state.push(*pressed as u8));
+        state
+    }
+
+    /// Restore a state saved by `save_state`. The state is left unchanged if the
+    /// bytes are not a valid save state.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let invalid = || "Not a save state of this interpreter".to_string();
+        let stack_position = MAGIC.len() + 1 + self.memory.bytes().len();
+        if state.len() < stack_position + 4 || &state[..MAGIC.len()] != MAGIC {
+            return Err(invalid());
+        }
+        if state[MAGIC.len()] != VERSION {
+            return Err(format!(
+                "Unsupported save state version {}",
+                state[MAGIC.len()]
+            ));
+        }
+
+        let mut reader = StateReader {
+            state,
+            position: MAGIC.len() + 1,
+        };
+        let memory = reader.bytes(self.memory.bytes().len());
+        let stack_entries = reader.u32() as usize;
+        if stack_entries > state.len() || state.len() != self.state_size_with_stack(stack_entries) {
+            return Err(invalid());
+        }
+        let stack = (0..stack_entries).map(|_| reader.u16()).collect();
+        let stack_pointer = reader.u32() as usize;
+        let max_stack_depth = reader.u32() as usize;
+        let registers = reader.bytes(self.cpu.v.len());
+        let i_register = reader.u16();
+        let delay = reader.u8();
+        let sound = reader.u8();
+        let program_counter = reader.u16();
+        let waiting_for_key_press = reader.u8() != 0;
+        let key_press_result_register = reader.u8();
+        if key_press_result_register as usize >= self.cpu.v.len() {
+            return Err(format!(
+                "The save state waits for a key press into the unknown register {}",
+                key_press_result_register
+            ));
+        }
+        let waiting_for_vblank = reader.u8() != 0;
+        let mut rows = [0; SCREEN_Y];
+        rows.iter_mut().for_each(|row| *row = reader.row());
+        let mut key_states = [false; NUMBER_OF_KEYS as usize];
+        key_states
+            .iter_mut()
+            .for_each(|pressed| *pressed = reader.u8() != 0);
+
+        self.stack.restore(stack, stack_pointer, max_stack_depth)?;
+        self.memory.bytes_write(0, memory)?;
+        self.cpu.v.copy_from_slice(registers);
+        self.cpu.i = i_register;
+        self.cpu.delay = delay;
+        self.cpu.sound = sound;
+        self.cpu.pc = program_counter;
+        self.waiting_for_key_press = waiting_for_key_press;
+        self.key_press_result_register = key_press_result_register;
+        self.waiting_for_vblank = waiting_for_vblank;
+        self.display.restore(Framebuffer::from_rows(rows));
+        self.keyboard.restore(key_states);
+        Ok(())
+    }
+
+    /// The number of bytes of a save state. It only changes when a stack without
+    /// limit grows.
+    pub fn state_size(&self) -> usize {
+        let (stack, _, _) = self.stack.state();
+        self.state_size_with_stack(stack.len())
+    }
+
+    /// The number of bytes of a save state with the given number of stack entries.
+    fn state_size_with_stack(&self, stack_entries: usize) -> usize {
+        MAGIC.len()
+            + 1
+            + self.memory.bytes().len()
+            + 4
+            + stack_entries * 2
+            + 4
+            + 4
+            + self.cpu.v.len()
+            + 2
+            + 1
+            + 1
+            + 2
+            + 1
+            + 1
+            + 1
+            + SCREEN_Y * std::mem::size_of::<Row>()
+            + NUMBER_OF_KEYS as usize
+    }
+}
+
+/// Reads the values of a save state one after another.
+struct StateReader<'a> {
+    state: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    fn bytes(&mut self, length: usize) -> &'a [u8] {
+        let bytes = &self.state[self.position..self.position + length];
+        self.position += length;
+        bytes
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.bytes(2));
+        u16::from_le_bytes(bytes)
+    }
+
+    fn u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4));
+        u32::from_le_bytes(bytes)
+    }
+
+    fn row(&mut self) -> Row {
+        let mut bytes = [0; std::mem::size_of::<Row>()];
+        bytes.copy_from_slice(self.bytes(std::mem::size_of::<Row>()));
+        Row::from_le_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The position of the register that a key press is stored into, from the end.
+    const KEY_REGISTER_FROM_END: usize =
+        NUMBER_OF_KEYS as usize + SCREEN_Y * std::mem::size_of::<Row>() + 2;
+
+    fn interpreter() -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        // V5 = 0x2A, wait for a key press into V7.
+        interpreter
+            .load_program_bytes(&[0x65, 0x2A, 0xF7, 0x0A])
+            .unwrap();
+        interpreter.instruction_step();
+        interpreter.instruction_step();
+        interpreter
+    }
+
+    #[test]
+    fn restores_saved_state() {
+        let state = interpreter().save_state();
+        let mut restored = Interpreter::new();
+        restored
+            .load_program_bytes(&[0x65, 0x2A, 0xF7, 0x0A])
+            .unwrap();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.cpu.v[5], 0x2A);
+        assert_eq!(restored.key_press_result_register, 7);
+    }
+
+    #[test]
+    fn rejects_unknown_key_register() {
+        let mut state = interpreter().save_state();
+        let position = state.len() - KEY_REGISTER_FROM_END;
+        assert_eq!(state[position], 7);
+        state[position] = 16;
+
+        let mut restored = Interpreter::new();
+        let unchanged = restored.save_state();
+        let error = restored.load_state(&state).unwrap_err();
+        assert!(error.contains("register 16"), "{}", error);
+        assert_eq!(restored.save_state(), unchanged);
+    }
+
+    #[test]
+    fn rejects_other_data() {
+        let mut interpreter = Interpreter::new();
+        assert!(interpreter.load_state(b"C8SS").is_err());
+        let mut state = interpreter.save_state();
+        state[4] = VERSION + 1;
+        assert!(interpreter.load_state(&state).is_err());
+        state.pop();
+        state[4] = VERSION;
+        assert!(interpreter.load_state(&state).is_err());
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::config::{
+    DEFAULT_ANALOG_STICK, DEFAULT_CONTROLLER_KEYPAD, DEFAULT_CONTROLS, DEFAULT_KEYPAD,
+};
+use crate::database::GameKeys;
+
+/// Prefix of a binding that refers to a physical key position instead of a key symbol.
+const SCANCODE_PREFIX: &str = "scancode:";
+
+/// Something that happens on press of a bound key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// A key of the CHIP-8 hex keypad.
+    Keypad(u8),
+    /// Reset the interpreter.
+    Reset,
+    /// Execute the next instruction in step mode.
+    InstructionStep,
+    /// Quit the interpreter.
+    Exit,
+    /// Switch to the next built-in palette.
+    CyclePalette,
+    /// Save a screenshot of the display.
+    Screenshot,
+    /// Start or stop recording the display and sound.
+    Record,
+    /// Switch between fullscreen and window.
+    ToggleFullscreen,
+    /// Pause or resume the interpreter.
+    Pause,
+    /// Run the next frame while paused or in step mode.
+    FrameAdvance,
+    /// Switch step mode on or off.
+    ToggleStepMode,
+    /// Run faster while held.
+    FastForward,
+    /// Show the launcher to pick another ROM.
+    Launcher,
+}
+
+impl Action {
+    /// Look up a control by the name it has in the `[controls]` section of a keymap file.
+    pub fn from_control_name(name: &str) -> Option<Action> {
+        match name {
+            "reset" => Some(Action::Reset),
+            "step" => Some(Action::InstructionStep),
+            "exit" => Some(Action::Exit),
+            "palette" => Some(Action::CyclePalette),
+            "screenshot" => Some(Action::Screenshot),
+            "record" => Some(Action::Record),
+            "fullscreen" => Some(Action::ToggleFullscreen),
+            "pause" => Some(Action::Pause),
+            "frame_advance" => Some(Action::FrameAdvance),
+            "step_mode" => Some(Action::ToggleStepMode),
+            "fast_forward" => Some(Action::FastForward),
+            "launcher" => Some(Action::Launcher),
+            _ => None,
+        }
+    }
+}
+
+/// A key that an action is bound to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    /// The key that produces this symbol in the active keyboard layout, e.g. `W`.
+    Key(String),
+    /// The key at this physical position, independent of the keyboard layout.
+    /// Positions are named after the key of a US QWERTY keyboard, e.g. `scancode:W`.
+    Scancode(String),
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(name) => write!(f, "{}", name),
+            Binding::Scancode(position) => write!(f, "{}{}", SCANCODE_PREFIX, position),
+        }
+    }
+}
+
+impl Binding {
+    fn parse(binding: &str) -> Binding {
+        match binding.strip_prefix(SCANCODE_PREFIX) {
+            Some(position) => Binding::Scancode(position.to_string()),
+            None => Binding::Key(binding.to_string()),
+        }
+    }
+}
+
+/// The keys and game controller buttons bound to the hex keypad and to the
+/// interpreter controls.
+pub struct Keymap {
+    bindings: HashMap<Action, Vec<Binding>>,
+    /// The bindings set by a keymap file, rather than by default.
+    explicit: HashSet<Binding>,
+    button_bindings: HashMap<Action, Vec<String>>,
+    analog_stick: bool,
+}
+
+impl Keymap {
+    /// Load the keymap from a TOML file. Bindings that are not part of the file keep
+    /// their default. If the file has an override section for `rom_name`, it is applied
+    /// on top of the general bindings.
+    pub fn from_file(path: &Path, rom_name: Option<&str>) -> Result<Keymap, String> {
+        let mut keymap = Keymap::default();
+        keymap.apply_file(path, rom_name)?;
+        Ok(keymap)
+    }
+
+    /// The keymap for the ROM at the given path: the default bindings, with the keys
+    /// that the ROM database knows for the game, and the bindings of the keymap file
+    /// with its overrides for the ROM on top, if a file is given.
+    pub fn for_rom(
+        keymap_file: Option<&Path>,
+        rom: &Path,
+        game_keys: Option<&GameKeys>,
+    ) -> Result<Keymap, String> {
+        let mut keymap = Keymap::default();
+        if let Some(game_keys) = game_keys {
+            keymap.bind_game_keys(game_keys);
+        }
+        if let Some(path) = keymap_file {
+            let rom_name = rom.file_name().and_then(|name| name.to_str());
+            keymap.apply_file(path, rom_name)?;
+        }
+        Ok(keymap)
+    }
+
+    /// All keyboard bindings with the action they trigger.
+    pub fn bindings(&self) -> impl Iterator<Item = (Action, &Binding)> {
+        self.bindings
+            .iter()
+            .flat_map(|(action, bindings)| bindings.iter().map(move |b| (*action, b)))
+    }
+
+    /// Whether the binding was set by a keymap file. Frontends let these take
+    /// precedence over default bindings that refer to the same key another way, e.g.
+    /// by its position instead of its symbol.
+    pub fn is_explicit(&self, binding: &Binding) -> bool {
+        self.explicit.contains(binding)
+    }
+
+    /// All game controller bindings with the action they trigger. Buttons are
+    /// named as in the SDL game controller database, e.g. `a` or `dpup`.
+    #[cfg(feature = "sdl")]
+    pub fn button_bindings(&self) -> impl Iterator<Item = (Action, &str)> {
+        self.button_bindings
+            .iter()
+            .flat_map(|(action, buttons)| buttons.iter().map(move |b| (*action, b.as_str())))
+    }
+
+    /// Whether the left analog stick of game controllers acts as the D-pad.
+    #[cfg(feature = "sdl")]
+    pub fn analog_stick(&self) -> bool {
+        self.analog_stick
+    }
+
+    fn apply_file(&mut self, path: &Path, rom_name: Option<&str>) -> Result<(), String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read keymap {}: {}", path.display(), e))?;
+        let file: KeymapFile = toml::from_str(&contents)
+            .map_err(|e| format!("Could not parse keymap {}: {}", path.display(), e))?;
+
+        self.apply(&file.bindings)?;
+        if let Some(overrides) = rom_name.and_then(|name| file.rom.get(name)) {
+            self.apply(overrides)?;
+        }
+        Ok(())
+    }
+
+    /// Bind the arrow keys and the D-pad to the keys the game moves with, and
+    /// `Return`, `Right Shift` and the A and B buttons to its action keys.
+    fn bind_game_keys(&mut self, keys: &GameKeys) {
+        let game_bindings = [
+            (keys.up, "Up", "dpup"),
+            (keys.down, "Down", "dpdown"),
+            (keys.left, "Left", "dpleft"),
+            (keys.right, "Right", "dpright"),
+            (keys.a, "Return", "a"),
+            (keys.b, "Right Shift", "b"),
+        ];
+        for (key, binding, button) in game_bindings.iter() {
+            if let Some(key) = key {
+                let action = Action::Keypad(*key);
+                let binding = Binding::Key(binding.to_string());
+                for bindings in self.bindings.values_mut() {
+                    bindings.retain(|b| *b != binding);
+                }
+                self.bindings.entry(action).or_default().push(binding);
+                for buttons in self.button_bindings.values_mut() {
+                    buttons.retain(|b| b != button);
+                }
+                self.button_bindings
+                    .entry(action)
+                    .or_default()
+                    .push(button.to_string());
+            }
+        }
+    }
+
+    /// Replace the bindings of every action that is part of the given section. Keys
+    /// and buttons of the section are no longer bound to other actions. Fails if the
+    /// section binds a key or button to more than one action.
+    fn apply(&mut self, section: &KeymapSection) -> Result<(), String> {
+        let actions = section.bindings.actions()?;
+        check_duplicates(&actions, "Key")?;
+        for (action, bindings) in actions {
+            let bindings: Vec<Binding> = bindings.iter().map(|b| Binding::parse(b)).collect();
+            for other in self.bindings.values_mut() {
+                other.retain(|b| !bindings.contains(b));
+            }
+            self.explicit.extend(bindings.iter().cloned());
+            self.bindings.insert(action, bindings);
+        }
+        let button_actions = section.controller.bindings.actions()?;
+        check_duplicates(&button_actions, "Button")?;
+        for (action, buttons) in button_actions {
+            for other in self.button_bindings.values_mut() {
+                other.retain(|b| !buttons.contains(b));
+            }
+            self.button_bindings.insert(action, buttons.to_vec());
+        }
+        if let Some(analog_stick) = section.controller.analog_stick {
+            self.analog_stick = analog_stick;
+        }
+        Ok(())
+    }
+
+    fn bind(&mut self, action: Action, bindings: &[String]) {
+        self.bindings
+            .insert(action, bindings.iter().map(|b| Binding::parse(b)).collect());
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+            explicit: HashSet::new(),
+            button_bindings: HashMap::new(),
+            analog_stick: DEFAULT_ANALOG_STICK,
+        };
+        for (key, binding) in DEFAULT_KEYPAD.iter().enumerate() {
+            keymap.bind(Action::Keypad(key as u8), &[binding.to_string()]);
+        }
+        for (name, binding) in DEFAULT_CONTROLS.iter() {
+            let action = Action::from_control_name(name).expect("Invalid default control");
+            keymap.bind(action, &[binding.to_string()]);
+        }
+        for (key, button) in DEFAULT_CONTROLLER_KEYPAD.iter() {
+            keymap
+                .button_bindings
+                .insert(Action::Keypad(*key), vec![button.to_string()]);
+        }
+        keymap
+    }
+}
+
+/// Fail if a key or button is bound to more than one action, as it would trigger any
+/// one of them.
+fn check_duplicates(actions: &[(Action, &[String])], kind: &str) -> Result<(), String> {
+    let mut bound = HashMap::new();
+    for (action, bindings) in actions {
+        for binding in bindings.iter() {
+            match bound.insert(binding.as_str(), *action) {
+                Some(other) if other != *action => {
+                    return Err(format!(
+                        "{} {} is bound to more than one action in keymap",
+                        kind, binding
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Layout of a keymap file.
+#[derive(Deserialize)]
+struct KeymapFile {
+    #[serde(flatten)]
+    bindings: KeymapSection,
+    /// Overrides per ROM, keyed by the file name of the ROM.
+    #[serde(default)]
+    rom: HashMap<String, KeymapSection>,
+}
+
+#[derive(Deserialize, Default)]
+struct KeymapSection {
+    #[serde(flatten)]
+    bindings: BindingsSection,
+    #[serde(default)]
+    controller: ControllerSection,
+}
+
+#[derive(Deserialize, Default)]
+struct ControllerSection {
+    #[serde(flatten)]
+    bindings: BindingsSection,
+    analog_stick: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+struct BindingsSection {
+    #[serde(default)]
+    keypad: HashMap<String, OneOrMany>,
+    #[serde(default)]
+    controls: HashMap<String, OneOrMany>,
+}
+
+impl BindingsSection {
+    /// The bindings of this section by the action they trigger.
+    fn actions(&self) -> Result<Vec<(Action, &[String])>, String> {
+        let mut actions = Vec::new();
+        for (key, bindings) in &self.keypad {
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|k| *k < 16)
+                .ok_or(format!("Invalid keypad key in keymap: {}", key))?;
+            actions.push((Action::Keypad(key), bindings.as_slice()));
+        }
+        for (name, bindings) in &self.controls {
+            let action = Action::from_control_name(name)
+                .ok_or(format!("Unknown control in keymap: {}", name))?;
+            actions.push((action, bindings.as_slice()));
+        }
+        Ok(actions)
+    }
+}
+
+/// A single binding or a list of bindings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn as_slice(&self) -> &[String] {
+        match self {
+            OneOrMany::One(binding) => std::slice::from_ref(binding),
+            OneOrMany::Many(bindings) => bindings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The keymap of a keymap file with the given contents.
+    fn keymap(name: &str, contents: &str, rom_name: Option<&str>) -> Result<Keymap, String> {
+        let path =
+            std::env::temp_dir().join(format!("chip8-keymap-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let keymap = Keymap::from_file(&path, rom_name);
+        fs::remove_file(&path).unwrap();
+        keymap
+    }
+
+    fn bound(keymap: &Keymap, binding: &str) -> Vec<Action> {
+        let binding = Binding::parse(binding);
+        keymap
+            .bindings()
+            .filter(|(_, b)| **b == binding)
+            .map(|(action, _)| action)
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_defaults_of_unbound_actions() {
+        let keymap = keymap("defaults", "[keypad]\n1 = \"Q\"\n", None).unwrap();
+        assert_eq!(bound(&keymap, "Q"), vec![Action::Keypad(1)]);
+        assert_eq!(bound(&keymap, "scancode:2"), vec![]);
+        assert_eq!(bound(&keymap, "scancode:3"), vec![Action::Keypad(2)]);
+        assert_eq!(bound(&keymap, "Escape"), vec![Action::Exit]);
+    }
+
+    #[test]
+    fn moves_a_rebound_key_away_from_its_default_action() {
+        let keymap = keymap("moved", "[controls]\nscreenshot = \"P\"\n", None).unwrap();
+        assert_eq!(bound(&keymap, "P"), vec![Action::Screenshot]);
+        assert!(keymap.is_explicit(&Binding::parse("P")));
+        assert!(!keymap.is_explicit(&Binding::parse("Escape")));
+    }
+
+    #[test]
+    fn rejects_a_key_bound_to_two_actions() {
+        let error = keymap(
+            "conflict",
+            "[keypad]\n1 = \"Q\"\n2 = [\"W\", \"Q\"]\n",
+            None,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error, "Key Q is bound to more than one action in keymap");
+        let error = keymap(
+            "buttons",
+            "[controller.controls]\nreset = \"a\"\nexit = \"a\"\n",
+            None,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error, "Button a is bound to more than one action in keymap");
+    }
+
+    #[test]
+    fn applies_the_overrides_of_the_rom() {
+        let contents = "[keypad]\n5 = \"Up\"\n\n[rom.\"pong.ch8\".keypad]\n1 = \"Up\"\n";
+        let keymap = keymap("rom", contents, Some("pong.ch8")).unwrap();
+        assert_eq!(bound(&keymap, "Up"), vec![Action::Keypad(1)]);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_controls() {
+        let error = keymap("keypad", "[keypad]\n10 = \"Q\"\n", None)
+            .err()
+            .unwrap();
+        assert_eq!(error, "Invalid keypad key in keymap: 10");
+        let error = keymap("control", "[controls]\njump = \"Q\"\n", None)
+            .err()
+            .unwrap();
+        assert_eq!(error, "Unknown control in keymap: jump");
+    }
+}
@@ -0,0 +1,224 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sdl2::gfx::primitives::DrawRenderer;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use crate::config::{LAUNCHER_LINE_HEIGHT, LAUNCHER_MIN_LINES};
+use crate::database::RomDatabase;
+use crate::interpreter::MemoryMap;
+use crate::palette::{Palette, Rgb};
+use crate::recent::RecentRoms;
+use crate::rom;
+
+/// Width and height of a character of the built-in font of SDL2_gfx.
+const CHARACTER_SIZE: i32 = 8;
+
+/// A line of the launcher list.
+enum Line {
+    /// A heading or note, which can not be selected.
+    Text(String),
+    /// A ROM with its title, from the ROM database or else its file name.
+    Rom(PathBuf, String),
+}
+
+/// Lists the recently played ROMs and the ROMs and zip archives of the ROM directory,
+/// or the ROMs of a zip archive, to pick one, by their title in the ROM database if
+/// they are known. It is drawn into the window with the built-in font of SDL2_gfx.
+pub struct Launcher {
+    lines: Vec<Line>,
+    /// Index of the selected line, which is a ROM unless there are none.
+    selected: usize,
+    /// Index of the first line shown.
+    scroll: usize,
+    /// Shown below the list, e.g. why a ROM could not be loaded.
+    message: Option<String>,
+}
+
+impl Launcher {
+    /// List the ROMs of the directory or zip archive, after the recently played ROMs if given.
+    pub fn new(
+        directory: &Path,
+        recent: Option<&RecentRoms>,
+        database: &RomDatabase,
+        memory: &MemoryMap,
+    ) -> Self {
+        let rom = |path: PathBuf| {
+            let title = rom_title(&path, database, memory);
+            Line::Rom(path, title)
+        };
+        let mut lines = Vec::new();
+        let mut message = None;
+        let recent_roms = recent
+            .into_iter()
+            .flat_map(|recent| recent.roms().map(Path::to_path_buf))
+            .collect::<Vec<_>>();
+        if !recent_roms.is_empty() {
+            lines.push(Line::Text("Recently played".to_string()));
+            lines.extend(recent_roms.into_iter().map(rom));
+            lines.push(Line::Text(String::new()));
+        }
+        lines.push(Line::Text(format!("ROMs in {}", directory.display())));
+        match rom_files(directory) {
+            Ok(roms) if roms.is_empty() => lines.push(Line::Text("  (none)".to_string())),
+            Ok(roms) => lines.extend(roms.into_iter().map(rom)),
+            Err(e) => message = Some(e),
+        }
+
+        Launcher {
+            selected: lines
+                .iter()
+                .position(|line| matches!(line, Line::Rom(..)))
+                .unwrap_or(0),
+            lines,
+            scroll: 0,
+            message,
+        }
+    }
+
+    /// Show a message below the list.
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    /// Move the selection by the given number of ROMs, up if negative. The selection
+    /// stops at the first and last ROM.
+    pub fn move_selection(&mut self, steps: isize) {
+        let roms = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| matches!(line, Line::Rom(..)))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        if let Some(position) = roms.iter().position(|index| *index == self.selected) {
+            let position = (position as isize)
+                .saturating_add(steps)
+                .clamp(0, roms.len() as isize - 1);
+            self.selected = roms[position as usize];
+        }
+    }
+
+    /// The selected ROM, if there are any.
+    pub fn selected(&self) -> Option<&Path> {
+        match self.lines.get(self.selected) {
+            Some(Line::Rom(path, _)) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Draw the launcher into the window. `in_game` is whether a ROM is running that
+    /// the launcher can return to.
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        palette: &Palette,
+        in_game: bool,
+    ) -> Result<(), String> {
+        let (width, height) = canvas.output_size()?;
+        let scale = (height / (LAUNCHER_MIN_LINES * LAUNCHER_LINE_HEIGHT as u32)).max(1);
+        let (width, height) = ((width / scale) as i32, (height / scale) as i32);
+        let background = to_color(palette.background());
+        let foreground = to_color(palette.foreground());
+
+        // The title, a blank line, the list, the message and the help line.
+        let list_lines = (height / LAUNCHER_LINE_HEIGHT - 4).max(1) as usize;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + list_lines {
+            self.scroll = self.selected + 1 - list_lines;
+        }
+
+        canvas.set_scale(scale as f32, scale as f32)?;
+        canvas.set_draw_color(background);
+        canvas.clear();
+        draw_text(canvas, 0, "CHIP-8", foreground)?;
+        for (row, (index, line)) in self
+            .lines
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(list_lines)
+            .enumerate()
+        {
+            let row = row as i32 + 2;
+            match line {
+                Line::Text(text) => draw_text(canvas, row, text, foreground)?,
+                Line::Rom(_, name) => {
+                    if index == self.selected {
+                        canvas.set_draw_color(foreground);
+                        canvas.fill_rect(Rect::new(
+                            0,
+                            row * LAUNCHER_LINE_HEIGHT,
+                            width as u32,
+                            LAUNCHER_LINE_HEIGHT as u32,
+                        ))?;
+                        draw_text(canvas, row, &format!("  {}", name), background)?;
+                    } else {
+                        draw_text(canvas, row, &format!("  {}", name), foreground)?;
+                    }
+                }
+            }
+        }
+        let last_row = height / LAUNCHER_LINE_HEIGHT - 1;
+        if let Some(message) = &self.message {
+            draw_text(canvas, last_row - 1, message, foreground)?;
+        }
+        let help = if in_game {
+            "Up/Down: choose  Return: play  Escape: back to the game"
+        } else {
+            "Up/Down: choose  Return: play  Escape: quit"
+        };
+        draw_text(canvas, last_row, help, foreground)?;
+        canvas.set_scale(1.0, 1.0)?;
+        canvas.present();
+        Ok(())
+    }
+}
+
+/// The ROM files and zip archives of the directory sorted by name, or the ROMs of
+/// the zip archive.
+fn rom_files(directory: &Path) -> Result<Vec<PathBuf>, String> {
+    if rom::is_archive(directory) {
+        return rom::archive_roms(directory);
+    }
+    let entries = fs::read_dir(directory)
+        .map_err(|e| format!("Could not list {}: {}", directory.display(), e))?;
+    let mut roms = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && (rom::is_rom_file(path) || rom::is_archive(path)))
+        .collect::<Vec<_>>();
+    roms.sort_by_key(|path| path.file_name().map(|name| name.to_ascii_lowercase()));
+    Ok(roms)
+}
+
+/// The title and authors of the ROM from the database, or else its file name.
+fn rom_title(path: &Path, database: &RomDatabase, memory: &MemoryMap) -> String {
+    rom::load(path, memory)
+        .ok()
+        .and_then(|rom| database.lookup(&rom.program))
+        .map(|info| info.to_string())
+        .unwrap_or_else(|| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        })
+}
+
+/// Draw text on the given line of launcher text, after a small margin.
+pub fn draw_text(
+    canvas: &mut Canvas<Window>,
+    row: i32,
+    text: &str,
+    color: Color,
+) -> Result<(), String> {
+    let y = row * LAUNCHER_LINE_HEIGHT + (LAUNCHER_LINE_HEIGHT - CHARACTER_SIZE) / 2;
+    canvas.string(CHARACTER_SIZE as i16, y as i16, text, color)
+}
+
+fn to_color(rgb: Rgb) -> Color {
+    Color::RGB(rgb.0, rgb.1, rgb.2)
+}
@@ -0,0 +1,36 @@
+//! A CHIP-8 interpreter with frontends for an SDL window, the terminal, headless
+//! runs, libretro and the browser. The `chip8` binary is the command line around it.
+
+pub mod buzzer;
+pub mod config;
+pub mod database;
+pub mod emulator;
+pub mod filter;
+pub mod frontend;
+#[cfg(feature = "sdl")]
+pub mod geometry;
+pub mod headless;
+pub mod instruction;
+pub mod interpreter;
+pub mod keymap;
+#[cfg(feature = "sdl")]
+pub mod launcher;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+pub mod logger;
+pub mod octo;
+pub mod palette;
+#[cfg(feature = "sdl")]
+pub mod recent;
+pub mod recording;
+pub mod rom;
+pub mod screenshot;
+#[cfg(feature = "sdl")]
+pub mod sdl2;
+pub mod settings;
+pub mod terminal;
+pub mod viewport;
+#[cfg(feature = "sdl")]
+pub mod watch;
+#[cfg(feature = "web")]
+pub mod web;
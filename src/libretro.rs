@@ -0,0 +1,751 @@
+//! A libretro core around the interpreter. Built with the `libretro` feature, the
+//! `chip8` library exports the libretro API, so that the cdylib can be loaded by
+//! libretro frontends like RetroArch.
+//!
+//! The safety requirements of the exported functions are those of the libretro API:
+//! they are called from a single thread, and all pointers are valid for the sizes
+//! given along with them.
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::{c_char, c_uint};
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
+use std::sync::{Mutex, OnceLock};
+
+use log::warn;
+
+use crate::buzzer::Buzzer;
+use crate::config::{
+    AUDIO_SAMPLE_RATE, DEFAULT_REFRESH_RATE, LIBRETRO_DATABASE_DIRECTORY, LIBRETRO_MAX_STACK_DEPTH,
+    SCREEN_X, SCREEN_Y,
+};
+use crate::database::RomDatabase;
+use crate::emulator::Emulator;
+use crate::frontend::{Frontend, FRAME_MICROS};
+use crate::interpreter::{DirtyRect, Framebuffer, Interpreter, Quirks};
+use crate::keymap::Action;
+use crate::palette::{Palette, Rgb};
+use crate::rom::Rom;
+use crate::settings::Settings;
+
+const RETRO_API_VERSION: c_uint = 1;
+
+const RETRO_ENVIRONMENT_SHUTDOWN: c_uint = 7;
+const RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY: c_uint = 9;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS: c_uint = 11;
+const RETRO_ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const RETRO_ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+const RETRO_REGION_NTSC: c_uint = 0;
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+
+const RETRO_DEVICE_ID_JOYPAD_B: c_uint = 0;
+const RETRO_DEVICE_ID_JOYPAD_Y: c_uint = 1;
+const RETRO_DEVICE_ID_JOYPAD_SELECT: c_uint = 2;
+const RETRO_DEVICE_ID_JOYPAD_START: c_uint = 3;
+const RETRO_DEVICE_ID_JOYPAD_UP: c_uint = 4;
+const RETRO_DEVICE_ID_JOYPAD_DOWN: c_uint = 5;
+const RETRO_DEVICE_ID_JOYPAD_LEFT: c_uint = 6;
+const RETRO_DEVICE_ID_JOYPAD_RIGHT: c_uint = 7;
+const RETRO_DEVICE_ID_JOYPAD_A: c_uint = 8;
+const RETRO_DEVICE_ID_JOYPAD_X: c_uint = 9;
+const RETRO_DEVICE_ID_JOYPAD_L: c_uint = 10;
+const RETRO_DEVICE_ID_JOYPAD_R: c_uint = 11;
+const RETRO_DEVICE_ID_JOYPAD_L2: c_uint = 12;
+const RETRO_DEVICE_ID_JOYPAD_R2: c_uint = 13;
+const RETRO_DEVICE_ID_JOYPAD_L3: c_uint = 14;
+const RETRO_DEVICE_ID_JOYPAD_R3: c_uint = 15;
+
+/// The RetroPad buttons and the keys of the keypad they press. The D-pad and A and
+/// B press the same keys as with game controllers in the SDL window.
+const KEYPAD_BUTTONS: [(c_uint, u8, &str); 16] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2, "Key 2"),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4, "Key 4"),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6, "Key 6"),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8, "Key 8"),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x5, "Key 5"),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0xA, "Key A"),
+    (RETRO_DEVICE_ID_JOYPAD_X, 0x0, "Key 0"),
+    (RETRO_DEVICE_ID_JOYPAD_Y, 0xB, "Key B"),
+    (RETRO_DEVICE_ID_JOYPAD_L, 0x1, "Key 1"),
+    (RETRO_DEVICE_ID_JOYPAD_R, 0x3, "Key 3"),
+    (RETRO_DEVICE_ID_JOYPAD_L2, 0x7, "Key 7"),
+    (RETRO_DEVICE_ID_JOYPAD_R2, 0x9, "Key 9"),
+    (RETRO_DEVICE_ID_JOYPAD_L3, 0xD, "Key D"),
+    (RETRO_DEVICE_ID_JOYPAD_R3, 0xE, "Key E"),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, 0xC, "Key C"),
+    (RETRO_DEVICE_ID_JOYPAD_START, 0xF, "Key F"),
+];
+
+/// Speeds of the instructions that can be chosen in the core options.
+const SPEED_OPTIONS: [&str; 9] = ["1", "1.5", "2", "3", "5", "10", "20", "50", "0.5"];
+
+const SPEED_OPTION: &str = "chip8_speed";
+const PALETTE_OPTION: &str = "chip8_palette";
+const VBLANK_OPTION: &str = "chip8_vblank";
+
+/// The key and description of a core option, and the quirk it sets.
+type QuirkOption = (&'static str, &'static str, fn(&mut Quirks) -> &mut bool);
+
+/// The quirks that can be set in the core options. With `auto`, the quirk is left as
+/// the ROM database has it.
+const QUIRK_OPTIONS: [QuirkOption; 7] = [
+    (
+        "chip8_quirk_shift",
+        "Quirk: 8XY6/8XYE shift VX in place",
+        |quirks| &mut quirks.shift,
+    ),
+    (
+        "chip8_quirk_memory_increment_by_x",
+        "Quirk: FX55/FX65 increase I by X",
+        |quirks| &mut quirks.memory_increment_by_x,
+    ),
+    (
+        "chip8_quirk_memory_leave_i_unchanged",
+        "Quirk: FX55/FX65 leave I unchanged",
+        |quirks| &mut quirks.memory_leave_i_unchanged,
+    ),
+    (
+        "chip8_quirk_wrap",
+        "Quirk: Sprites wrap around the edges",
+        |quirks| &mut quirks.wrap,
+    ),
+    (
+        "chip8_quirk_jump",
+        "Quirk: BXNN jumps to XNN + VX",
+        |quirks| &mut quirks.jump,
+    ),
+    (
+        "chip8_quirk_vblank",
+        "Quirk: DXYN waits for the next frame",
+        |quirks| &mut quirks.vblank,
+    ),
+    (
+        "chip8_quirk_logic",
+        "Quirk: 8XY1/8XY2/8XY3 reset VF",
+        |quirks| &mut quirks.logic,
+    ),
+];
+
+#[repr(C)]
+pub struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct GameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct SystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct SystemAvInfo {
+    geometry: GameGeometry,
+    timing: SystemTiming,
+}
+
+#[repr(C)]
+pub struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct Variable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[repr(C)]
+struct InputDescriptor {
+    port: c_uint,
+    device: c_uint,
+    index: c_uint,
+    id: c_uint,
+    description: *const c_char,
+}
+
+pub type EnvironmentFn = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+pub type VideoRefreshFn =
+    unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type AudioSampleFn = unsafe extern "C" fn(left: i16, right: i16);
+pub type AudioSampleBatchFn = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type InputPollFn = unsafe extern "C" fn();
+pub type InputStateFn =
+    unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+/// The callbacks into the libretro frontend.
+#[derive(Copy, Clone)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+impl Callbacks {
+    fn environment(&self, cmd: c_uint, data: *mut c_void) -> bool {
+        match self.environment {
+            Some(environment) => unsafe { environment(cmd, data) },
+            None => false,
+        }
+    }
+
+    /// The directory of the frontend for system files of cores, if it has one.
+    fn system_directory(&self) -> Option<PathBuf> {
+        let mut directory: *const c_char = ptr::null();
+        let found = self.environment(
+            RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY,
+            &mut directory as *mut *const c_char as *mut c_void,
+        );
+        if found && !directory.is_null() {
+            let directory = unsafe { CStr::from_ptr(directory) };
+            Some(PathBuf::from(directory.to_string_lossy().into_owned()))
+        } else {
+            None
+        }
+    }
+
+    /// The value of a core option, if the frontend knows it.
+    fn variable(&self, key: &str) -> Option<String> {
+        let key = CString::new(key).ok()?;
+        let mut variable = Variable {
+            key: key.as_ptr(),
+            value: ptr::null(),
+        };
+        let found = self.environment(
+            RETRO_ENVIRONMENT_GET_VARIABLE,
+            &mut variable as *mut Variable as *mut c_void,
+        );
+        if found && !variable.value.is_null() {
+            let value = unsafe { CStr::from_ptr(variable.value) };
+            Some(value.to_string_lossy().into_owned())
+        } else {
+            None
+        }
+    }
+
+    /// Whether the core options changed since they were last read.
+    fn variables_updated(&self) -> bool {
+        let mut updated = false;
+        self.environment(
+            RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE,
+            &mut updated as *mut bool as *mut c_void,
+        ) && updated
+    }
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+/// The loaded game, if any.
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+struct Core {
+    interpreter: Interpreter,
+    emulator: Emulator,
+    frontend: RetroFrontend,
+    settings: Settings,
+    /// Whether the interpreter failed and can not continue until it is reset.
+    stopped: bool,
+}
+
+impl Core {
+    /// Show the display as it is, silently, without running the interpreter.
+    fn present_stopped(&mut self) {
+        self.frontend.set_buzzer(&[(0.0, false)]);
+        if let Err(e) = self.frontend.present(
+            self.interpreter.framebuffer(),
+            Some(DirtyRect::full()),
+            false,
+        ) {
+            warn!("{}", e);
+        }
+    }
+
+    /// Apply the core options that the frontend knows.
+    fn read_options(&mut self, callbacks: &Callbacks) {
+        if let Some(speed) = callbacks.variable(SPEED_OPTION) {
+            match speed.parse() {
+                Ok(speed) => self.settings.emulation.speed = speed,
+                Err(_) => warn!("Invalid speed {}", speed),
+            }
+        }
+        if let Some(palette) = callbacks.variable(PALETTE_OPTION) {
+            match Palette::preset(&palette) {
+                Some(palette) => self.frontend.palette = palette,
+                // The colours of the ROM database or the Octo options, if any.
+                None if palette == "auto" => {
+                    self.frontend.palette =
+                        self.settings.display.palette.clone().unwrap_or_default()
+                }
+                None => warn!("Unknown palette {}", palette),
+            }
+        }
+        if let Some(vblank) = callbacks.variable(VBLANK_OPTION) {
+            self.settings.display.draw_on_vblank = vblank == "enabled";
+        }
+        let mut quirks = self.settings.quirks.unwrap_or_default();
+        for (key, _, quirk) in QUIRK_OPTIONS.iter() {
+            match callbacks.variable(key).as_deref() {
+                Some("enabled") => *quirk(&mut quirks) = true,
+                Some("disabled") => *quirk(&mut quirks) = false,
+                _ => {}
+            }
+        }
+        self.interpreter.set_quirks(quirks);
+    }
+}
+
+/// Hands the frames of the interpreter to the libretro frontend, one for each call
+/// of `retro_run`.
+struct RetroFrontend {
+    callbacks: Callbacks,
+    palette: Palette,
+    buzzer: Buzzer,
+    buzzer_changes: Vec<(f32, bool)>,
+    samples: Vec<f32>,
+    audio: Vec<i16>,
+    pixels: Vec<u32>,
+    buttons_pressed: [bool; KEYPAD_BUTTONS.len()],
+    frames_presented: u64,
+}
+
+impl RetroFrontend {
+    fn new(settings: &Settings) -> Self {
+        let samples_per_frame = (AUDIO_SAMPLE_RATE as u32 / DEFAULT_REFRESH_RATE) as usize;
+        RetroFrontend {
+            callbacks: *CALLBACKS.lock().unwrap(),
+            palette: settings.display.palette.clone().unwrap_or_default(),
+            buzzer: Buzzer::new(AUDIO_SAMPLE_RATE, settings.audio.tone()),
+            buzzer_changes: Vec::new(),
+            samples: vec![0.0; samples_per_frame],
+            audio: vec![0; samples_per_frame * 2],
+            pixels: vec![0; SCREEN_X * SCREEN_Y],
+            buttons_pressed: [false; KEYPAD_BUTTONS.len()],
+            frames_presented: 0,
+        }
+    }
+}
+
+impl Frontend for RetroFrontend {
+    fn present(
+        &mut self,
+        framebuffer: &Framebuffer,
+        _dirty: Option<DirtyRect>,
+        _advanced: bool,
+    ) -> Result<(), String> {
+        let background = xrgb(self.palette.background());
+        let foreground = xrgb(self.palette.foreground());
+        for (pixel, on) in self
+            .pixels
+            .iter_mut()
+            .zip(framebuffer.pixel_rows().flatten())
+        {
+            *pixel = if on { foreground } else { background };
+        }
+        if let Some(video_refresh) = self.callbacks.video_refresh {
+            unsafe {
+                video_refresh(
+                    self.pixels.as_ptr() as *const c_void,
+                    SCREEN_X as c_uint,
+                    SCREEN_Y as c_uint,
+                    SCREEN_X * 4,
+                )
+            };
+        }
+
+        self.buzzer.render(&mut self.samples, &self.buzzer_changes);
+        for (frame, sample) in self.audio.chunks_mut(2).zip(&self.samples) {
+            let sample = (sample * i16::MAX as f32) as i16;
+            frame[0] = sample;
+            frame[1] = sample;
+        }
+        if let Some(audio_sample_batch) = self.callbacks.audio_sample_batch {
+            unsafe { audio_sample_batch(self.audio.as_ptr(), self.samples.len()) };
+        }
+
+        self.frames_presented += 1;
+        Ok(())
+    }
+
+    fn set_buzzer(&mut self, changes: &[(f32, bool)]) {
+        self.buzzer_changes = changes.to_vec();
+    }
+
+    fn poll_input(&mut self) -> Result<Vec<(Action, bool)>, String> {
+        let (input_poll, input_state) =
+            match (self.callbacks.input_poll, self.callbacks.input_state) {
+                (Some(input_poll), Some(input_state)) => (input_poll, input_state),
+                _ => return Ok(Vec::new()),
+            };
+        unsafe { input_poll() };
+
+        let mut actions = Vec::new();
+        for ((button, key, _), was_pressed) in
+            KEYPAD_BUTTONS.iter().zip(self.buttons_pressed.iter_mut())
+        {
+            let pressed = unsafe { input_state(0, RETRO_DEVICE_JOYPAD, 0, *button) } != 0;
+            if pressed != *was_pressed {
+                actions.push((Action::Keypad(*key), pressed));
+                *was_pressed = pressed;
+            }
+        }
+        Ok(actions)
+    }
+
+    /// Every call of `retro_run` runs one frame.
+    fn wall_time(&self) -> u128 {
+        (self.frames_presented as u128 + 1) * FRAME_MICROS
+    }
+}
+
+/// The colour as 0RGB pixel.
+fn xrgb(color: Rgb) -> u32 {
+    (color.0 as u32) << 16 | (color.1 as u32) << 8 | color.2 as u32
+}
+
+/// Keys and values of the core options, as expected by `RETRO_ENVIRONMENT_SET_VARIABLES`.
+/// The first value of each option is its default.
+fn options() -> &'static [(CString, CString)] {
+    static OPTIONS: OnceLock<Vec<(CString, CString)>> = OnceLock::new();
+    OPTIONS.get_or_init(|| {
+        let palettes = Palette::preset_names().collect::<Vec<_>>().join("|");
+        vec![
+            (
+                SPEED_OPTION,
+                format!(
+                    "Speed (multiple of the COSMAC VIP); {}",
+                    SPEED_OPTIONS.join("|")
+                ),
+            ),
+            (PALETTE_OPTION, format!("Colours; auto|{}", palettes)),
+            (
+                VBLANK_OPTION,
+                "Draw only on vertical blank; disabled|enabled".to_string(),
+            ),
+        ]
+        .into_iter()
+        .chain(
+            QUIRK_OPTIONS.iter().map(|(key, description, _)| {
+                (*key, format!("{}; auto|disabled|enabled", description))
+            }),
+        )
+        .map(|(key, value)| {
+            (
+                CString::new(key).unwrap(),
+                CString::new(value).expect("Core option with NUL byte"),
+            )
+        })
+        .collect()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    static VERSION: OnceLock<CString> = OnceLock::new();
+    let version = VERSION.get_or_init(|| CString::new(env!("CARGO_PKG_VERSION")).unwrap());
+    *info = SystemInfo {
+        library_name: b"CHIP-8\0".as_ptr() as *const c_char,
+        library_version: version.as_ptr(),
+        valid_extensions: b"ch8|c8|gif\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: SCREEN_X as c_uint,
+            base_height: SCREEN_Y as c_uint,
+            max_width: SCREEN_X as c_uint,
+            max_height: SCREEN_Y as c_uint,
+            aspect_ratio: SCREEN_X as f32 / SCREEN_Y as f32,
+        },
+        timing: SystemTiming {
+            fps: DEFAULT_REFRESH_RATE as f64,
+            sample_rate: AUDIO_SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: EnvironmentFn) {
+    let mut callbacks = CALLBACKS.lock().unwrap();
+    callbacks.environment = Some(environment);
+
+    let mut variables = options()
+        .iter()
+        .map(|(key, value)| Variable {
+            key: key.as_ptr(),
+            value: value.as_ptr(),
+        })
+        .collect::<Vec<_>>();
+    variables.push(Variable {
+        key: ptr::null(),
+        value: ptr::null(),
+    });
+    callbacks.environment(
+        RETRO_ENVIRONMENT_SET_VARIABLES,
+        variables.as_mut_ptr() as *mut c_void,
+    );
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(video_refresh);
+}
+
+/// Audio is only sent in batches.
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(audio_sample_batch);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(input_poll);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(input_state);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+    let data = slice::from_raw_parts((*game).data as *const u8, (*game).size);
+    let mut interpreter = Interpreter::new();
+    let rom = match Rom::from_bytes(data.to_vec()) {
+        Ok(rom) => rom,
+        Err(e) => {
+            warn!("{}", e);
+            return false;
+        }
+    };
+    if let Err(e) = interpreter.load_program_bytes(&rom.program) {
+        warn!("{}", e);
+        return false;
+    }
+
+    let callbacks = *CALLBACKS.lock().unwrap();
+    let mut pixel_format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !callbacks.environment(
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+        &mut pixel_format as *mut c_uint as *mut c_void,
+    ) {
+        warn!("The frontend does not support XRGB8888 pixels");
+        return false;
+    }
+    let descriptions = KEYPAD_BUTTONS
+        .iter()
+        .map(|(_, _, description)| CString::new(*description).unwrap())
+        .collect::<Vec<_>>();
+    let mut descriptors = KEYPAD_BUTTONS
+        .iter()
+        .zip(descriptions.iter())
+        .map(|((button, _, _), description)| InputDescriptor {
+            port: 0,
+            device: RETRO_DEVICE_JOYPAD,
+            index: 0,
+            id: *button,
+            description: description.as_ptr(),
+        })
+        .collect::<Vec<_>>();
+    descriptors.push(InputDescriptor {
+        port: 0,
+        device: 0,
+        index: 0,
+        id: 0,
+        description: ptr::null(),
+    });
+    callbacks.environment(
+        RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS,
+        descriptors.as_mut_ptr() as *mut c_void,
+    );
+
+    let mut settings = Settings::default();
+    settings.database.directory = callbacks
+        .system_directory()
+        .map(|directory| directory.join(LIBRETRO_DATABASE_DIRECTORY))
+        .filter(|directory| directory.is_dir());
+    rom.apply(&mut settings);
+    if let Some(rom_info) = RomDatabase::load(&settings).lookup(&rom.program) {
+        rom_info.apply(&mut settings);
+    }
+    if settings.stack.limit().is_none() {
+        settings.stack.depth = LIBRETRO_MAX_STACK_DEPTH;
+    }
+    interpreter.set_quirks(settings.quirks.unwrap_or_default());
+    interpreter.set_stack_config(settings.stack);
+    let mut core = Core {
+        emulator: Emulator::new(&interpreter, false),
+        interpreter,
+        frontend: RetroFrontend::new(&settings),
+        settings,
+        stopped: false,
+    };
+    core.read_options(&callbacks);
+    *CORE.lock().unwrap() = Some(core);
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const GameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.interpreter.reset();
+        core.stopped = false;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = *CALLBACKS.lock().unwrap();
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        if callbacks.variables_updated() {
+            core.read_options(&callbacks);
+        }
+        core.frontend.callbacks = callbacks;
+        if core.stopped {
+            core.present_stopped();
+            return;
+        }
+        if let Err(e) =
+            core.emulator
+                .frame(&mut core.interpreter, &mut core.frontend, &core.settings)
+        {
+            // The frame was not presented, so the last state of the display is shown
+            // while the frontend shuts the core down.
+            warn!("{}", e);
+            core.stopped = true;
+            core.present_stopped();
+            callbacks.environment(RETRO_ENVIRONMENT_SHUTDOWN, ptr::null_mut());
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    match CORE.lock().unwrap().as_ref() {
+        Some(core) => core.interpreter.state_size(),
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    match CORE.lock().unwrap().as_ref() {
+        Some(core) if size >= core.interpreter.state_size() => {
+            let state = core.interpreter.save_state();
+            ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+            true
+        }
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    match CORE.lock().unwrap().as_mut() {
+        Some(core) => {
+            let state = slice::from_raw_parts(data as *const u8, size);
+            match core.interpreter.load_state(state) {
+                Ok(()) => {
+                    core.stopped = false;
+                    true
+                }
+                Err(e) => {
+                    warn!("{}", e);
+                    false
+                }
+            }
+        }
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// The memory of the interpreter is only accessible through save states.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
@@ -1,5 +1,10 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use log::{LevelFilter, Metadata, Record};
 
+/// Whether the terminal is in raw mode, where a new line does not return the cursor.
+static RAW_TERMINAL: AtomicBool = AtomicBool::new(false);
+
 struct StdoutLogger {
     log_level: LevelFilter,
 }
@@ -17,12 +22,21 @@ impl log::Log for StdoutLogger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            println!("{}", record.args());
+            if RAW_TERMINAL.load(Ordering::Relaxed) {
+                print!("{}\r\n", record.args());
+            } else {
+                println!("{}", record.args());
+            }
         }
     }
     fn flush(&self) {}
 }
 
+/// Switch line endings for a terminal in raw mode on or off.
+pub fn set_raw_terminal(raw: bool) {
+    RAW_TERMINAL.store(raw, Ordering::Relaxed);
+}
+
 pub fn init(level: LevelFilter) {
     log::set_boxed_logger(Box::new(StdoutLogger::new(level)))
         .map(|()| log::set_max_level(level))
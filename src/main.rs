@@ -1,38 +1,235 @@
-use std::fs::File;
+use std::convert::TryFrom;
+use std::path::Path;
 
-use crate::interpreter::Interpreter;
+use chip8::database::RomDatabase;
+use chip8::interpreter::Interpreter;
+use chip8::keymap::Keymap;
+use chip8::settings::Settings;
+use chip8::{headless, logger, rom, terminal};
 use clap::{App, Arg};
 use log::LevelFilter;
 
-mod config;
-mod instruction;
-mod interpreter;
-mod logger;
-mod sdl2;
-
 fn main() {
     let matches = App::new("Chip-8 Interpreter")
         .version("0.1")
         .author("Alex Friesenhahn")
         .arg(Arg::with_name("filename")
-            .help("the file to load")
-            .required(true)
+            .help("the ROM to load: a file, an Octo cartridge (.gif), - for standard input, a .gz file, or a .zip archive, optionally followed by the name of a ROM in it (roms.zip/pong.ch8); without it, the window shows the launcher to pick one")
+            .required(false)
             .takes_value(true))
         .arg(Arg::with_name("verbosity")
-            .help("the verbosity of the system out; debug logs out all executed instructions, trace adds logs of all register and memory accesses")
+            .help("the verbosity of the system out; debug logs out all executed instructions and trace the registers they change, if built with the observer feature")
             .short("v")
             .required(false)
             .possible_values(&["info", "debug", "trace"])
             .default_value("info"))
         .arg(Arg::with_name("step_mode")
-            .help("If step mode is enabled, the interpreter processes one instruction each time the SPACE key is pressed")
+            .help("Start in step mode, where the interpreter processes one instruction each time the SPACE key is pressed")
             .short("s")
             .required(false)
             .takes_value(false)
         )
+        .arg(Arg::with_name("speed")
+            .help("the speed of the instructions, as multiple of their timing on the COSMAC VIP (default 1); the timers always run at 60 Hz")
+            .long("speed")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("instructions_per_frame")
+            .help("run this many instructions per frame, all taking the same time, instead of timing them like the COSMAC VIP")
+            .long("ipf")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("program_start")
+            .help("the address that the program is loaded at, e.g. 0x600 for ETI-660 programs (default 0x200)")
+            .long("program-start")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("entry_point")
+            .help("the address that the program starts running at, if it is not the address it is loaded at")
+            .long("entry-point")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("font_address")
+            .help("the address of the font in memory, e.g. 0x50 (default 0)")
+            .long("font-address")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("memory_size")
+            .help("the size of the memory in bytes, at most 65536 (default 4096)")
+            .long("memory-size")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("stack_depth")
+            .help("the number of return addresses that the call stack holds, e.g. 12 for COSMAC VIP programs, or unlimited (default 16)")
+            .long("stack-depth")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("stack_fault")
+            .help("what happens when a program calls a subroutine with the stack full or returns with it empty: stop with an error, wrap the stack pointer around, or break into step mode")
+            .long("stack-fault")
+            .required(false)
+            .possible_values(&["error", "wrap", "break"])
+            .takes_value(true))
+        .arg(Arg::with_name("font")
+            .help("the font of the digits; either the name of a built-in font (chip48, vip, dream6800, eti660, schip, octo), or a file with the 80 bytes of a custom font, optionally followed by a big font of 100 or 160 bytes")
+            .long("font")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("fast_forward")
+            .help("the speed while the fast-forward key is held, as multiple of the normal speed (default 4)")
+            .long("fast-forward")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("keymap")
+            .help("TOML file with the key bindings of the keypad and the interpreter controls")
+            .long("keymap")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("rom_directory")
+            .help("the directory whose ROMs the launcher lists")
+            .long("rom-dir")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("database_directory")
+            .help("a directory with programs.json and sha1-hashes.json of the chip-8-database, to look up ROMs in instead of the built-in copy")
+            .long("database-dir")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("no_database")
+            .help("do not look up ROMs in the ROM database for their quirks, speed, colours and keys")
+            .long("no-database")
+            .required(false)
+            .takes_value(false))
+        .arg(Arg::with_name("watch")
+            .help("load the ROM again in the window whenever its file changes")
+            .long("watch")
+            .required(false)
+            .takes_value(false))
+        .arg(Arg::with_name("keep_state")
+            .help("with --watch, let the changed program continue from the current state where it can, instead of starting it again")
+            .long("keep-state")
+            .required(false)
+            .takes_value(false))
+        .arg(Arg::with_name("config")
+            .help("TOML file with the settings of the interpreter")
+            .long("config")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("palette")
+            .help("the colours of the display; either the name of a built-in palette (classic, vip, octo, amber, green, lcd), or two or four comma separated hex colours, e.g. #000000,#FFFFFF")
+            .long("palette")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("filter")
+            .help("filter against flicker: none, decay (pixels fade out; strength is the brightness kept per frame, e.g. decay=0.6) or blend (pixels are averaged over frames; strength is the number of frames, e.g. blend=3)")
+            .long("filter")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("draw_on_vblank")
+            .help("only show the display once sprites have been redrawn, instead of on every frame")
+            .long("vblank")
+            .required(false)
+            .takes_value(false))
+        .arg(Arg::with_name("scale")
+            .help("the size of a CHIP-8 pixel in the window at start; without it, the window opens with the size it had when it was last closed")
+            .long("scale")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("fullscreen")
+            .help("start in fullscreen")
+            .long("fullscreen")
+            .required(false)
+            .takes_value(false))
+        .arg(Arg::with_name("pixel_aspect")
+            .help("the shape of a CHIP-8 pixel as ratio of width to height, e.g. 2:3")
+            .long("aspect")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("effect")
+            .help("draw dark lines between the rows (scanlines) or rows and columns (grid) of pixels")
+            .long("effect")
+            .required(false)
+            .possible_values(&["none", "scanlines", "grid"])
+            .takes_value(true))
+        .arg(Arg::with_name("screenshot_directory")
+            .help("the directory that screenshots are saved in")
+            .long("screenshot-dir")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("screenshot_scale")
+            .help("the size of a CHIP-8 pixel in screenshots")
+            .long("screenshot-scale")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("recording_directory")
+            .help("the directory that recordings are saved in")
+            .long("record-dir")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("recording_format")
+            .help("the file format of recordings; gif for short clips without sound, y4m for an uncompressed video with the sound in a separate WAV file")
+            .long("record-format")
+            .required(false)
+            .possible_values(&["gif", "y4m"])
+            .takes_value(true))
+        .arg(Arg::with_name("recording_scale")
+            .help("the size of a CHIP-8 pixel in recordings, from 1 to 1023")
+            .long("record-scale")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("tone")
+            .help("the frequency of the buzzer tone in Hz, below 22050")
+            .long("tone")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("waveform")
+            .help("the waveform of the buzzer tone")
+            .long("waveform")
+            .required(false)
+            .possible_values(&["square", "sine", "triangle", "noise"])
+            .takes_value(true))
+        .arg(Arg::with_name("volume")
+            .help("the volume of the buzzer, from 0 to 1")
+            .long("volume")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("mute")
+            .help("turn off the sound of the buzzer")
+            .long("mute")
+            .required(false)
+            .takes_value(false))
+        .arg(Arg::with_name("headless")
+            .help("run the given number of frames without a window, as fast as possible")
+            .long("headless")
+            .required(false)
+            .takes_value(true)
+            .value_name("FRAMES"))
+        .arg(Arg::with_name("terminal")
+            .help("run in the terminal instead of a window (always, if built without the sdl feature)")
+            .long("terminal")
+            .required(false)
+            .conflicts_with("headless")
+            .takes_value(false))
+        .arg(Arg::with_name("terminal_graphics")
+            .help("how the display is drawn in the terminal")
+            .long("terminal-graphics")
+            .required(false)
+            .possible_values(&["auto", "halfblock", "braille", "sixel", "kitty"])
+            .takes_value(true))
+        .arg(Arg::with_name("key_hold")
+            .help("in the terminal, the time in seconds after which a key counts as released unless it is repeated (default 0.2)")
+            .long("key-hold")
+            .required(false)
+            .takes_value(true))
+        .arg(Arg::with_name("wav")
+            .help("in a headless run, write the sound of the buzzer into this WAV file")
+            .long("wav")
+            .required(false)
+            .requires("headless")
+            .takes_value(true))
     .get_matches();
 
-    let file_name = matches.value_of("filename").unwrap();
+    let rom = matches.value_of("filename").map(Path::new);
     let log_level = match matches.value_of("verbosity").unwrap() {
         "info" => LevelFilter::Info,
         "debug" => LevelFilter::Debug,
@@ -43,7 +240,184 @@ fn main() {
 
     logger::init(log_level);
 
+    let keymap_file = matches.value_of("keymap").map(Path::new);
+
+    let mut settings = match matches.value_of("config") {
+        Some(settings_file) => {
+            Settings::from_file(Path::new(settings_file)).expect("Could not load settings")
+        }
+        None => Settings::default(),
+    };
+    if let Some(palette) = matches.value_of("palette") {
+        settings.display.palette = Some(palette.parse().expect("Invalid palette"));
+    }
+    if let Some(filter) = matches.value_of("filter") {
+        settings.display.filter = filter.parse().expect("Invalid filter");
+    }
+    if matches.is_present("draw_on_vblank") {
+        settings.display.draw_on_vblank = true;
+    }
+    if let Some(speed) = matches.value_of("speed") {
+        settings.emulation.speed = speed.parse().expect("Invalid speed");
+    }
+    if let Some(instructions) = matches.value_of("instructions_per_frame") {
+        settings.emulation.instructions_per_frame = Some(
+            instructions
+                .parse()
+                .expect("Invalid instructions per frame"),
+        );
+    }
+    if let Some(address) = matches.value_of("program_start") {
+        settings.memory.program_start = parse_address(address).expect("Invalid program start");
+    }
+    if let Some(address) = matches.value_of("entry_point") {
+        settings.memory.entry_point = Some(parse_address(address).expect("Invalid entry point"));
+    }
+    if let Some(address) = matches.value_of("font_address") {
+        settings.memory.font_address = parse_address(address).expect("Invalid font address");
+    }
+    if let Some(size) = matches.value_of("memory_size") {
+        settings.memory.size = parse_number(size).expect("Invalid memory size");
+    }
+    if let Some(depth) = matches.value_of("stack_depth") {
+        settings.stack.depth = match depth {
+            "unlimited" => 0,
+            depth => parse_number(depth).expect("Invalid stack depth"),
+        };
+    }
+    if let Some(action) = matches.value_of("stack_fault") {
+        settings.stack.on_fault = action.parse().expect("Invalid stack fault action");
+    }
+    if let Some(font) = matches.value_of("font") {
+        match font.parse() {
+            Ok(set) => settings.font.set = set,
+            Err(_) => settings.font.file = Some(font.into()),
+        }
+    }
+    if let Some(fast_forward) = matches.value_of("fast_forward") {
+        settings.emulation.fast_forward = fast_forward.parse().expect("Invalid fast-forward speed");
+    }
+    if let Some(scale) = matches.value_of("scale") {
+        settings.window.scale = Some(scale.parse().expect("Invalid scale"));
+    }
+    if matches.is_present("fullscreen") {
+        settings.window.fullscreen = Some(true);
+    }
+    if let Some(aspect) = matches.value_of("pixel_aspect") {
+        settings.window.pixel_aspect = aspect.parse().expect("Invalid pixel aspect ratio");
+    }
+    if let Some(effect) = matches.value_of("effect") {
+        settings.window.effect = effect.parse().expect("Invalid effect");
+    }
+    if let Some(directory) = matches.value_of("screenshot_directory") {
+        settings.screenshot.directory = directory.into();
+    }
+    if let Some(scale) = matches.value_of("screenshot_scale") {
+        settings.screenshot.scale = scale.parse().expect("Invalid screenshot scale");
+    }
+    if let Some(frequency) = matches.value_of("tone") {
+        settings.audio.frequency = frequency.parse().expect("Invalid tone frequency");
+    }
+    if let Some(waveform) = matches.value_of("waveform") {
+        settings.audio.waveform = waveform.parse().expect("Invalid waveform");
+    }
+    if let Some(volume) = matches.value_of("volume") {
+        settings.audio.volume = volume.parse().expect("Invalid volume");
+    }
+    if matches.is_present("mute") {
+        settings.audio.mute = true;
+    }
+    if let Some(directory) = matches.value_of("recording_directory") {
+        settings.recording.directory = directory.into();
+    }
+    if let Some(format) = matches.value_of("recording_format") {
+        settings.recording.format = format.parse().expect("Invalid recording format");
+    }
+    if let Some(scale) = matches.value_of("recording_scale") {
+        settings.recording.scale = scale.parse().expect("Invalid recording scale");
+    }
+    if let Some(graphics) = matches.value_of("terminal_graphics") {
+        settings.terminal.graphics = graphics.parse().expect("Invalid terminal graphics");
+    }
+    if let Some(key_hold) = matches.value_of("key_hold") {
+        settings.terminal.key_hold = key_hold.parse().expect("Invalid key hold time");
+    }
+    if let Some(directory) = matches.value_of("rom_directory") {
+        settings.launcher.directory = directory.into();
+    }
+    if let Some(directory) = matches.value_of("database_directory") {
+        settings.database.directory = Some(directory.into());
+    }
+    if matches.is_present("no_database") {
+        settings.database.enabled = false;
+    }
+    if matches.is_present("watch") {
+        settings.watch.enabled = true;
+    }
+    if matches.is_present("keep_state") {
+        settings.watch.keep_state = true;
+    }
+
+    settings.validate().expect("Invalid settings");
+
+    #[cfg(feature = "sdl")]
+    {
+        if !matches.is_present("headless") && !matches.is_present("terminal") {
+            chip8::sdl2::run(rom, keymap_file, &settings, step_mode).expect("Error!");
+            return;
+        }
+    }
+
+    // Only the window has a launcher to pick the ROM in.
+    let rom = rom.expect("A ROM file is required without a window");
+    let loaded = rom::load(rom, &settings.memory).expect("Could not read ROM");
+    loaded.apply(&mut settings);
+    let rom_info = RomDatabase::load(&settings).lookup(&loaded.program);
+    if let Some(rom_info) = &rom_info {
+        rom_info.apply(&mut settings);
+    }
     let mut interpreter = Interpreter::new();
-    interpreter.load_program_file(&mut File::open(file_name).expect("Could not open file"));
-    sdl2::for_interpreter(&mut interpreter, step_mode).expect("Error!");
+    interpreter
+        .set_memory_map(settings.memory)
+        .expect("Invalid memory map");
+    interpreter
+        .set_font(settings.font.font().expect("Could not load font"))
+        .expect("Invalid font");
+    interpreter
+        .load_program_bytes(&loaded.program)
+        .expect("Could not load ROM");
+    interpreter.set_quirks(settings.quirks.unwrap_or_default());
+    interpreter.set_stack_config(settings.stack);
+    match matches.value_of("headless") {
+        Some(frames) => {
+            let frames = frames.parse().expect("Invalid number of frames");
+            let wav_file = matches.value_of("wav").map(Path::new);
+            headless::run(&mut interpreter, &settings, frames, wav_file).expect("Error!");
+        }
+        None => {
+            let keymap =
+                Keymap::for_rom(keymap_file, rom, rom_info.as_ref().map(|info| &info.keys))
+                    .expect("Could not load keymap");
+            terminal::for_interpreter(&mut interpreter, &keymap, &settings, step_mode)
+                .expect("Error!")
+        }
+    }
+}
+
+/// Parse a number, in hex if it starts with `0x`.
+fn parse_number(value: &str) -> Result<usize, String> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|e| format!("{}: {}", value, e))
+}
+
+/// Parse an address in memory, in hex if it starts with `0x`.
+fn parse_address(value: &str) -> Result<u16, String> {
+    let address = parse_number(value)?;
+    u16::try_from(address).map_err(|_| format!("{} is not a 16-bit address", value))
 }
@@ -0,0 +1,218 @@
+//! Programs and options of Octo (https://github.com/JohnEarnest/Octo).
+//!
+//! Octo shares programs as cartridges: GIF images whose pixels carry a payload in
+//! the low four bits of their colour indices, two pixels per byte with the high half
+//! first, through all frames. The payload is its length as 32-bit big-endian number,
+//! followed by JSON with the `options` of Octo and the `program`, which is usually
+//! the source code, assembled on loading (see `assembler`). Octo options can also be
+//! given for a plain ROM in a file beside it, e.g. `game.octo.json` for
+//! `game.ch8`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::Deserialize;
+
+use crate::interpreter::{FontSet, Quirks};
+use crate::palette::Palette;
+use crate::settings::Settings;
+
+mod assembler;
+
+pub use assembler::assemble;
+
+/// The signature that GIF images start with, followed by the version.
+const GIF_SIGNATURE: &[u8] = b"GIF8";
+/// The extension of the options file beside a ROM, instead of its own extension.
+const OPTIONS_EXTENSION: &str = "octo.json";
+
+/// The options of Octo that this interpreter supports. Options that are not given
+/// leave the settings unchanged.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OctoOptions {
+    /// The instructions per frame.
+    pub tickrate: Option<u32>,
+    pub background_color: Option<String>,
+    /// The colour of the pixels of the first bitplane.
+    pub fill_color: Option<String>,
+    /// The colour of the pixels of the second bitplane.
+    pub fill_color2: Option<String>,
+    /// The colour of the pixels of both bitplanes.
+    pub blend_color: Option<String>,
+    pub shift_quirks: Option<bool>,
+    pub load_store_quirks: Option<bool>,
+    pub clip_quirks: Option<bool>,
+    pub jump_quirks: Option<bool>,
+    pub v_blank_quirks: Option<bool>,
+    pub logic_quirks: Option<bool>,
+    /// The name of the font, e.g. `vip`.
+    pub font_style: Option<String>,
+}
+
+impl OctoOptions {
+    /// Read the options from a JSON file.
+    pub fn from_file(path: &Path) -> Result<OctoOptions, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Could not parse Octo options {}: {}", path.display(), e))
+    }
+
+    /// Read the options file beside the ROM, if there is one.
+    pub fn beside(rom: &Path) -> Option<Result<OctoOptions, String>> {
+        let path = options_file(rom);
+        if path.is_file() {
+            Some(OctoOptions::from_file(&path))
+        } else {
+            None
+        }
+    }
+
+    /// The quirks that the options set, based on those of Octo, which has none of
+    /// them by default. Nothing if no quirk is set.
+    pub fn quirks(&self) -> Option<Quirks> {
+        let options = [
+            self.shift_quirks,
+            self.load_store_quirks,
+            self.clip_quirks,
+            self.jump_quirks,
+            self.v_blank_quirks,
+            self.logic_quirks,
+        ];
+        if options.iter().all(Option::is_none) {
+            return None;
+        }
+        Some(Quirks {
+            shift: self.shift_quirks.unwrap_or(false),
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: self.load_store_quirks.unwrap_or(false),
+            // Octo clips sprites with the quirk, and wraps them without it.
+            wrap: !self.clip_quirks.unwrap_or(false),
+            jump: self.jump_quirks.unwrap_or(false),
+            vblank: self.v_blank_quirks.unwrap_or(false),
+            logic: self.logic_quirks.unwrap_or(false),
+        })
+    }
+
+    /// The colours of the options as palette, if all four or the first two of them are set.
+    pub fn palette(&self) -> Option<Result<Palette, String>> {
+        let colors = [
+            &self.background_color,
+            &self.fill_color,
+            &self.fill_color2,
+            &self.blend_color,
+        ];
+        let colors = match colors {
+            [Some(background), Some(fill), Some(fill2), Some(blend)] => {
+                vec![background, fill, fill2, blend]
+            }
+            [Some(background), Some(fill), ..] => vec![background, fill],
+            _ => return None,
+        };
+        Some(
+            colors
+                .iter()
+                .map(|color| color.as_str())
+                .collect::<Vec<_>>()
+                .join(",")
+                .parse(),
+        )
+    }
+
+    /// Take the settings that are not set explicitly from the options: the quirks,
+    /// the instructions per frame, the colours and, while the font is the default
+    /// one, the font.
+    pub fn apply(&self, settings: &mut Settings) {
+        if settings.quirks.is_none() {
+            settings.quirks = self.quirks();
+        }
+        if settings.emulation.instructions_per_frame.is_none() {
+            settings.emulation.instructions_per_frame =
+                self.tickrate.filter(|tickrate| *tickrate > 0);
+        }
+        if settings.font.set == FontSet::default() && settings.font.file.is_none() {
+            match self.font_style.as_deref().map(str::parse) {
+                Some(Ok(set)) => settings.font.set = set,
+                Some(Err(e)) => warn!("Font of Octo options not supported: {}", e),
+                None => {}
+            }
+        }
+        if settings.display.palette.is_none() {
+            match self.palette() {
+                Some(Ok(palette)) => settings.display.palette = Some(palette),
+                Some(Err(e)) => warn!("Invalid colours in Octo options: {}", e),
+                None => {}
+            }
+        }
+    }
+}
+
+/// The options file beside the ROM.
+pub fn options_file(rom: &Path) -> PathBuf {
+    rom.with_extension(OPTIONS_EXTENSION)
+}
+
+/// Whether the bytes are a GIF image, which is taken to be an Octo cartridge.
+pub fn is_cartridge(bytes: &[u8]) -> bool {
+    bytes.starts_with(GIF_SIGNATURE)
+}
+
+/// The program and the options in the Octo cartridge.
+pub fn read_cartridge(image: &[u8]) -> Result<(Vec<u8>, OctoOptions), String> {
+    let error = |e: String| format!("Could not read Octo cartridge: {}", e);
+    let mut decode_options = gif::DecodeOptions::new();
+    decode_options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = decode_options
+        .read_info(image)
+        .map_err(|e| error(e.to_string()))?;
+    let mut halves = Vec::new();
+    while let Some(frame) = decoder
+        .read_next_frame()
+        .map_err(|e| error(e.to_string()))?
+    {
+        halves.extend(frame.buffer.iter().map(|index| index & 0xF));
+    }
+    let bytes = halves
+        .chunks_exact(2)
+        .map(|pair| pair[0] << 4 | pair[1])
+        .collect::<Vec<u8>>();
+
+    if bytes.len() < 4 {
+        return Err(error("no payload".to_string()));
+    }
+    let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    let payload = 4usize
+        .checked_add(length)
+        .and_then(|end| bytes.get(4..end))
+        .ok_or_else(|| error("the payload is cut off".to_string()))?;
+    // Octo stores the characters of the payload as single bytes.
+    let json = payload.iter().map(|byte| *byte as char).collect::<String>();
+    let cartridge: Cartridge = serde_json::from_str(&json).map_err(|e| error(e.to_string()))?;
+    match cartridge.program {
+        Program::Bytes(program) => Ok((program, cartridge.options)),
+        Program::Source(source) => {
+            let program = assemble(&source)
+                .map_err(|e| error(format!("could not assemble its source code: {}", e)))?;
+            Ok((program, cartridge.options))
+        }
+    }
+}
+
+/// The payload of an Octo cartridge.
+#[derive(Deserialize)]
+struct Cartridge {
+    #[serde(default)]
+    options: OctoOptions,
+    program: Program,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Program {
+    /// The assembled program.
+    Bytes(Vec<u8>),
+    /// The source code of the program in the Octo language.
+    Source(String),
+}
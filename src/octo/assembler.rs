@@ -0,0 +1,1165 @@
+//! An assembler for the Octo language, for cartridges that carry the source code of
+//! their program instead of the assembled bytes.
+//!
+//! It follows the Octo compiler: tokens are separated by whitespace, `#` starts a
+//! comment, programs start at 0x200 with a jump to the `main` label, and labels can
+//! be used before they are defined, except in `:calc` expressions, which are
+//! evaluated from right to left without operator precedence.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::f64::consts::{E, PI};
+
+use crate::config::DEFAULT_PROGRAM_START;
+
+/// The address that Octo programs are assembled for.
+const START: u32 = DEFAULT_PROGRAM_START as u32;
+
+/// Assemble Octo source code into the bytes of the program.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler::new(tokenize(source)?);
+    assembler
+        .run()
+        .map_err(|e| format!("line {}: {}", assembler.line, e))?;
+    Ok(assembler.rom)
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    /// Whether the token is a quoted string rather than a word.
+    string: bool,
+    line: usize,
+}
+
+impl Token {
+    fn is(&self, text: &str) -> bool {
+        !self.string && self.text == text
+    }
+}
+
+fn tokenize(source: &str) -> Result<VecDeque<Token>, String> {
+    let mut tokens = VecDeque::new();
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut chars = text.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c == '#' {
+                break;
+            } else if c == '"' {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.push(match chars.next() {
+                            Some('n') => '\n',
+                            Some('r') => '\r',
+                            Some('t') => '\t',
+                            Some('0') => '\0',
+                            Some(c) => c,
+                            None => return Err(format!("line {}: unterminated string", line)),
+                        }),
+                        Some(c) => string.push(c),
+                        None => return Err(format!("line {}: unterminated string", line)),
+                    }
+                }
+                tokens.push_back(Token {
+                    text: string,
+                    string: true,
+                    line,
+                });
+            } else {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek().filter(|c| !c.is_whitespace()) {
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push_back(Token {
+                    text: word,
+                    string: false,
+                    line,
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// A value in the program that refers to a label defined further on.
+#[derive(Copy, Clone)]
+enum Reference {
+    /// The 12-bit address in the instruction at the address.
+    Address,
+    /// A 16-bit address, e.g. after `i := long`.
+    Wide,
+    /// The byte of an instruction that `:unpack` sets to the nibble followed by the
+    /// highest four bits of the address.
+    Unpack(u8),
+    /// The highest byte of the address.
+    High,
+    /// The lowest byte of the address.
+    Low,
+}
+
+/// A block of control flow that is not closed yet.
+enum Block {
+    /// `if ... begin`, with the address of the jump past it.
+    Begin(u32),
+    /// `else`, with the address of the jump past it.
+    Else(u32),
+    /// `loop`, with its start and the addresses of the jumps out of it by `while`.
+    Loop(u32, Vec<u32>),
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+    calls: u32,
+}
+
+/// A value that is known, or the name of a label that is not defined yet.
+enum Value {
+    Known(f64),
+    Label(String),
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    /// The program, starting at 0x200.
+    rom: Vec<u8>,
+    /// The address of the next byte.
+    here: u32,
+    /// The line of the token read last.
+    line: usize,
+    labels: HashMap<String, u32>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    /// The bodies of the string modes, by name and character, with the position of
+    /// the character in the characters of the mode.
+    string_modes: HashMap<String, HashMap<char, (usize, Vec<Token>)>>,
+    /// The labels used before their definition: where, how, and the line for errors.
+    references: Vec<(u32, Reference, String, usize)>,
+    blocks: Vec<Block>,
+}
+
+impl Assembler {
+    fn new(tokens: VecDeque<Token>) -> Self {
+        let aliases = [
+            ("unpack-hi", 0x0),
+            ("unpack-lo", 0x1),
+            ("compare-temp", 0xF),
+        ]
+        .iter()
+        .map(|(name, register)| (name.to_string(), *register))
+        .collect();
+        Assembler {
+            tokens,
+            rom: Vec::new(),
+            here: START,
+            line: 1,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases,
+            macros: HashMap::new(),
+            string_modes: HashMap::new(),
+            references: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        self.reference(Reference::Address, "main".to_string());
+        self.instruction(0x1000)?;
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+        if let Some(block) = self.blocks.last() {
+            return Err(match block {
+                Block::Begin(_) | Block::Else(_) => "`begin` without `end`",
+                Block::Loop(..) => "`loop` without `again`",
+            }
+            .to_string());
+        }
+        self.resolve()
+    }
+
+    /// Fill in the labels that were used before their definition.
+    fn resolve(&mut self) -> Result<(), String> {
+        for (address, reference, name, line) in std::mem::take(&mut self.references) {
+            self.line = line;
+            let value = match self.labels.get(&name) {
+                Some(value) => *value,
+                None if name == "main" => return Err("the program has no `main` label".to_string()),
+                None => return Err(format!("undefined name {}", name)),
+            };
+            let index = (address - START) as usize;
+            match reference {
+                Reference::Address => {
+                    check_range(value as f64, 0.0, 0xFFF as f64, &name)?;
+                    self.rom[index] = self.rom[index] & 0xF0 | (value >> 8) as u8;
+                    self.rom[index + 1] = value as u8;
+                }
+                Reference::Wide => {
+                    check_range(value as f64, 0.0, 0xFFFF as f64, &name)?;
+                    self.rom[index] = (value >> 8) as u8;
+                    self.rom[index + 1] = value as u8;
+                }
+                Reference::Unpack(nibble) => {
+                    check_range(value as f64, 0.0, 0xFFF as f64, &name)?;
+                    self.rom[index] = nibble << 4 | (value >> 8) as u8;
+                }
+                Reference::High => self.rom[index] = (value >> 8) as u8,
+                Reference::Low => self.rom[index] = value as u8,
+            }
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .pop_front()
+            .ok_or_else(|| "unexpected end of the source".to_string())?;
+        self.line = token.line;
+        Ok(token)
+    }
+
+    fn next_word(&mut self) -> Result<String, String> {
+        let token = self.next()?;
+        if token.string {
+            return Err(format!("expected a name instead of \"{}\"", token.text));
+        }
+        Ok(token.text)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.is(text))
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if !token.is(text) {
+            return Err(format!("expected {} instead of {}", text, token.text));
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, byte: u8) -> Result<(), String> {
+        if self.here > 0xFFFF {
+            return Err("the program does not fit into 64 KB".to_string());
+        }
+        let index = (self.here - START) as usize;
+        if index >= self.rom.len() {
+            self.rom.resize(index + 1, 0);
+        }
+        self.rom[index] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn instruction(&mut self, instruction: u16) -> Result<(), String> {
+        self.byte((instruction >> 8) as u8)?;
+        self.byte(instruction as u8)
+    }
+
+    /// Remember that the label is used at the current address.
+    fn reference(&mut self, reference: Reference, name: String) {
+        self.references
+            .push((self.here, reference, name, self.line));
+    }
+
+    /// Set the 12-bit address of the jump at the address to the current address.
+    fn patch_jump(&mut self, address: u32) {
+        let index = (address - START) as usize;
+        self.rom[index] = self.rom[index] & 0xF0 | (self.here >> 8) as u8 & 0xF;
+        self.rom[index + 1] = self.here as u8;
+    }
+
+    fn define(&mut self, name: String, value: u32) -> Result<(), String> {
+        self.check_name(&name)?;
+        self.labels.insert(name, value);
+        Ok(())
+    }
+
+    fn check_name(&self, name: &str) -> Result<(), String> {
+        if parse_number(name).is_some() || register(name).is_some() {
+            return Err(format!("{} can not be used as a name", name));
+        }
+        if self.labels.contains_key(name) || self.constants.contains_key(name) {
+            return Err(format!("{} is already defined", name));
+        }
+        Ok(())
+    }
+
+    fn is_register(&self) -> bool {
+        self.tokens
+            .front()
+            .is_some_and(|token| !token.string && self.register_of(&token.text).is_some())
+    }
+
+    fn register_of(&self, name: &str) -> Option<u8> {
+        register(name).or_else(|| self.aliases.get(name).copied())
+    }
+
+    fn register(&mut self) -> Result<u16, String> {
+        let name = self.next_word()?;
+        self.register_of(&name)
+            .map(u16::from)
+            .ok_or_else(|| format!("expected a register instead of {}", name))
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let token = self.next()?;
+        if token.string {
+            return Err(format!("expected a value instead of \"{}\"", token.text));
+        }
+        if token.is("{") {
+            return self.calc().map(Value::Known);
+        }
+        if let Some(value) = self.known(&token.text) {
+            return Ok(Value::Known(value));
+        }
+        if self.register_of(&token.text).is_some() {
+            return Err(format!("expected a value instead of {}", token.text));
+        }
+        Ok(Value::Label(token.text))
+    }
+
+    fn known(&self, name: &str) -> Option<f64> {
+        parse_number(name)
+            .or_else(|| self.constants.get(name).copied())
+            .or_else(|| self.labels.get(name).map(|value| *value as f64))
+    }
+
+    fn known_value(&mut self) -> Result<f64, String> {
+        match self.value()? {
+            Value::Known(value) => Ok(value),
+            Value::Label(name) => Err(format!("undefined name {}", name)),
+        }
+    }
+
+    /// An 8-bit value, which may be negative.
+    fn short_value(&mut self) -> Result<u16, String> {
+        let value = self.known_value()?;
+        check_range(value, -128.0, 255.0, "the value")?;
+        Ok(value as i64 as u8 as u16)
+    }
+
+    /// A 4-bit value.
+    fn tiny_value(&mut self) -> Result<u16, String> {
+        let value = self.known_value()?;
+        check_range(value, 0.0, 15.0, "the value")?;
+        Ok(value as u16)
+    }
+
+    /// A 12-bit address, which may be a label that is defined later on.
+    fn address(&mut self) -> Result<u16, String> {
+        match self.value()? {
+            Value::Known(value) => {
+                check_range(value, 0.0, 0xFFF as f64, "the address")?;
+                Ok(value as u16)
+            }
+            Value::Label(name) => {
+                self.reference(Reference::Address, name);
+                Ok(0)
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        if token.string {
+            return Err(format!("unexpected string \"{}\"", token.text));
+        }
+        let text = token.text.as_str();
+        match text {
+            ":" => {
+                let name = self.next_word()?;
+                self.define(name, self.here)?;
+            }
+            ":next" => {
+                let name = self.next_word()?;
+                self.define(name, self.here + 1)?;
+            }
+            ":const" => {
+                let name = self.next_word()?;
+                let value = self.known_value()?;
+                self.check_name(&name)?;
+                self.constants.insert(name, value);
+            }
+            ":calc" => {
+                let name = self.next_word()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.check_name(&name)?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.next_word()?;
+                let register = if self.peek_is("{") {
+                    self.next()?;
+                    let value = self.calc()?;
+                    check_range(value, 0.0, 15.0, "the register")?;
+                    value as u16
+                } else {
+                    self.register()?
+                };
+                self.aliases.insert(name, register as u8);
+            }
+            ":byte" => match self.value()? {
+                Value::Known(value) => {
+                    check_range(value, -128.0, 255.0, "the byte")?;
+                    self.byte(value as i64 as u8)?;
+                }
+                Value::Label(name) => {
+                    self.reference(Reference::Low, name);
+                    self.byte(0)?;
+                }
+            },
+            ":pointer" => match self.value()? {
+                Value::Known(value) => {
+                    check_range(value, 0.0, 0xFFFF as f64, "the pointer")?;
+                    self.instruction(value as u16)?;
+                }
+                Value::Label(name) => {
+                    self.reference(Reference::Wide, name);
+                    self.instruction(0)?;
+                }
+            },
+            ":org" => {
+                let value = self.known_value()?;
+                check_range(value, START as f64, 0xFFFF as f64, "the address")?;
+                self.here = value as u32;
+            }
+            ":unpack" => self.unpack()?,
+            ":call" => {
+                let address = self.address()?;
+                self.instruction(0x2000 | address)?;
+            }
+            ":macro" => self.define_macro()?,
+            ":stringmode" => self.define_string_mode()?,
+            ":assert" => {
+                let message = match self.tokens.front() {
+                    Some(token) if token.string => Some(self.next()?.text),
+                    _ => None,
+                };
+                self.expect("{")?;
+                if self.calc()? == 0.0 {
+                    return Err(match message {
+                        Some(message) => format!("assertion failed: {}", message),
+                        None => "assertion failed".to_string(),
+                    });
+                }
+            }
+            ":breakpoint" | ":proto" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            "return" | ";" => self.instruction(0x00EE)?,
+            "clear" => self.instruction(0x00E0)?,
+            "scroll-right" => self.instruction(0x00FB)?,
+            "scroll-left" => self.instruction(0x00FC)?,
+            "exit" => self.instruction(0x00FD)?,
+            "lores" => self.instruction(0x00FE)?,
+            "hires" => self.instruction(0x00FF)?,
+            "audio" => self.instruction(0xF002)?,
+            "scroll-down" => {
+                let rows = self.tiny_value()?;
+                self.instruction(0x00C0 | rows)?;
+            }
+            "scroll-up" => {
+                let rows = self.tiny_value()?;
+                self.instruction(0x00D0 | rows)?;
+            }
+            "plane" => {
+                let plane = self.tiny_value()?;
+                self.instruction(0xF001 | plane << 8)?;
+            }
+            "bcd" => self.register_instruction(0xF033)?,
+            "saveflags" => self.register_instruction(0xF075)?,
+            "loadflags" => self.register_instruction(0xF085)?,
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek_is("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    let code = if text == "save" { 0x5002 } else { 0x5003 };
+                    self.instruction(code | x << 8 | y << 4)?;
+                } else {
+                    let code = if text == "save" { 0xF055 } else { 0xF065 };
+                    self.instruction(code | x << 8)?;
+                }
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let height = self.tiny_value()?;
+                self.instruction(0xD000 | x << 8 | y << 4 | height)?;
+            }
+            "jump" => {
+                let address = self.address()?;
+                self.instruction(0x1000 | address)?;
+            }
+            "jump0" => {
+                let address = self.address()?;
+                self.instruction(0xB000 | address)?;
+            }
+            "native" => {
+                let address = self.address()?;
+                self.instruction(address)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let code = match text {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.register_instruction(code)?;
+            }
+            "i" => self.index_statement()?,
+            "if" => {
+                let begin = self.peek_condition_end()?;
+                self.condition(begin)?;
+                self.next()?;
+                if begin {
+                    self.blocks.push(Block::Begin(self.here));
+                    self.instruction(0x1000)?;
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::Begin(jump)) => {
+                    self.blocks.push(Block::Else(self.here));
+                    self.instruction(0x1000)?;
+                    self.patch_jump(jump);
+                }
+                _ => return Err("`else` without `begin`".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::Begin(jump)) | Some(Block::Else(jump)) => self.patch_jump(jump),
+                _ => return Err("`end` without `begin`".to_string()),
+            },
+            "loop" => self.blocks.push(Block::Loop(self.here, Vec::new())),
+            "while" => {
+                self.condition(true)?;
+                let jump = self.here;
+                match self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop(_, whiles) => Some(whiles),
+                    _ => None,
+                }) {
+                    Some(whiles) => whiles.push(jump),
+                    None => return Err("`while` outside of a loop".to_string()),
+                }
+                self.instruction(0x1000)?;
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop(start, whiles)) => {
+                    self.instruction(0x1000 | start as u16)?;
+                    for jump in whiles {
+                        self.patch_jump(jump);
+                    }
+                }
+                _ => return Err("`again` without `loop`".to_string()),
+            },
+            _ if self.register_of(text).is_some() => {
+                let x = u16::from(self.register_of(text).unwrap_or(0));
+                self.register_statement(x)?;
+            }
+            _ if self.macros.contains_key(text) => self.expand_macro(text)?,
+            _ if self.string_modes.contains_key(text) => self.expand_string_mode(text)?,
+            _ => match parse_number(text) {
+                // Numbers on their own are data.
+                Some(value) => {
+                    check_range(value, -128.0, 255.0, "the byte")?;
+                    self.byte(value as i64 as u8)?;
+                }
+                // Any other name is a call of a subroutine.
+                None => {
+                    let address = match self.known(text) {
+                        Some(value) => {
+                            check_range(value, 0.0, 0xFFF as f64, text)?;
+                            value as u16
+                        }
+                        None => {
+                            self.reference(Reference::Address, token.text.clone());
+                            0
+                        }
+                    };
+                    self.instruction(0x2000 | address)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn register_instruction(&mut self, code: u16) -> Result<(), String> {
+        let x = self.register()?;
+        self.instruction(code | x << 8)
+    }
+
+    fn index_statement(&mut self) -> Result<(), String> {
+        let operator = self.next_word()?;
+        match operator.as_str() {
+            "+=" => self.register_instruction(0xF01E),
+            ":=" if self.peek_is("hex") => {
+                self.next()?;
+                self.register_instruction(0xF029)
+            }
+            ":=" if self.peek_is("bighex") => {
+                self.next()?;
+                self.register_instruction(0xF030)
+            }
+            ":=" if self.peek_is("long") => {
+                self.next()?;
+                self.instruction(0xF000)?;
+                match self.value()? {
+                    Value::Known(value) => {
+                        check_range(value, 0.0, 0xFFFF as f64, "the address")?;
+                        self.instruction(value as u16)
+                    }
+                    Value::Label(name) => {
+                        self.reference(Reference::Wide, name);
+                        self.instruction(0)
+                    }
+                }
+            }
+            ":=" => {
+                let address = self.address()?;
+                self.instruction(0xA000 | address)
+            }
+            _ => Err(format!("unknown operator i {}", operator)),
+        }
+    }
+
+    fn register_statement(&mut self, x: u16) -> Result<(), String> {
+        let operator = self.next_word()?;
+        if operator == ":=" {
+            let source = match self.tokens.front() {
+                Some(token) if token.is("key") => Some(0xF00A),
+                Some(token) if token.is("delay") => Some(0xF007),
+                Some(token) if token.is("random") => Some(0xC000),
+                _ => None,
+            };
+            if let Some(code) = source {
+                self.next()?;
+                let mask = if code == 0xC000 {
+                    self.short_value()?
+                } else {
+                    0
+                };
+                return self.instruction(code | x << 8 | mask);
+            }
+        }
+        let code = match operator.as_str() {
+            ":=" => 0x0,
+            "|=" => 0x1,
+            "&=" => 0x2,
+            "^=" => 0x3,
+            "+=" => 0x4,
+            "-=" => 0x5,
+            ">>=" => 0x6,
+            "=-" => 0x7,
+            "<<=" => 0xE,
+            _ => return Err(format!("unknown operator {}", operator)),
+        };
+        if self.is_register() {
+            let y = self.register()?;
+            return self.instruction(0x8000 | x << 8 | y << 4 | code);
+        }
+        let value = self.short_value()?;
+        match operator.as_str() {
+            ":=" => self.instruction(0x6000 | x << 8 | value),
+            "+=" => self.instruction(0x7000 | x << 8 | value),
+            "-=" => self.instruction(0x7000 | x << 8 | (0x100 - value) & 0xFF),
+            _ => Err(format!("{} needs a register on both sides", operator)),
+        }
+    }
+
+    /// Whether the condition after `if` is followed by `begin` rather than `then`.
+    fn peek_condition_end(&self) -> Result<bool, String> {
+        for token in &self.tokens {
+            if token.is("then") {
+                return Ok(false);
+            }
+            if token.is("begin") {
+                return Ok(true);
+            }
+        }
+        Err("`if` without `then` or `begin`".to_string())
+    }
+
+    /// Emit a condition that skips the next instruction unless it holds, or, when
+    /// negated, if it holds.
+    fn condition(&mut self, negated: bool) -> Result<(), String> {
+        let x = self.register()?;
+        let mut operator = self.next_word()?;
+        if negated {
+            operator = match operator.as_str() {
+                "==" => "!=",
+                "!=" => "==",
+                "<" => ">=",
+                ">" => "<=",
+                "<=" => ">",
+                ">=" => "<",
+                "key" => "-key",
+                "-key" => "key",
+                _ => return Err(format!("unknown comparison {}", operator)),
+            }
+            .to_string();
+        }
+        let temp = u16::from(self.aliases["compare-temp"]);
+        match operator.as_str() {
+            "key" => self.instruction(0xE0A1 | x << 8),
+            "-key" => self.instruction(0xE09E | x << 8),
+            "==" | "!=" if self.is_register() => {
+                let y = self.register()?;
+                let code = if operator == "==" { 0x9000 } else { 0x5000 };
+                self.instruction(code | x << 8 | y << 4)
+            }
+            "==" | "!=" => {
+                let value = self.short_value()?;
+                let code = if operator == "==" { 0x4000 } else { 0x3000 };
+                self.instruction(code | x << 8 | value)
+            }
+            "<" | ">" | "<=" | ">=" => {
+                if self.is_register() {
+                    let y = self.register()?;
+                    self.instruction(0x8000 | temp << 8 | y << 4)?;
+                } else {
+                    let value = self.short_value()?;
+                    self.instruction(0x6000 | temp << 8 | value)?;
+                }
+                let (subtract, skip) = match operator.as_str() {
+                    ">" => (0x5, 0x3001),
+                    "<" => (0x7, 0x3001),
+                    ">=" => (0x7, 0x4001),
+                    _ => (0x5, 0x4001),
+                };
+                self.instruction(0x8000 | temp << 8 | x << 4 | subtract)?;
+                self.instruction(skip | temp << 8)
+            }
+            _ => Err(format!("unknown comparison {}", operator)),
+        }
+    }
+
+    fn unpack(&mut self) -> Result<(), String> {
+        let long = self.peek_is("long");
+        let nibble = if long {
+            self.next()?;
+            0
+        } else {
+            self.tiny_value()? as u8
+        };
+        let high = u16::from(self.aliases["unpack-hi"]);
+        let low = u16::from(self.aliases["unpack-lo"]);
+        let (high_byte, low_byte) = match self.value()? {
+            Value::Known(value) => {
+                let max = if long { 0xFFFF } else { 0xFFF };
+                check_range(value, 0.0, max as f64, "the address")?;
+                let value = value as u16;
+                let high_byte = if long {
+                    value >> 8
+                } else {
+                    u16::from(nibble) << 4 | value >> 8
+                };
+                (high_byte, value & 0xFF)
+            }
+            Value::Label(name) => {
+                self.here += 1;
+                let reference = if long {
+                    Reference::High
+                } else {
+                    Reference::Unpack(nibble)
+                };
+                self.reference(reference, name.clone());
+                self.here += 2;
+                self.reference(Reference::Low, name);
+                self.here -= 3;
+                (0, 0)
+            }
+        };
+        self.instruction(0x6000 | high << 8 | high_byte)?;
+        self.instruction(0x6000 | low << 8 | low_byte)
+    }
+
+    /// The tokens up to the closing brace, after the opening one.
+    fn block(&mut self) -> Result<Vec<Token>, String> {
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next()?;
+            if token.is("{") {
+                depth += 1;
+            } else if token.is("}") {
+                if depth == 0 {
+                    return Ok(body);
+                }
+                depth -= 1;
+            }
+            body.push(token);
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.next_word()?;
+        let mut parameters = Vec::new();
+        loop {
+            let parameter = self.next_word()?;
+            if parameter == "{" {
+                break;
+            }
+            parameters.push(parameter);
+        }
+        let body = self.block()?;
+        self.macros.insert(
+            name,
+            Macro {
+                parameters,
+                body,
+                calls: 0,
+            },
+        );
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), String> {
+        let count = self.macros[name].parameters.len();
+        let mut arguments = Vec::with_capacity(count);
+        for _ in 0..count {
+            arguments.push(self.next()?);
+        }
+        let definition = self.macros.get_mut(name).expect("macro is defined");
+        let calls = Token {
+            text: definition.calls.to_string(),
+            string: false,
+            line: 0,
+        };
+        definition.calls += 1;
+        let line = self.line;
+        let body = definition
+            .body
+            .iter()
+            .map(|token| {
+                let replacement = if token.is("CALLS") {
+                    Some(&calls)
+                } else {
+                    definition
+                        .parameters
+                        .iter()
+                        .position(|parameter| token.is(parameter))
+                        .map(|index| &arguments[index])
+                };
+                Token {
+                    line,
+                    ..replacement.unwrap_or(token).clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        self.push_front(body);
+        Ok(())
+    }
+
+    fn define_string_mode(&mut self) -> Result<(), String> {
+        let name = self.next_word()?;
+        let alphabet = self.next()?;
+        if !alphabet.string {
+            return Err(format!(
+                "expected the characters of string mode {} as string",
+                name
+            ));
+        }
+        self.expect("{")?;
+        let body = self.block()?;
+        let modes = self.string_modes.entry(name).or_default();
+        for (position, c) in alphabet.text.chars().enumerate() {
+            modes.insert(c, (position, body.clone()));
+        }
+        Ok(())
+    }
+
+    fn expand_string_mode(&mut self, name: &str) -> Result<(), String> {
+        let text = self.next()?;
+        if !text.string {
+            return Err(format!("string mode {} needs a string", name));
+        }
+        let line = self.line;
+        let mut expanded = Vec::new();
+        for (index, c) in text.text.chars().enumerate() {
+            let (position, body) = self.string_modes[name]
+                .get(&c)
+                .ok_or_else(|| format!("string mode {} has no character {:?}", name, c))?;
+            let char_code = (c as u32).to_string();
+            let value = position.to_string();
+            let index = index.to_string();
+            expanded.extend(body.iter().map(|token| {
+                let text = match token.text.as_str() {
+                    "CHAR" if !token.string => char_code.clone(),
+                    "VALUE" if !token.string => value.clone(),
+                    "INDEX" if !token.string => index.clone(),
+                    _ => token.text.clone(),
+                };
+                Token {
+                    text,
+                    line,
+                    ..token.clone()
+                }
+            }));
+        }
+        self.push_front(expanded);
+        Ok(())
+    }
+
+    fn push_front(&mut self, tokens: Vec<Token>) {
+        for token in tokens.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+    }
+
+    /// Evaluate a `:calc` expression up to the closing brace, after the opening one.
+    fn calc(&mut self) -> Result<f64, String> {
+        let value = self.calc_expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn calc_expression(&mut self) -> Result<f64, String> {
+        let value = self.calc_term()?;
+        if self.peek_is("}") || self.peek_is(")") {
+            return Ok(value);
+        }
+        let operator = self.next_word()?;
+        let rest = self.calc_expression()?;
+        let (a, b) = (value.floor() as i64, rest.floor() as i64);
+        let truth = |holds: bool| if holds { 1.0 } else { 0.0 };
+        Ok(match operator.as_str() {
+            "+" => value + rest,
+            "-" => value - rest,
+            "*" => value * rest,
+            "/" => value / rest,
+            "%" => value % rest,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.checked_shl(b as u32).unwrap_or(0) as f64,
+            ">>" => a.checked_shr(b as u32).unwrap_or(0) as f64,
+            "pow" => value.powf(rest),
+            "min" => value.min(rest),
+            "max" => value.max(rest),
+            "<" => truth(value < rest),
+            "<=" => truth(value <= rest),
+            ">" => truth(value > rest),
+            ">=" => truth(value >= rest),
+            "==" => truth(value == rest),
+            "!=" => truth(value != rest),
+            _ => return Err(format!("unknown operator {} in expression", operator)),
+        })
+    }
+
+    fn calc_term(&mut self) -> Result<f64, String> {
+        let token = self.next_word()?;
+        let unary = |f: fn(f64) -> f64, this: &mut Self| this.calc_term().map(f);
+        match token.as_str() {
+            "(" => {
+                let value = self.calc_expression()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            "-" => unary(|x| -x, self),
+            "~" => unary(|x| !(x.floor() as i64) as f64, self),
+            "!" => unary(|x| if x == 0.0 { 1.0 } else { 0.0 }, self),
+            "sin" => unary(f64::sin, self),
+            "cos" => unary(f64::cos, self),
+            "tan" => unary(f64::tan, self),
+            "exp" => unary(f64::exp, self),
+            "log" => unary(f64::ln, self),
+            "abs" => unary(f64::abs, self),
+            "sqrt" => unary(f64::sqrt, self),
+            "sign" => unary(f64::signum, self),
+            "ceil" => unary(f64::ceil, self),
+            "floor" => unary(f64::floor, self),
+            "@" => {
+                let address = self.calc_term()?;
+                let index = address as i64 - START as i64;
+                Ok(usize::try_from(index)
+                    .ok()
+                    .and_then(|index| self.rom.get(index))
+                    .map_or(0.0, |byte| *byte as f64))
+            }
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(PI),
+            "E" => Ok(E),
+            _ => self
+                .known(&token)
+                .ok_or_else(|| format!("undefined name {} in expression", token)),
+        }
+    }
+}
+
+/// The number of register `v0` to `vf`.
+fn register(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => {
+            digit.to_digit(16).map(|digit| digit as u8)
+        }
+        _ => None,
+    }
+}
+
+/// A decimal, hexadecimal (`0x`) or binary (`0b`) number, which may be negative.
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn check_range(value: f64, min: f64, max: f64, what: &str) -> Result<(), String> {
+    if value < min || value > max {
+        return Err(format!(
+            "{} {} is not between {} and {}",
+            what, value, min, max
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble_body(source: &str) -> Vec<u8> {
+        let rom = assemble(&format!(": main\n{}", source)).unwrap();
+        assert_eq!(rom[..2], [0x12, 0x02]);
+        rom[2..].to_vec()
+    }
+
+    #[test]
+    fn jumps_to_main() {
+        let rom = assemble(": sub return\n: main sub loop again").unwrap();
+        assert_eq!(rom, [0x12, 0x04, 0x00, 0xEE, 0x22, 0x02, 0x12, 0x06]);
+    }
+
+    #[test]
+    fn missing_main_is_an_error() {
+        assert!(assemble(": start clear").unwrap_err().contains("main"));
+    }
+
+    #[test]
+    fn assembles_instructions() {
+        let rom = assemble_body(
+            "clear v3 := 0x2A v3 += v4 v3 -= 1 v3 =- v4 v1 := key v1 := random 0b111
+             i := 0x345 i += v2 i := hex v1 sprite v1 v2 5 bcd v2 save v5 load v6 - v8
+             delay := v1 buzzer := v2 vf <<= ve",
+        );
+        assert_eq!(
+            rom,
+            [
+                0x00, 0xE0, 0x63, 0x2A, 0x83, 0x44, 0x73, 0xFF, 0x83, 0x47, 0xF1, 0x0A, 0xC1, 0x07,
+                0xA3, 0x45, 0xF2, 0x1E, 0xF1, 0x29, 0xD1, 0x25, 0xF2, 0x33, 0xF5, 0x55, 0x56, 0x83,
+                0xF1, 0x15, 0xF2, 0x18, 0x8F, 0xEE,
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_labels_defined_later() {
+        let rom = assemble_body("i := data jump end i := long data : end :byte 7 : data 0xFF");
+        assert_eq!(
+            rom,
+            [0xA2, 0x0B, 0x12, 0x0A, 0xF0, 0x00, 0x02, 0x0B, 0x07, 0xFF]
+        );
+    }
+
+    #[test]
+    fn assembles_control_flow() {
+        let rom = assemble_body(
+            "loop
+               if v0 == 3 then v1 := 1
+               if v0 key begin v2 := 2 else v2 := 3 end
+               while v0 != v1
+             again",
+        );
+        assert_eq!(
+            rom,
+            [
+                0x40, 0x03, 0x61, 0x01, // if v0 == 3 then v1 := 1
+                0xE0, 0x9E, 0x12, 0x0E, 0x62, 0x02, 0x12, 0x10, 0x62, 0x03, // if ... end
+                0x90, 0x10, 0x12, 0x16, // while v0 != v1
+                0x12, 0x02, // again
+            ]
+        );
+    }
+
+    #[test]
+    fn compares_with_the_flag_register() {
+        assert_eq!(
+            assemble_body("if v1 > 5 then clear"),
+            [0x6F, 0x05, 0x8F, 0x15, 0x3F, 0x01, 0x00, 0xE0]
+        );
+        assert_eq!(
+            assemble_body("if v1 <= v2 begin end"),
+            [0x8F, 0x20, 0x8F, 0x15, 0x3F, 0x01, 0x12, 0x0A]
+        );
+    }
+
+    #[test]
+    fn expands_macros_and_constants() {
+        let rom = assemble_body(
+            ":const SPEED 4 :alias x v5
+             :macro move register amount { register += amount }
+             move x SPEED :calc twice { SPEED * 2 + 1 } :byte twice :byte { SPEED << 4 }",
+        );
+        assert_eq!(rom, [0x75, 0x04, 0x0C, 0x40]);
+    }
+
+    #[test]
+    fn calc_evaluates_right_to_left() {
+        assert_eq!(
+            assemble_body(":byte { 2 * 3 + 1 } :byte { ( 2 * 3 ) + 1 }"),
+            [8, 7]
+        );
+    }
+
+    #[test]
+    fn expands_string_modes() {
+        let rom = assemble_body(
+            ":stringmode text \"AB\" { :byte VALUE :byte CHAR :byte INDEX } text \"BAB\"",
+        );
+        assert_eq!(rom, [1, 66, 0, 0, 65, 1, 1, 66, 2]);
+    }
+
+    #[test]
+    fn unpacks_addresses() {
+        let rom = assemble_body(":unpack 0xA data :unpack long data : data");
+        assert_eq!(rom, [0x60, 0xA2, 0x61, 0x0A, 0x60, 0x02, 0x61, 0x0A]);
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        let error = assemble(": main\n  v0 := 300").unwrap_err();
+        assert!(error.starts_with("line 2:"), "{}", error);
+        let error = assemble(": main\n  loop\n  clear").unwrap_err();
+        assert!(error.contains("again"), "{}", error);
+        let error = assemble(": main\n  undefined-label").unwrap_err();
+        assert!(error.contains("undefined-label"), "{}", error);
+    }
+}
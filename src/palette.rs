@@ -0,0 +1,265 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+
+/// A colour as red, green and blue components.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// Parse a colour in hex notation, e.g. `#FFCC00` or `ffcc00`.
+    fn parse_hex(hex: &str) -> Result<Rgb, String> {
+        let digits = hex.trim().trim_start_matches('#');
+        if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid hex colour: {}", hex));
+        }
+        let component = |i: usize| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("Invalid hex colour: {}", hex))
+        };
+        Ok(Rgb(component(0)?, component(2)?, component(4)?))
+    }
+
+    /// The colour at `share` of the way from this colour to `other`.
+    pub fn mix(self, other: Rgb, share: f32) -> Rgb {
+        let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * share) as u8;
+        Rgb(
+            channel(self.0, other.0),
+            channel(self.1, other.1),
+            channel(self.2, other.2),
+        )
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02X}{:02X}{:02X}", self.0, self.1, self.2)
+    }
+}
+
+/// The colours that pixels are drawn in. A palette has one colour for each
+/// combination of the two XO-CHIP bitplanes: off, only the first plane set,
+/// only the second plane set, and both planes set. CHIP-8 programs only use
+/// the first two.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    name: Option<&'static str>,
+    colors: [Rgb; 4],
+}
+
+/// The built-in palettes, in the order they are cycled through.
+const PRESETS: [(&str, [Rgb; 4]); 6] = [
+    (
+        "classic",
+        [
+            Rgb(0x00, 0x00, 0x00),
+            Rgb(0xFF, 0xFF, 0xFF),
+            Rgb(0xAA, 0xAA, 0xAA),
+            Rgb(0x55, 0x55, 0x55),
+        ],
+    ),
+    (
+        "vip",
+        [
+            Rgb(0x11, 0x11, 0x11),
+            Rgb(0xE8, 0xE8, 0xE0),
+            Rgb(0x9C, 0x9C, 0x96),
+            Rgb(0x50, 0x50, 0x4C),
+        ],
+    ),
+    (
+        "octo",
+        [
+            Rgb(0x99, 0x66, 0x00),
+            Rgb(0xFF, 0xCC, 0x00),
+            Rgb(0xFF, 0x66, 0x00),
+            Rgb(0x66, 0x22, 0x00),
+        ],
+    ),
+    (
+        "amber",
+        [
+            Rgb(0x1A, 0x0F, 0x00),
+            Rgb(0xFF, 0xB0, 0x00),
+            Rgb(0xB3, 0x6B, 0x00),
+            Rgb(0x66, 0x3D, 0x00),
+        ],
+    ),
+    (
+        "green",
+        [
+            Rgb(0x0A, 0x14, 0x0A),
+            Rgb(0x33, 0xFF, 0x33),
+            Rgb(0x1F, 0xA6, 0x1F),
+            Rgb(0x14, 0x5C, 0x14),
+        ],
+    ),
+    (
+        "lcd",
+        [
+            Rgb(0x9B, 0xBC, 0x0F),
+            Rgb(0x0F, 0x38, 0x0F),
+            Rgb(0x30, 0x62, 0x30),
+            Rgb(0x8B, 0xAC, 0x0F),
+        ],
+    ),
+];
+
+impl Palette {
+    /// Look up a built-in palette by name.
+    pub fn preset(name: &str) -> Option<Palette> {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(preset, colors)| Palette {
+                name: Some(preset),
+                colors: *colors,
+            })
+    }
+
+    /// Names of all built-in palettes.
+    pub fn preset_names() -> impl Iterator<Item = &'static str> {
+        PRESETS.iter().map(|(name, _)| *name)
+    }
+
+    /// The built-in palette following this one. Custom palettes are followed by the
+    /// first built-in palette.
+    pub fn next_preset(&self) -> Palette {
+        let position = PRESETS
+            .iter()
+            .position(|(name, _)| Some(*name) == self.name)
+            .map_or(0, |i| (i + 1) % PRESETS.len());
+        let (name, colors) = PRESETS[position];
+        Palette {
+            name: Some(name),
+            colors,
+        }
+    }
+
+    /// The colour for a pixel with the given bitplanes set, where bit 0 is the first plane.
+    pub fn color(&self, planes: u8) -> Rgb {
+        self.colors[(planes & 0b11) as usize]
+    }
+
+    /// The colour of pixels that are off.
+    pub fn background(&self) -> Rgb {
+        self.color(0)
+    }
+
+    /// The colour of pixels that are on.
+    pub fn foreground(&self) -> Rgb {
+        self.color(1)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::preset("classic").expect("Missing default palette")
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    /// Parse a palette either by preset name, or as a comma separated list of two
+    /// or four hex colours. With two colours, both bitplanes use the second one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(palette) = Palette::preset(s) {
+            return Ok(palette);
+        }
+        let colors = s
+            .split(',')
+            .map(Rgb::parse_hex)
+            .collect::<Result<Vec<Rgb>, String>>()
+            .map_err(|e| {
+                let presets: Vec<&str> = Palette::preset_names().collect();
+                format!("{} (available palettes: {})", e, presets.join(", "))
+            })?;
+        let colors = match colors.as_slice() {
+            [off, on] => [*off, *on, *on, *on],
+            [off, first, second, both] => [*off, *first, *second, *both],
+            _ => return Err(format!("A palette needs two or four colours: {}", s)),
+        };
+        Ok(Palette { name: None, colors })
+    }
+}
+
+impl<'de> Deserialize<'de> for Palette {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "{}", name),
+            None => write!(
+                f,
+                "{},{},{},{}",
+                self.colors[0], self.colors[1], self.colors[2], self.colors[3]
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colours() {
+        assert_eq!(Rgb::parse_hex("#FFCC00"), Ok(Rgb(0xFF, 0xCC, 0x00)));
+        assert_eq!(Rgb::parse_hex(" 0a1b2c "), Ok(Rgb(0x0A, 0x1B, 0x2C)));
+        for invalid in &["#FFF", "#FFCC000", "#GGCC00", "#+FCC00", "#FFCCÄ"] {
+            assert_eq!(
+                Rgb::parse_hex(invalid),
+                Err(format!("Invalid hex colour: {}", invalid))
+            );
+        }
+    }
+
+    #[test]
+    fn parses_palettes_of_two_or_four_colours() {
+        let two: Palette = "#000000,#FFCC00".parse().unwrap();
+        assert_eq!(two.background(), Rgb(0, 0, 0));
+        assert_eq!(two.color(0b11), Rgb(0xFF, 0xCC, 0x00));
+        assert_eq!(two.to_string(), "#000000,#FFCC00,#FFCC00,#FFCC00");
+
+        let four: Palette = "000000,111111,222222,333333".parse().unwrap();
+        assert_eq!(four.color(0b10), Rgb(0x22, 0x22, 0x22));
+        assert_eq!(four.to_string().parse(), Ok(four));
+
+        assert_eq!(
+            "#000000,#111111,#222222".parse::<Palette>(),
+            Err("A palette needs two or four colours: #000000,#111111,#222222".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_palettes_by_name() {
+        for name in Palette::preset_names() {
+            let palette: Palette = name.parse().unwrap();
+            assert_eq!(palette.to_string(), name);
+        }
+        assert_eq!("OCTO".parse::<Palette>().unwrap().to_string(), "octo");
+        let error = "sepia".parse::<Palette>().unwrap_err();
+        assert!(error.starts_with("Invalid hex colour: sepia"), "{}", error);
+        assert!(error.contains("classic, vip, octo"), "{}", error);
+    }
+
+    #[test]
+    fn cycles_through_the_presets() {
+        let custom: Palette = "#000000,#FFFFFF".parse().unwrap();
+        let mut palette = custom.next_preset();
+        assert_eq!(palette, Palette::default());
+        for name in Palette::preset_names().skip(1) {
+            palette = palette.next_preset();
+            assert_eq!(palette.to_string(), name);
+        }
+        assert_eq!(palette.next_preset(), Palette::default());
+    }
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::MAX_RECENT_ROMS;
+use crate::rom;
+
+/// The ROMs played most recently, remembered between runs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentRoms {
+    /// Paths of the ROMs, the most recent first.
+    roms: Vec<PathBuf>,
+}
+
+impl RecentRoms {
+    /// The ROMs remembered from earlier runs, if any.
+    pub fn load() -> RecentRoms {
+        recent_file()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// The ROMs that still exist, the most recent first.
+    pub fn roms(&self) -> impl Iterator<Item = &Path> {
+        self.roms
+            .iter()
+            .map(PathBuf::as_path)
+            .filter(|rom| rom::exists(rom))
+    }
+
+    /// Move the ROM to the front, dropping the oldest ROM if there are too many.
+    pub fn add(&mut self, rom: &Path) {
+        let rom = absolute(rom);
+        self.roms.retain(|recent| *recent != rom);
+        self.roms.insert(0, rom);
+        self.roms.truncate(MAX_RECENT_ROMS);
+    }
+
+    /// Remember the ROMs for the next run.
+    pub fn save(&self) -> Result<(), String> {
+        let path = recent_file().ok_or("Could not find configuration directory")?;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| {
+                format!("Could not create directory {}: {}", directory.display(), e)
+            })?;
+        }
+        let contents = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(&path, contents).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
+
+/// The path made absolute, also for ROMs inside archives, which can not be
+/// canonicalized as a whole.
+fn absolute(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find_map(|ancestor| {
+            let canonical = ancestor.canonicalize().ok()?;
+            Some(canonical.join(path.strip_prefix(ancestor).ok()?))
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+fn recent_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("chip8").join("recent.toml"))
+}
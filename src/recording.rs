@@ -0,0 +1,388 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::buzzer::{Buzzer, Tone};
+use crate::config::{
+    AUDIO_SAMPLE_RATE, DEFAULT_REFRESH_RATE, GIF_MIN_FRAME_DELAY, MAX_RECORDING_SCALE, SCREEN_X,
+    SCREEN_Y,
+};
+use crate::interpreter::Framebuffer;
+use crate::palette::Palette;
+use crate::screenshot::{rgb_pixels, timestamped_path};
+use crate::settings::Settings;
+
+/// The file format of a recording.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingFormat {
+    /// An animated GIF without sound, for short clips.
+    Gif,
+    /// An uncompressed YUV4MPEG2 video with the sound in a separate WAV file, for
+    /// encoding with external tools.
+    Y4m,
+}
+
+impl FromStr for RecordingFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gif" => Ok(RecordingFormat::Gif),
+            "y4m" => Ok(RecordingFormat::Y4m),
+            _ => Err(format!(
+                "Unknown recording format: {} (available: gif, y4m)",
+                s
+            )),
+        }
+    }
+}
+
+/// Check that CHIP-8 pixels of the given size fit into a recording.
+pub fn check_scale(scale: u32) -> Result<(), String> {
+    if !(1..=MAX_RECORDING_SCALE).contains(&scale) {
+        return Err(format!(
+            "Recording scale {} is not between 1 and {}",
+            scale, MAX_RECORDING_SCALE
+        ));
+    }
+    Ok(())
+}
+
+/// A recording of the frames shown on the display, and of the sound of the buzzer.
+pub struct Recording {
+    video: Video,
+    audio: Option<Audio>,
+    paths: Vec<PathBuf>,
+    scale: u32,
+}
+
+enum Video {
+    Gif(GifVideo),
+    Y4m(BufWriter<File>),
+}
+
+impl Recording {
+    /// Start a new recording into files with a timestamped name in the given directory,
+    /// which is created if necessary. Every CHIP-8 pixel is recorded as a square of
+    /// `scale` by `scale` pixels.
+    pub fn start(
+        directory: &Path,
+        format: RecordingFormat,
+        scale: u32,
+        palette: &Palette,
+        tone: Tone,
+    ) -> Result<Recording, String> {
+        check_scale(scale)?;
+        fs::create_dir_all(directory).map_err(|e| {
+            format!(
+                "Could not create recording directory {}: {}",
+                directory.display(),
+                e
+            )
+        })?;
+        let width = SCREEN_X as u32 * scale;
+        let height = SCREEN_Y as u32 * scale;
+
+        match format {
+            RecordingFormat::Gif => {
+                let path = timestamped_path(directory, "gif");
+                let video = GifVideo::new(create(&path)?, width, height, palette)
+                    .map_err(|e| format!("Could not write recording: {}", e))?;
+                Ok(Recording {
+                    video: Video::Gif(video),
+                    audio: None,
+                    paths: vec![path],
+                    scale,
+                })
+            }
+            RecordingFormat::Y4m => {
+                let path = timestamped_path(directory, "y4m");
+                let audio_path = path.with_extension("wav");
+                let mut video = create(&path)?;
+                writeln!(
+                    video,
+                    "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                    width, height, DEFAULT_REFRESH_RATE
+                )
+                .map_err(|e| format!("Could not write recording: {}", e))?;
+                Ok(Recording {
+                    video: Video::Y4m(video),
+                    audio: Some(Audio::new(&audio_path, tone)?),
+                    paths: vec![path, audio_path],
+                    scale,
+                })
+            }
+        }
+    }
+
+    /// Add the next frame of the display, shown for one refresh of the screen, and
+    /// the times during it at which the buzzer was switched on or off.
+    pub fn add_frame(
+        &mut self,
+        framebuffer: &Framebuffer,
+        palette: &Palette,
+        sound_changes: &[(f32, bool)],
+    ) -> Result<(), String> {
+        match &mut self.video {
+            Video::Gif(video) => video
+                .add_frame(framebuffer, palette, self.scale)
+                .map_err(|e| format!("Could not write recording: {}", e))?,
+            Video::Y4m(video) => write_y4m_frame(video, framebuffer, palette, self.scale)
+                .map_err(|e| format!("Could not write recording: {}", e))?,
+        }
+        if let Some(audio) = &mut self.audio {
+            audio.add_frame(sound_changes)?;
+        }
+        Ok(())
+    }
+
+    /// Finish writing the recording. Returns the paths of all files it was written to.
+    pub fn finish(self) -> Result<Vec<PathBuf>, String> {
+        match self.video {
+            Video::Gif(video) => video
+                .finish()
+                .map_err(|e| format!("Could not write recording: {}", e))?,
+            Video::Y4m(mut video) => video
+                .flush()
+                .map_err(|e| format!("Could not write recording: {}", e))?,
+        }
+        if let Some(audio) = self.audio {
+            audio.finish()?;
+        }
+        Ok(self.paths)
+    }
+}
+
+/// Start a recording as set in the settings, or finish the active one. Errors are
+/// logged rather than returned, so that they do not stop the interpreter.
+pub fn toggle(active: &mut Option<Recording>, settings: &Settings, palette: &Palette) {
+    match active.take() {
+        Some(recording) => finish(recording),
+        None => match Recording::start(
+            &settings.recording.directory,
+            settings.recording.format,
+            settings.recording.scale,
+            palette,
+            settings.audio.tone(),
+        ) {
+            Ok(started) => {
+                info!("Recording started");
+                *active = Some(started);
+            }
+            Err(e) => warn!("{}", e),
+        },
+    }
+}
+
+/// Finish the recording, and log the files it was written to.
+pub fn finish(recording: Recording) {
+    match recording.finish() {
+        Ok(paths) => {
+            for path in paths {
+                info!("Saved recording {}", path.display());
+            }
+        }
+        Err(e) => warn!("{}", e),
+    }
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>, String> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|e| format!("Could not create recording {}: {}", path.display(), e))
+}
+
+/// Write a frame of a YUV4MPEG2 video in 4:4:4 sampling.
+fn write_y4m_frame<W: Write>(
+    writer: &mut W,
+    framebuffer: &Framebuffer,
+    palette: &Palette,
+    scale: u32,
+) -> std::io::Result<()> {
+    let rgb = rgb_pixels(framebuffer, palette, scale);
+    let pixels = rgb.len() / 3;
+    let mut planes = vec![0u8; pixels * 3];
+    for (i, color) in rgb.chunks(3).enumerate() {
+        let (r, g, b) = (color[0] as f32, color[1] as f32, color[2] as f32);
+        planes[i] = (16.0 + 0.257 * r + 0.504 * g + 0.098 * b) as u8;
+        planes[pixels + i] = (128.0 - 0.148 * r - 0.291 * g + 0.439 * b) as u8;
+        planes[2 * pixels + i] = (128.0 + 0.439 * r - 0.368 * g - 0.071 * b) as u8;
+    }
+    writer.write_all(b"FRAME\n")?;
+    writer.write_all(&planes)
+}
+
+/// An animated GIF. GIF frame delays are counted in hundredths of a second, and most
+/// viewers slow down frames that are shorter than two hundredths, so frames are merged
+/// until they have been shown for at least that long.
+struct GifVideo {
+    encoder: gif::Encoder<BufWriter<File>>,
+    width: u16,
+    height: u16,
+    global_palette: Vec<u8>,
+    pending: Option<GifFrame>,
+    frames: u64,
+}
+
+struct GifFrame {
+    pixels: Vec<u8>,
+    palette: Vec<u8>,
+    /// Time (in hundredths of a second) at which the frame is first shown.
+    start: u64,
+}
+
+impl GifVideo {
+    fn new(
+        writer: BufWriter<File>,
+        width: u32,
+        height: u32,
+        palette: &Palette,
+    ) -> Result<Self, gif::EncodingError> {
+        let global_palette = gif_palette(palette);
+        let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &global_palette)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        Ok(GifVideo {
+            encoder,
+            width: width as u16,
+            height: height as u16,
+            global_palette,
+            pending: None,
+            frames: 0,
+        })
+    }
+
+    fn add_frame(
+        &mut self,
+        framebuffer: &Framebuffer,
+        palette: &Palette,
+        scale: u32,
+    ) -> Result<(), gif::EncodingError> {
+        let frame = GifFrame {
+            pixels: gif_pixels(framebuffer, scale),
+            palette: gif_palette(palette),
+            start: self.time(),
+        };
+        self.frames += 1;
+
+        match self.pending.take() {
+            Some(pending) if pending.pixels == frame.pixels && pending.palette == frame.palette => {
+                self.pending = Some(pending);
+            }
+            Some(pending) if frame.start - pending.start < GIF_MIN_FRAME_DELAY => {
+                self.pending = Some(GifFrame {
+                    start: pending.start,
+                    ..frame
+                });
+            }
+            Some(pending) => {
+                self.write_frame(pending, frame.start)?;
+                self.pending = Some(frame);
+            }
+            None => self.pending = Some(frame),
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), gif::EncodingError> {
+        let end = self.time();
+        if let Some(pending) = self.pending.take() {
+            self.write_frame(pending, end)?;
+        }
+        // The encoder writes the end of the file when it is dropped.
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: GifFrame, end: u64) -> Result<(), gif::EncodingError> {
+        let mut gif_frame =
+            gif::Frame::from_indexed_pixels(self.width, self.height, &frame.pixels, None);
+        gif_frame.delay = (end - frame.start)
+            .max(GIF_MIN_FRAME_DELAY)
+            .min(u16::MAX as u64) as u16;
+        if frame.palette != self.global_palette {
+            gif_frame.palette = Some(frame.palette);
+        }
+        self.encoder.write_frame(&gif_frame)
+    }
+
+    /// The time (in hundredths of a second) at which the next frame is shown.
+    fn time(&self) -> u64 {
+        self.frames * 100 / DEFAULT_REFRESH_RATE as u64
+    }
+}
+
+/// The background and foreground colours of the palette, as GIF colour table.
+fn gif_palette(palette: &Palette) -> Vec<u8> {
+    let background = palette.background();
+    let foreground = palette.foreground();
+    vec![
+        background.0,
+        background.1,
+        background.2,
+        foreground.0,
+        foreground.1,
+        foreground.2,
+    ]
+}
+
+/// The display as indices into the GIF colour table.
+fn gif_pixels(framebuffer: &Framebuffer, scale: u32) -> Vec<u8> {
+    let scale = scale as usize;
+    let width = framebuffer.width() * scale;
+    let mut pixels = Vec::with_capacity(width * framebuffer.height() * scale);
+    for row in framebuffer.pixel_rows() {
+        let start = pixels.len();
+        for on in row {
+            pixels.resize(pixels.len() + scale, on as u8);
+        }
+        for _ in 1..scale {
+            pixels.extend_from_within(start..start + width);
+        }
+    }
+    pixels
+}
+
+/// The sound of the buzzer, as WAV file.
+struct Audio {
+    writer: hound::WavWriter<BufWriter<File>>,
+    buzzer: Buzzer,
+    samples: Vec<f32>,
+}
+
+impl Audio {
+    fn new(path: &Path, tone: Tone) -> Result<Self, String> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: AUDIO_SAMPLE_RATE as u32,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer = hound::WavWriter::create(path, spec)
+            .map_err(|e| format!("Could not create recording {}: {}", path.display(), e))?;
+        Ok(Audio {
+            writer,
+            buzzer: Buzzer::new(AUDIO_SAMPLE_RATE, tone),
+            samples: vec![0.0; (AUDIO_SAMPLE_RATE as u32 / DEFAULT_REFRESH_RATE) as usize],
+        })
+    }
+
+    fn add_frame(&mut self, sound_changes: &[(f32, bool)]) -> Result<(), String> {
+        self.buzzer.render(&mut self.samples, sound_changes);
+        for sample in &self.samples {
+            self.writer
+                .write_sample((sample * i16::MAX as f32) as i16)
+                .map_err(|e| format!("Could not write recording: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), String> {
+        self.writer
+            .finalize()
+            .map_err(|e| format!("Could not write recording: {}", e))
+    }
+}
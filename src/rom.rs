@@ -0,0 +1,260 @@
+//! Reading ROMs from files, standard input and compressed archives.
+//!
+//! A ROM is named by a path: a ROM file, `-` for standard input, a gzip-compressed
+//! ROM ending in `.gz`, or a zip archive. A ROM inside a zip archive is named by the
+//! path of the archive followed by its name in the archive, e.g. `roms.zip/pong.ch8`.
+//! The ROM may also be an Octo cartridge, or come with Octo options (see `octo`).
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use crate::config::{MAX_CARTRIDGE_SIZE, ROM_EXTENSIONS};
+use crate::interpreter::MemoryMap;
+use crate::octo::{self, OctoOptions};
+use crate::settings::Settings;
+
+/// The name of standard input as ROM path.
+pub const STDIN: &str = "-";
+
+/// A program, with the Octo options that came with it.
+pub struct Rom {
+    pub program: Vec<u8>,
+    pub options: Option<OctoOptions>,
+}
+
+impl Rom {
+    /// The program in the bytes of a ROM, which may be an Octo cartridge.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Rom, String> {
+        if octo::is_cartridge(&bytes) {
+            let (program, options) = octo::read_cartridge(&bytes)?;
+            return Ok(Rom {
+                program,
+                options: Some(options),
+            });
+        }
+        Ok(Rom {
+            program: bytes,
+            options: None,
+        })
+    }
+
+    /// Take the settings that are not set explicitly from the Octo options, if any.
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(options) = &self.options {
+            options.apply(settings);
+        }
+    }
+}
+
+/// Read the ROM at the path, with the program and options of an Octo cartridge,
+/// or the Octo options in the file beside a ROM file. A ROM that does not fit into
+/// the memory is not read to its end.
+pub fn load(path: &Path, memory: &MemoryMap) -> Result<Rom, String> {
+    let mut rom = Rom::from_bytes(read(path, memory.max_program_size())?)?;
+    if rom.options.is_none() && path.is_file() {
+        rom.options = OctoOptions::beside(path).transpose()?;
+    }
+    Ok(rom)
+}
+
+/// Read the bytes of the ROM at the path, which must be at most `limit` bytes, or
+/// `MAX_CARTRIDGE_SIZE` for an Octo cartridge. A zip archive must hold a single ROM,
+/// otherwise one of its ROMs has to be named.
+pub fn read(path: &Path, limit: usize) -> Result<Vec<u8>, String> {
+    if path == Path::new(STDIN) {
+        return read_all(io::stdin().lock(), path, limit);
+    }
+    if let Some((archive, entry)) = split_archive_path(path) {
+        return read_from_archive(&archive, &entry, limit);
+    }
+    if is_archive(path) {
+        let roms = archive_roms(path)?;
+        return match roms.as_slice() {
+            [rom] => read(rom, limit),
+            [] => Err(format!("{} holds no ROMs", path.display())),
+            _ => Err(format!(
+                "{} holds several ROMs, name one of them: {}",
+                path.display(),
+                roms.iter()
+                    .map(|rom| rom.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        };
+    }
+
+    let file = File::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    if has_extension(path, "gz") {
+        read_all(GzDecoder::new(file), path, limit)
+    } else {
+        read_all(file, path, limit)
+    }
+}
+
+/// Whether the path is a zip archive, which may hold several ROMs.
+pub fn is_archive(path: &Path) -> bool {
+    has_extension(path, "zip")
+}
+
+/// Whether the path names something that can be read as ROM: an existing file, or a
+/// ROM in an existing zip archive.
+pub fn exists(path: &Path) -> bool {
+    path.is_file() || split_archive_path(path).is_some()
+}
+
+/// The file that the ROM is read from, which is the archive for a ROM in one, or
+/// nothing for standard input.
+pub fn file(path: &Path) -> Option<PathBuf> {
+    if path == Path::new(STDIN) {
+        return None;
+    }
+    match split_archive_path(path) {
+        Some((archive, _)) => Some(archive),
+        None => Some(path.to_path_buf()),
+    }
+}
+
+/// The ROMs in the zip archive, as paths for `read`.
+pub fn archive_roms(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut archive = open_archive(path)?;
+    let mut roms = Vec::new();
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index(index)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let name = Path::new(entry.name());
+        if entry.is_file() && is_rom_file(name) {
+            roms.push(path.join(name));
+        }
+    }
+    roms.sort();
+    Ok(roms)
+}
+
+/// Whether the file name has the extension of a ROM, possibly gzip-compressed.
+pub fn is_rom_file(path: &Path) -> bool {
+    let path = match path.file_stem() {
+        Some(stem) if has_extension(path, "gz") => Path::new(stem),
+        _ => path,
+    };
+    ROM_EXTENSIONS
+        .iter()
+        .any(|extension| has_extension(path, extension))
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+/// Split a path into the zip archive it leads into and the name of the entry in it.
+fn split_archive_path(path: &Path) -> Option<(PathBuf, String)> {
+    path.ancestors()
+        .skip(1)
+        .find(|ancestor| is_archive(ancestor) && ancestor.is_file())
+        .and_then(|archive| {
+            let entry = path.strip_prefix(archive).ok()?;
+            let entry = entry
+                .iter()
+                .map(|part| part.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            Some((archive.to_path_buf(), entry))
+        })
+}
+
+fn read_from_archive(archive_path: &Path, name: &str, limit: usize) -> Result<Vec<u8>, String> {
+    let mut archive = open_archive(archive_path)?;
+    let entry = archive.by_name(name).map_err(|e| {
+        format!(
+            "Could not read {} in {}: {}",
+            name,
+            archive_path.display(),
+            e
+        )
+    })?;
+    let path = archive_path.join(name);
+    if has_extension(&path, "gz") {
+        read_all(GzDecoder::new(entry), &path, limit)
+    } else {
+        read_all(entry, &path, limit)
+    }
+}
+
+fn open_archive(path: &Path) -> Result<ZipArchive<File>, String> {
+    let file = File::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    ZipArchive::new(file).map_err(|e| format!("Could not read {}: {}", path.display(), e))
+}
+
+/// Read at most one byte more than the limit, so that a compressed ROM can not
+/// exhaust the memory, and fail if the ROM is larger than the limit.
+fn read_all<R: Read>(reader: R, path: &Path, limit: usize) -> Result<Vec<u8>, String> {
+    let error = |e: io::Error| format!("Could not read {}: {}", path.display(), e);
+    let mut program = Vec::new();
+    let mut reader = reader.take(limit as u64 + 1);
+    reader.read_to_end(&mut program).map_err(error)?;
+    let limit = if octo::is_cartridge(&program) {
+        let remaining = MAX_CARTRIDGE_SIZE.saturating_sub(program.len());
+        reader.set_limit(remaining as u64 + 1);
+        reader.read_to_end(&mut program).map_err(error)?;
+        MAX_CARTRIDGE_SIZE
+    } else {
+        limit
+    };
+    if program.len() > limit {
+        return Err(format!(
+            "{} is larger than the {} bytes that fit into memory",
+            path.display(),
+            limit
+        ));
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn read_bytes(bytes: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+        read_all(Cursor::new(bytes), Path::new("rom"), limit)
+    }
+
+    #[test]
+    fn reads_roms_up_to_the_limit() {
+        assert_eq!(read_bytes(&[1, 2, 3], 3).unwrap(), vec![1, 2, 3]);
+        assert!(read_bytes(&[1, 2, 3, 4], 3).is_err());
+    }
+
+    #[test]
+    fn stops_reading_a_compressed_rom_at_the_limit() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0; 8 << 20]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let error = read_all(
+            GzDecoder::new(compressed.as_slice()),
+            Path::new("rom.gz"),
+            3584,
+        )
+        .unwrap_err();
+        assert!(error.contains("larger than the 3584 bytes"), "{}", error);
+    }
+
+    #[test]
+    fn cartridges_may_be_larger_than_the_memory() {
+        let mut cartridge = b"GIF89a".to_vec();
+        cartridge.resize(10_000, 0);
+        assert_eq!(read_bytes(&cartridge, 3584).unwrap().len(), 10_000);
+        cartridge.resize(MAX_CARTRIDGE_SIZE + 1, 0);
+        assert!(read_bytes(&cartridge, 3584).is_err());
+    }
+}
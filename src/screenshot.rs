@@ -0,0 +1,89 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use crate::config::{SCREEN_X, SCREEN_Y};
+use crate::interpreter::Framebuffer;
+use crate::palette::Palette;
+
+/// Render the display into 8-bit RGB pixel data in row-major order, with every
+/// CHIP-8 pixel drawn as a square of `scale` by `scale` pixels.
+pub fn rgb_pixels(framebuffer: &Framebuffer, palette: &Palette, scale: u32) -> Vec<u8> {
+    let scale = scale as usize;
+    let width = framebuffer.width() * scale;
+    let mut data = Vec::with_capacity(width * framebuffer.height() * scale * 3);
+    for row in framebuffer.pixel_rows() {
+        let start = data.len();
+        for on in row {
+            let color = if on {
+                palette.foreground()
+            } else {
+                palette.background()
+            };
+            for _ in 0..scale {
+                data.extend_from_slice(&[color.0, color.1, color.2]);
+            }
+        }
+        for _ in 1..scale {
+            data.extend_from_within(start..start + width * 3);
+        }
+    }
+    data
+}
+
+/// Encode the display as PNG image, with every CHIP-8 pixel drawn as a square of
+/// `scale` by `scale` pixels.
+pub fn write_png<W: Write>(
+    writer: W,
+    framebuffer: &Framebuffer,
+    palette: &Palette,
+    scale: u32,
+) -> Result<(), String> {
+    if scale == 0 {
+        return Err("Screenshot scale must be at least 1".to_string());
+    }
+    let mut encoder = png::Encoder::new(writer, SCREEN_X as u32 * scale, SCREEN_Y as u32 * scale);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&rgb_pixels(framebuffer, palette, scale)))
+        .map_err(|e| format!("Could not encode screenshot: {}", e))
+}
+
+/// Save the display as PNG file with a timestamped name in the given directory, which
+/// is created if necessary. Returns the path of the new file.
+pub fn save(
+    directory: &Path,
+    framebuffer: &Framebuffer,
+    palette: &Palette,
+    scale: u32,
+) -> Result<PathBuf, String> {
+    fs::create_dir_all(directory).map_err(|e| {
+        format!(
+            "Could not create screenshot directory {}: {}",
+            directory.display(),
+            e
+        )
+    })?;
+    let path = timestamped_path(directory, "png");
+    let file = File::create(&path)
+        .map_err(|e| format!("Could not create screenshot {}: {}", path.display(), e))?;
+    write_png(BufWriter::new(file), framebuffer, palette, scale)?;
+    Ok(path)
+}
+
+/// A path in the given directory that is named after the current time. If a file of
+/// that name exists already, a counter is appended.
+pub fn timestamped_path(directory: &Path, extension: &str) -> PathBuf {
+    let timestamp = Local::now().format("chip8-%Y%m%d-%H%M%S").to_string();
+    let mut path = directory.join(format!("{}.{}", timestamp, extension));
+    let mut counter = 1;
+    while path.exists() {
+        path = directory.join(format!("{}-{}.{}", timestamp, counter, extension));
+        counter += 1;
+    }
+    path
+}
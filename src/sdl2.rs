@@ -1,194 +1,971 @@
 extern crate sdl2;
 
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 
-use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::controller::{Axis, Button, GameController};
 use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Scancode};
 use sdl2::pixels::Color;
-use sdl2::rect::Point;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::video::{FullscreenType, Window, WindowContext};
 
-use self::sdl2::audio::AudioDevice;
+use self::sdl2::{EventPump, GameControllerSubsystem};
+use log::{info, warn};
+
+use crate::buzzer::{Buzzer, Tone};
 use crate::config::*;
-use crate::interpreter::Interpreter;
+use crate::database::RomDatabase;
+use crate::emulator::Emulator;
+use crate::filter::{DisplayFilter, Intensities};
+use crate::frontend::Frontend;
+use crate::geometry::WindowGeometry;
+use crate::interpreter::{DirtyRect, Framebuffer, Interpreter};
+use crate::keymap::{Action, Binding, Keymap};
+use crate::launcher::{self, Launcher};
+use crate::palette::{Palette, Rgb};
+use crate::recent::RecentRoms;
+use crate::recording::{self, Recording};
+use crate::rom;
+use crate::screenshot;
+use crate::settings::{Settings, WindowSettings};
+use crate::viewport::{Effect, ScreenRect, Viewport};
+use crate::watch::RomWatcher;
 
-const WHITE: Color = Color::RGB(255, 255, 255);
-const BLACK: Color = Color::RGB(0, 0, 0);
+/// Colour of the bars beside the display when the window has a different aspect ratio.
+const BORDER: Color = Color::RGB(0, 0, 0);
 
-pub fn for_interpreter(interpreter: &mut Interpreter, step_mode: bool) -> Result<(), String> {
+/// Run the ROM in a window, or show the launcher to pick one if no ROM is given.
+pub fn run(
+    rom: Option<&Path>,
+    keymap_file: Option<&Path>,
+    settings: &Settings,
+    step_mode: bool,
+) -> Result<(), String> {
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
     let audio_subsystem = sdl_context.audio()?;
-    let audio_device = prepare_sound(&audio_subsystem);
 
-    let window = video_subsystem
-        .window("Chip-8", 640, 320)
-        .position_centered()
+    let window_settings = &settings.window;
+    let remembered = if window_settings.remember_geometry {
+        WindowGeometry::load()
+    } else {
+        None
+    };
+    let aspect = window_settings.pixel_aspect;
+    let (width, height) = match (window_settings.scale, remembered) {
+        (None, Some(geometry)) => (geometry.width, geometry.height),
+        (scale, _) => {
+            let scale = scale.unwrap_or(DEFAULT_WINDOW_SCALE).max(1);
+            (
+                SCREEN_X as u32 * aspect.width * scale,
+                SCREEN_Y as u32 * aspect.height * scale,
+            )
+        }
+    };
+    let mut window_builder = video_subsystem.window("Chip-8", width, height);
+    match remembered {
+        Some(geometry) => window_builder.position(geometry.x, geometry.y),
+        None => window_builder.position_centered(),
+    };
+    let window = window_builder
         .resizable()
         .opengl()
         .build()
         .map_err(|e| e.to_string())?;
+    let windowed_geometry = window_geometry(&window);
 
     let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
-    canvas
-        .set_logical_size(SCREEN_X as u32, SCREEN_Y as u32)
-        .map_err(|e| e.to_string())?;
-    canvas.set_draw_color(BLACK);
+    let start_fullscreen = window_settings
+        .fullscreen
+        .or_else(|| remembered.map(|g| g.fullscreen))
+        .unwrap_or(false);
+    if start_fullscreen {
+        set_fullscreen(&mut canvas, true)?;
+    }
+    let palette = settings.display.palette.clone().unwrap_or_default();
+    canvas.set_draw_color(to_color(palette.background()));
     canvas.clear();
     canvas.present();
+    let texture_creator = canvas.texture_creator();
+
+    let mut frontend = SdlFrontend {
+        settings,
+        key_bindings: KeyBindings::new(&Keymap::default())?,
+        event_pump: sdl_context.event_pump()?,
+        controllers: Controllers::new(sdl_context.game_controller()?),
+        sound: Sound::new(&audio_subsystem, settings.audio.tone())?,
+        screen: Screen::new(&texture_creator)?,
+        canvas,
+        windowed_geometry,
+        palette,
+        display_filter: DisplayFilter::new(settings.display.filter),
+        shown_framebuffer: Framebuffer::new(),
+        buzzer_changes: Vec::new(),
+        recording: None,
+        start_time: Instant::now(),
+        switch: None,
+        notice: None,
+    };
+    let first = match rom {
+        Some(rom) => Next::Rom(rom.to_path_buf()),
+        None => Next::Launcher,
+    };
+    let result = play(&mut frontend, first, keymap_file, step_mode);
+    frontend.finish();
+    result
+}
 
-    let mut event_pump = sdl_context.event_pump()?;
+/// What the window shows next.
+enum Next {
+    /// The launcher, to pick a ROM.
+    Launcher,
+    /// The ROM at the path, started from the beginning.
+    Rom(PathBuf),
+    /// The launcher with the ROMs of a zip archive, to pick one of them.
+    Archive(PathBuf),
+    /// The ROM that was running before the launcher was shown.
+    Game,
+    Exit,
+}
 
-    let mut pixel_states;
-    let mut interpreter_time = 0;
-    let start_time = Instant::now();
+/// A loaded ROM, with the settings it runs with.
+struct Game {
+    interpreter: Interpreter,
+    emulator: Emulator,
+    settings: Settings,
+    /// The title of the window while the game runs.
+    title: String,
+    rom: PathBuf,
+    /// Watches the ROM file to load it again when it changes, if enabled.
+    watcher: Option<RomWatcher>,
+}
 
-    'running: loop {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } => {
-                    break 'running;
+/// Switch between the launcher and the games picked in it until the window is closed.
+fn play(
+    frontend: &mut SdlFrontend,
+    first: Next,
+    keymap_file: Option<&Path>,
+    step_mode: bool,
+) -> Result<(), String> {
+    let settings = frontend.settings;
+    let database = RomDatabase::load(settings);
+    let mut recent = RecentRoms::load();
+    let mut game: Option<Game> = None;
+    let mut message = None;
+    let mut next = first;
+    loop {
+        next = match next {
+            Next::Launcher => {
+                let shown = Instant::now();
+                let next = frontend.launcher(
+                    &database,
+                    &settings.launcher.directory,
+                    Some(&recent),
+                    message.take(),
+                    game.is_some(),
+                )?;
+                // The game continues where it was left, as if no time had passed.
+                frontend.start_time += shown.elapsed();
+                next
+            }
+            Next::Archive(path) => {
+                let shown = Instant::now();
+                let next =
+                    frontend.launcher(&database, &path, None, message.take(), game.is_some())?;
+                frontend.start_time += shown.elapsed();
+                next
+            }
+            Next::Rom(path) if rom::is_archive(&path) => match rom::archive_roms(&path) {
+                Ok(mut roms) if roms.len() == 1 => Next::Rom(roms.remove(0)),
+                Ok(roms) if !roms.is_empty() => Next::Archive(path),
+                result => {
+                    let e = result
+                        .err()
+                        .unwrap_or_else(|| format!("{} holds no ROMs", path.display()));
+                    warn!("{}", e);
+                    message = Some(e);
+                    Next::Launcher
                 }
-
-                Event::KeyDown {
-                    keycode: Some(key), ..
-                } => match key {
-                    EXIT_KEY => {
-                        break 'running;
+            },
+            Next::Rom(path) => {
+                match load_game(&path, keymap_file, settings, &database, step_mode) {
+                    Ok((loaded, key_bindings)) => {
+                        info!("Playing {}", path.display());
+                        if path != Path::new(rom::STDIN) {
+                            recent.add(&path);
+                            if let Err(e) = recent.save() {
+                                warn!("Could not remember recent ROMs: {}", e);
+                            }
+                        }
+                        frontend.key_bindings = key_bindings;
+                        frontend.start_game(&loaded)?;
+                        game = Some(loaded);
+                        Next::Game
                     }
-                    RESET_KEY => {
-                        interpreter.reset();
+                    Err(e) => {
+                        warn!("{}", e);
+                        message = Some(e);
+                        Next::Launcher
                     }
-                    INSTRUCTION_STEP_KEY => {
-                        if step_mode {
-                            interpreter.instruction_step();
+                }
+            }
+            Next::Game => match game.as_mut() {
+                Some(game) => {
+                    while game
+                        .emulator
+                        .frame(&mut game.interpreter, frontend, &game.settings)?
+                        && frontend.switch.is_none()
+                    {
+                        if game.watcher.as_mut().is_some_and(RomWatcher::changed) {
+                            frontend.reload(game);
                         }
+                        frontend.wait();
                     }
-                    KEYPAD_1 => interpreter.key_pressed(1),
-                    KEYPAD_2 => interpreter.key_pressed(2),
-                    KEYPAD_3 => interpreter.key_pressed(3),
-                    KEYPAD_C => interpreter.key_pressed(0xC),
-                    KEYPAD_4 => interpreter.key_pressed(4),
-                    KEYPAD_5 => interpreter.key_pressed(5),
-                    KEYPAD_6 => interpreter.key_pressed(6),
-                    KEYPAD_D => interpreter.key_pressed(0xD),
-                    KEYPAD_7 => interpreter.key_pressed(7),
-                    KEYPAD_8 => interpreter.key_pressed(8),
-                    KEYPAD_9 => interpreter.key_pressed(9),
-                    KEYPAD_E => interpreter.key_pressed(0xE),
-                    KEYPAD_A => interpreter.key_pressed(0xA),
-                    KEYPAD_0 => interpreter.key_pressed(0),
-                    KEYPAD_B => interpreter.key_pressed(0xB),
-                    KEYPAD_F => interpreter.key_pressed(0xF),
-                    _ => {}
-                },
+                    frontend.switch.take().unwrap_or(Next::Exit)
+                }
+                None => Next::Launcher,
+            },
+            Next::Exit => return Ok(()),
+        };
+    }
+}
 
-                Event::KeyUp {
-                    keycode: Some(key), ..
-                } => match key {
-                    KEYPAD_1 => interpreter.key_released(1),
-                    KEYPAD_2 => interpreter.key_released(2),
-                    KEYPAD_3 => interpreter.key_released(3),
-                    KEYPAD_C => interpreter.key_released(0xC),
-                    KEYPAD_4 => interpreter.key_released(4),
-                    KEYPAD_5 => interpreter.key_released(5),
-                    KEYPAD_6 => interpreter.key_released(6),
-                    KEYPAD_D => interpreter.key_released(0xD),
-                    KEYPAD_7 => interpreter.key_released(7),
-                    KEYPAD_8 => interpreter.key_released(8),
-                    KEYPAD_9 => interpreter.key_released(9),
-                    KEYPAD_E => interpreter.key_released(0xE),
-                    KEYPAD_A => interpreter.key_released(0xA),
-                    KEYPAD_0 => interpreter.key_released(0),
-                    KEYPAD_B => interpreter.key_released(0xB),
-                    KEYPAD_F => interpreter.key_released(0xF),
-                    _ => {}
-                },
-
-                _ => {}
-            }
-        }
-        if !step_mode {
-            let now = start_time.elapsed().as_micros();
-
-            while now > interpreter_time {
-                let step_result = interpreter.instruction_step();
-                interpreter_time = interpreter_time + step_result.time_passed() as u128;
-            }
-        }
-
-        interpreter.frequency_step();
-        pixel_states = interpreter.pixel_states();
-
-        if interpreter.sound_on() {
-            audio_device.resume();
-        } else {
-            audio_device.pause();
+/// Read the ROM into a new interpreter, and look it up in the ROM database for its
+/// settings and key bindings.
+fn load_game(
+    rom: &Path,
+    keymap_file: Option<&Path>,
+    settings: &Settings,
+    database: &RomDatabase,
+    step_mode: bool,
+) -> Result<(Game, KeyBindings), String> {
+    let loaded = rom::load(rom, &settings.memory)?;
+    let mut settings = settings.clone();
+    loaded.apply(&mut settings);
+    let rom_info = database.lookup(&loaded.program);
+    if let Some(rom_info) = &rom_info {
+        rom_info.apply(&mut settings);
+    }
+    let keymap = Keymap::for_rom(keymap_file, rom, rom_info.as_ref().map(|info| &info.keys))?;
+    let name = match &rom_info {
+        Some(rom_info) => rom_info.to_string(),
+        None => rom
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_memory_map(settings.memory)?;
+    interpreter.set_font(settings.font.font()?)?;
+    interpreter.load_program_bytes(&loaded.program)?;
+    interpreter.set_quirks(settings.quirks.unwrap_or_default());
+    interpreter.set_stack_config(settings.stack);
+    let watcher = settings
+        .watch
+        .enabled
+        .then(|| RomWatcher::new(rom))
+        .flatten();
+    let game = Game {
+        emulator: Emulator::new(&interpreter, step_mode),
+        interpreter,
+        settings,
+        title: format!("Chip-8 - {}", name),
+        rom: rom.to_path_buf(),
+        watcher,
+    };
+    Ok((game, KeyBindings::new(&keymap)?))
+}
+
+/// The interpreter in an SDL window, with sound and game controllers.
+struct SdlFrontend<'a> {
+    settings: &'a Settings,
+    key_bindings: KeyBindings,
+    event_pump: EventPump,
+    controllers: Controllers,
+    sound: Sound,
+    screen: Screen<'a>,
+    canvas: Canvas<Window>,
+    /// The geometry of the window when it was last not in fullscreen.
+    windowed_geometry: WindowGeometry,
+    palette: Palette,
+    display_filter: DisplayFilter,
+    shown_framebuffer: Framebuffer,
+    buzzer_changes: Vec<(f32, bool)>,
+    recording: Option<Recording>,
+    /// When the running game started, moved on by the time the launcher was shown.
+    start_time: Instant,
+    /// What to show instead of the running game, once the current frame is done.
+    switch: Option<Next>,
+    /// A line of text shown over the display for a while, and since when.
+    notice: Option<(String, Instant)>,
+}
+
+impl<'a> SdlFrontend<'a> {
+    /// Handle an action that concerns the window rather than the interpreter.
+    fn window_action(&mut self, action: Action) -> Result<(), String> {
+        match action {
+            Action::CyclePalette => {
+                self.palette = self.palette.next_preset();
+                info!("Palette: {}", self.palette);
+            }
+            Action::Screenshot => {
+                match screenshot::save(
+                    &self.settings.screenshot.directory,
+                    &self.shown_framebuffer,
+                    &self.palette,
+                    self.settings.screenshot.scale,
+                ) {
+                    Ok(path) => info!("Saved screenshot {}", path.display()),
+                    Err(e) => warn!("{}", e),
+                }
+            }
+            Action::Record => recording::toggle(&mut self.recording, self.settings, &self.palette),
+            Action::ToggleFullscreen => {
+                let fullscreen = self.canvas.window().fullscreen_state() != FullscreenType::Off;
+                if !fullscreen {
+                    self.windowed_geometry = window_geometry(self.canvas.window());
+                }
+                set_fullscreen(&mut self.canvas, !fullscreen)?;
+            }
+            _ => {}
         }
+        Ok(())
+    }
 
-        redraw_screen(&mut canvas, pixel_states).expect("Failed to redraw screen");
-        sleep(Duration::new(0, 1_000_000_000u32 / DEFAULT_REFRESH_RATE));
+    /// Prepare the window and sound for a newly loaded game.
+    fn start_game(&mut self, game: &Game) -> Result<(), String> {
+        if let Some(active) = self.recording.take() {
+            recording::finish(active);
+        }
+        self.canvas
+            .window_mut()
+            .set_title(&game.title)
+            .map_err(|e| e.to_string())?;
+        self.palette = game.settings.display.palette.clone().unwrap_or_default();
+        self.display_filter = DisplayFilter::new(self.settings.display.filter);
+        self.shown_framebuffer = *game.interpreter.framebuffer();
+        self.buzzer_changes.clear();
+        self.start_time = Instant::now();
+        self.sound.restart();
+        self.notice = None;
+        Ok(())
+    }
+
+    /// Load the changed ROM of the game into its interpreter, and show how it went.
+    fn reload(&mut self, game: &mut Game) {
+        let keep_state = game.settings.watch.keep_state;
+        let reloaded = rom::load(&game.rom, &game.settings.memory)
+            .and_then(|loaded| game.interpreter.reload_program(&loaded.program, keep_state));
+        let notice = match reloaded {
+            Ok(true) => "ROM reloaded, state kept".to_string(),
+            Ok(false) => "ROM reloaded".to_string(),
+            Err(e) => {
+                warn!("{}", e);
+                "ROM could not be reloaded".to_string()
+            }
+        };
+        info!("{}: {}", notice, game.rom.display());
+        self.notice = Some((notice, Instant::now()));
+    }
+
+    /// Show the launcher until a ROM is picked or it is left. `in_game` is whether
+    /// there is a game to go back to.
+    fn launcher(
+        &mut self,
+        database: &RomDatabase,
+        directory: &Path,
+        recent: Option<&RecentRoms>,
+        message: Option<String>,
+        in_game: bool,
+    ) -> Result<Next, String> {
+        let mut launcher = Launcher::new(directory, recent, database, &self.settings.memory);
+        if let Some(message) = message {
+            launcher.set_message(message);
+        }
+        let back = || if in_game { Next::Game } else { Next::Exit };
+        // The buzzer is silent while the launcher is shown.
+        self.sound.play(self.start_time, &[(0.0, false)]);
+        loop {
+            let events: Vec<Event> = self.event_pump.poll_iter().collect();
+            for event in events {
+                let next = match event {
+                    Event::Quit { .. } => Some(Next::Exit),
+                    Event::DropFile { filename, .. } => Some(Next::Rom(filename.into())),
+
+                    Event::KeyDown {
+                        keycode, scancode, ..
+                    } => match keycode {
+                        Some(Keycode::Up) => launcher_move(&mut launcher, -1),
+                        Some(Keycode::Down) => launcher_move(&mut launcher, 1),
+                        Some(Keycode::PageUp) => launcher_move(&mut launcher, -LAUNCHER_PAGE),
+                        Some(Keycode::PageDown) => launcher_move(&mut launcher, LAUNCHER_PAGE),
+                        Some(Keycode::Home) => launcher_move(&mut launcher, isize::MIN),
+                        Some(Keycode::End) => launcher_move(&mut launcher, isize::MAX),
+                        Some(Keycode::Return) | Some(Keycode::KpEnter) => {
+                            launcher.selected().map(|rom| Next::Rom(rom.to_path_buf()))
+                        }
+                        Some(Keycode::Escape) => Some(back()),
+                        _ => {
+                            let mut next = None;
+                            for (action, _) in self.key_bindings.key_action(keycode, scancode, true)
+                            {
+                                match action {
+                                    Action::Launcher | Action::Exit => next = Some(back()),
+                                    Action::CyclePalette | Action::ToggleFullscreen => {
+                                        self.window_action(action)?
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            next
+                        }
+                    },
+
+                    Event::ControllerDeviceAdded { which, .. } => {
+                        self.controllers.add(which);
+                        None
+                    }
+                    Event::ControllerDeviceRemoved { .. } => {
+                        self.controllers.remove_detached();
+                        None
+                    }
+                    Event::ControllerButtonDown { button, .. } => match button {
+                        Button::DPadUp => launcher_move(&mut launcher, -1),
+                        Button::DPadDown => launcher_move(&mut launcher, 1),
+                        Button::A | Button::Start => {
+                            launcher.selected().map(|rom| Next::Rom(rom.to_path_buf()))
+                        }
+                        Button::B | Button::Back => Some(back()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some(next) = next {
+                    return Ok(next);
+                }
+            }
+
+            launcher.draw(&mut self.canvas, &self.palette, in_game)?;
+            self.wait();
+        }
     }
 
-    Ok(())
+    /// Finish the recording, and remember the geometry of the window.
+    fn finish(mut self) {
+        if let Some(active) = self.recording.take() {
+            recording::finish(active);
+        }
+        if self.settings.window.remember_geometry {
+            let fullscreen = self.canvas.window().fullscreen_state() != FullscreenType::Off;
+            if !fullscreen {
+                self.windowed_geometry = window_geometry(self.canvas.window());
+            }
+            let geometry = WindowGeometry {
+                fullscreen,
+                ..self.windowed_geometry
+            };
+            if let Err(e) = geometry.save() {
+                warn!("Could not remember window geometry: {}", e);
+            }
+        }
+    }
 }
 
-fn redraw_screen(
-    canvas: &mut Canvas<Window>,
-    pixel_states: [[bool; SCREEN_Y]; SCREEN_X],
-) -> Result<(), String> {
-    for i in 0..pixel_states.len() {
-        for j in 0..pixel_states[i].len() {
-            if pixel_states[i][j] {
-                canvas.set_draw_color(WHITE)
-            } else {
-                canvas.set_draw_color(BLACK)
+impl<'a> Frontend for SdlFrontend<'a> {
+    fn present(
+        &mut self,
+        framebuffer: &Framebuffer,
+        dirty: Option<DirtyRect>,
+        advanced: bool,
+    ) -> Result<(), String> {
+        self.shown_framebuffer = *framebuffer;
+        // Frames are only recorded while the interpreter runs.
+        if let Some(active) = self.recording.as_mut().filter(|_| advanced) {
+            if let Err(e) = active.add_frame(framebuffer, &self.palette, &self.buzzer_changes) {
+                warn!("{}", e);
+                self.recording = None;
             }
-            canvas.draw_point(Point::new(i as i32, j as i32))?;
         }
+
+        let (intensities, changed) = self.display_filter.apply(framebuffer, dirty);
+        let notice = self
+            .notice
+            .as_ref()
+            .filter(|(_, shown)| shown.elapsed() < Duration::from_secs(NOTICE_SECONDS))
+            .map(|(notice, _)| notice.as_str());
+        self.screen.redraw(
+            &mut self.canvas,
+            intensities,
+            changed,
+            &self.palette,
+            &self.settings.window,
+            notice,
+        )
+    }
+
+    fn set_buzzer(&mut self, changes: &[(f32, bool)]) {
+        self.sound.play(self.start_time, changes);
+        self.buzzer_changes = changes.to_vec();
+    }
+
+    fn poll_input(&mut self) -> Result<Vec<(Action, bool)>, String> {
+        let mut inputs = Vec::new();
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
+            let actions = match event {
+                Event::Quit { .. } => vec![(Action::Exit, true)],
+                Event::DropFile { filename, .. } => {
+                    self.switch = Some(Next::Rom(filename.into()));
+                    Vec::new()
+                }
+
+                Event::KeyDown {
+                    keycode, scancode, ..
+                } => self.key_bindings.key_action(keycode, scancode, true),
+                Event::KeyUp {
+                    keycode, scancode, ..
+                } => self.key_bindings.key_action(keycode, scancode, false),
+
+                Event::ControllerDeviceAdded { which, .. } => {
+                    self.controllers.add(which);
+                    Vec::new()
+                }
+                Event::ControllerDeviceRemoved { .. } => {
+                    self.controllers.remove_detached();
+                    Vec::new()
+                }
+                Event::ControllerButtonDown { button, .. } => {
+                    self.key_bindings.button_action(button, true)
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    self.key_bindings.button_action(button, false)
+                }
+                Event::ControllerAxisMotion { axis, value, .. }
+                    if self.key_bindings.analog_stick =>
+                {
+                    let key_bindings = &self.key_bindings;
+                    self.controllers
+                        .stick_moved(axis, value)
+                        .into_iter()
+                        .flat_map(|(button, pressed)| key_bindings.button_action(button, pressed))
+                        .collect()
+                }
+
+                _ => Vec::new(),
+            };
+
+            for (action, pressed) in actions {
+                match action {
+                    Action::CyclePalette
+                    | Action::Screenshot
+                    | Action::Record
+                    | Action::ToggleFullscreen => {
+                        if pressed {
+                            self.window_action(action)?;
+                        }
+                    }
+                    Action::Launcher => {
+                        if pressed {
+                            self.switch = Some(Next::Launcher);
+                        }
+                    }
+                    _ => inputs.push((action, pressed)),
+                }
+            }
+        }
+        Ok(inputs)
+    }
+
+    fn wall_time(&self) -> u128 {
+        self.start_time.elapsed().as_micros()
+    }
+
+    fn wait(&mut self) {
+        sleep(Duration::new(0, 1_000_000_000u32 / DEFAULT_REFRESH_RATE));
+    }
+}
+
+/// Move the selection of the launcher, which leaves it open.
+fn launcher_move(launcher: &mut Launcher, steps: isize) -> Option<Next> {
+    launcher.move_selection(steps);
+    None
+}
+
+fn window_geometry(window: &Window) -> WindowGeometry {
+    let (x, y) = window.position();
+    let (width, height) = window.size();
+    WindowGeometry {
+        x,
+        y,
+        width,
+        height,
+        fullscreen: window.fullscreen_state() != FullscreenType::Off,
     }
-    canvas.present();
-    Ok(())
 }
 
-fn prepare_sound(audio_subsystem: &sdl2::AudioSubsystem) -> AudioDevice<SquareWave> {
-    let desired_spec = AudioSpecDesired {
-        freq: Some(44100),
-        channels: Some(1),
-        samples: None,
+fn set_fullscreen(canvas: &mut Canvas<Window>, fullscreen: bool) -> Result<(), String> {
+    let fullscreen_type = if fullscreen {
+        FullscreenType::Desktop
+    } else {
+        FullscreenType::Off
     };
+    canvas.window_mut().set_fullscreen(fullscreen_type)
+}
 
-    let device = audio_subsystem
-        .open_playback(None, &desired_spec, |spec| SquareWave {
-            phase_inc: 440.0 / spec.freq as f32,
-            phase: 0.0,
-            volume: 0.25,
+/// Draws the display into the window. The display is uploaded into a texture of
+/// one texel per CHIP-8 pixel, which the renderer scales up to the window.
+struct Screen<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>,
+    texture: Texture<'a>,
+    /// The palette the texture was last uploaded with, if any.
+    palette: Option<Palette>,
+    /// The dark lines of the screen effect, for a display of the given size.
+    overlay: Option<(u32, u32, Texture<'a>)>,
+}
+
+impl<'a> Screen<'a> {
+    fn new(texture_creator: &'a TextureCreator<WindowContext>) -> Result<Self, String> {
+        let texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_X as u32, SCREEN_Y as u32)
+            .map_err(|e| e.to_string())?;
+        Ok(Screen {
+            texture_creator,
+            texture,
+            palette: None,
+            overlay: None,
         })
-        .unwrap();
+    }
+
+    fn redraw(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        intensities: &Intensities,
+        dirty: Option<DirtyRect>,
+        palette: &Palette,
+        window_settings: &WindowSettings,
+        notice: Option<&str>,
+    ) -> Result<(), String> {
+        // Only the rows that changed are uploaded into the texture, unless all of it
+        // has to be coloured anew.
+        let dirty = if self.palette.as_ref() != Some(palette) {
+            self.palette = Some(palette.clone());
+            Some(DirtyRect::full())
+        } else {
+            dirty
+        };
+        if let Some(dirty) = dirty {
+            let rows = &intensities[dirty.y..dirty.y + dirty.height];
+            let colors = display_colors(rows, palette);
+            let area = Rect::new(0, dirty.y as i32, SCREEN_X as u32, dirty.height as u32);
+            self.texture
+                .update(area, &colors, SCREEN_X * 3)
+                .map_err(|e| e.to_string())?;
+        }
 
-    device.pause();
-    device
+        let (width, height) = canvas.output_size()?;
+        let viewport = Viewport::fit(
+            width,
+            height,
+            window_settings.pixel_aspect,
+            window_settings.integer_scaling,
+        );
+        let display = to_rect(viewport.display_rect());
+
+        canvas.set_draw_color(BORDER);
+        canvas.clear();
+        canvas.copy(&self.texture, None, display)?;
+        if window_settings.effect != Effect::None {
+            let overlay = self.overlay(&viewport, window_settings.effect)?;
+            canvas.copy(overlay, None, display)?;
+        }
+        if let Some(notice) = notice {
+            draw_notice(canvas, notice, palette)?;
+        }
+        canvas.present();
+        Ok(())
+    }
+
+    /// A texture of the size of the display that darkens the lines between pixels
+    /// when drawn over it. It is only created again when the size of the display changes.
+    fn overlay(&mut self, viewport: &Viewport, effect: Effect) -> Result<&Texture<'a>, String> {
+        let (x, y, width, height) = viewport.display_rect();
+        let stale = match &self.overlay {
+            Some((overlay_width, overlay_height, _)) => {
+                (*overlay_width, *overlay_height) != (width, height)
+            }
+            None => true,
+        };
+        if stale {
+            // Black in RGBA8888, where the alpha is the lowest byte.
+            let dark = (EFFECT_DARKENING * 255.0) as u32;
+            let mut pixels = vec![dark; (width * height) as usize];
+            for i in 0..SCREEN_X {
+                for j in 0..SCREEN_Y {
+                    let (lit_x, lit_y, lit_width, lit_height) =
+                        Viewport::lit_rect(viewport.pixel_rect(i, j), effect);
+                    for row in (lit_y - y)..(lit_y - y + lit_height as i32) {
+                        let start = row as usize * width as usize + (lit_x - x) as usize;
+                        for pixel in &mut pixels[start..start + lit_width as usize] {
+                            *pixel = 0;
+                        }
+                    }
+                }
+            }
+            let mut texture = self
+                .texture_creator
+                .create_texture_static(PixelFormatEnum::RGBA8888, width.max(1), height.max(1))
+                .map_err(|e| e.to_string())?;
+            let bytes: Vec<u8> = pixels
+                .iter()
+                .flat_map(|p| p.to_ne_bytes().to_vec())
+                .collect();
+            texture
+                .update(None, &bytes, width as usize * 4)
+                .map_err(|e| e.to_string())?;
+            texture.set_blend_mode(BlendMode::Blend);
+            self.overlay = Some((width, height, texture));
+        }
+        Ok(&self.overlay.as_ref().expect("Overlay was just created").2)
+    }
+}
+
+/// Draw a line of text across the top of the window, in the size of the launcher text.
+fn draw_notice(canvas: &mut Canvas<Window>, notice: &str, palette: &Palette) -> Result<(), String> {
+    let (width, height) = canvas.output_size()?;
+    let scale = (height / (LAUNCHER_MIN_LINES * LAUNCHER_LINE_HEIGHT as u32)).max(1);
+    canvas.set_scale(scale as f32, scale as f32)?;
+    canvas.set_draw_color(to_color(palette.background()));
+    canvas.fill_rect(Rect::new(0, 0, width / scale, LAUNCHER_LINE_HEIGHT as u32))?;
+    launcher::draw_text(canvas, 0, notice, to_color(palette.foreground()))?;
+    canvas.set_scale(1.0, 1.0)
 }
 
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32,
+/// The colours of rows of the display as RGB in row-major order.
+fn display_colors(rows: &[[f32; SCREEN_X]], palette: &Palette) -> Vec<u8> {
+    let foreground = palette.foreground();
+    let background = palette.background();
+    let mut colors = Vec::with_capacity(SCREEN_X * rows.len() * 3);
+    for row in rows {
+        for intensity in row.iter() {
+            let color = background.mix(foreground, *intensity);
+            colors.extend_from_slice(&[color.0, color.1, color.2]);
+        }
+    }
+    colors
 }
 
-impl AudioCallback for SquareWave {
-    type Channel = f32;
+/// Lookup of the action bound to an SDL key or game controller button.
+struct KeyBindings {
+    /// The action bound to a key symbol, and whether the binding is explicit.
+    keycodes: HashMap<Keycode, (Action, bool)>,
+    /// The action bound to a key position, and whether the binding is explicit.
+    scancodes: HashMap<Scancode, (Action, bool)>,
+    buttons: HashMap<Button, Action>,
+    analog_stick: bool,
+}
 
-    fn callback(&mut self, out: &mut [f32]) {
-        for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
+impl KeyBindings {
+    fn new(keymap: &Keymap) -> Result<Self, String> {
+        let mut key_bindings = KeyBindings {
+            keycodes: HashMap::new(),
+            scancodes: HashMap::new(),
+            buttons: HashMap::new(),
+            analog_stick: keymap.analog_stick(),
+        };
+        for (action, binding) in keymap.bindings() {
+            let bound = (action, keymap.is_explicit(binding));
+            let duplicate = match binding {
+                Binding::Key(name) => {
+                    let keycode = Keycode::from_name(name)
+                        .ok_or(format!("Unknown key name in keymap: {}", name))?;
+                    key_bindings.keycodes.insert(keycode, bound)
+                }
+                Binding::Scancode(name) => {
+                    let scancode = Scancode::from_name(name)
+                        .ok_or(format!("Unknown scancode name in keymap: {}", name))?;
+                    key_bindings.scancodes.insert(scancode, bound)
+                }
             };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+            if let Some((other, _)) = duplicate {
+                if other != action {
+                    return Err(format!("Key {} is bound to more than one action", binding));
+                }
+            }
+        }
+        for (action, name) in keymap.button_bindings() {
+            let button = Button::from_string(name)
+                .ok_or(format!("Unknown controller button in keymap: {}", name))?;
+            if let Some(other) = key_bindings.buttons.insert(button, action) {
+                if other != action {
+                    return Err(format!("Button {} is bound to more than one action", name));
+                }
+            }
+        }
+        Ok(key_bindings)
+    }
+
+    /// The action bound to a key, together with whether it was pressed or released.
+    /// Bindings from the keymap file take precedence over default bindings, then
+    /// bindings to the physical key position over bindings to the key symbol.
+    fn key_action(
+        &self,
+        keycode: Option<Keycode>,
+        scancode: Option<Scancode>,
+        pressed: bool,
+    ) -> Vec<(Action, bool)> {
+        let by_position = scancode.and_then(|s| self.scancodes.get(&s));
+        let by_symbol = keycode.and_then(|k| self.keycodes.get(&k));
+        let bound = match (by_position, by_symbol) {
+            (Some((_, false)), Some((action, true))) => Some(action),
+            (Some((action, _)), _) | (None, Some((action, _))) => Some(action),
+            (None, None) => None,
+        };
+        bound.map(|action| (*action, pressed)).into_iter().collect()
+    }
+
+    /// The action bound to a controller button, together with whether it was pressed or released.
+    fn button_action(&self, button: Button, pressed: bool) -> Vec<(Action, bool)> {
+        self.buttons
+            .get(&button)
+            .map(|action| (*action, pressed))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// The connected game controllers. Controllers must stay open for SDL to deliver
+/// their events.
+struct Controllers {
+    subsystem: GameControllerSubsystem,
+    open: Vec<GameController>,
+    /// The D-pad direction that the left stick is pushed to, per axis.
+    stick_directions: [Option<Button>; 2],
+}
+
+impl Controllers {
+    fn new(subsystem: GameControllerSubsystem) -> Self {
+        Controllers {
+            subsystem,
+            open: Vec::new(),
+            stick_directions: [None, None],
+        }
+    }
+
+    /// Open a newly connected controller. SDL also reports the controllers that are
+    /// already connected on startup through this.
+    fn add(&mut self, joystick_index: u32) {
+        match self.subsystem.open(joystick_index) {
+            Ok(controller) => {
+                info!("Game controller connected: {}", controller.name());
+                self.open.push(controller);
+            }
+            Err(e) => warn!("Could not open game controller: {}", e),
+        }
+    }
+
+    /// Close all controllers that have been disconnected.
+    fn remove_detached(&mut self) {
+        self.open.retain(|controller| {
+            if !controller.attached() {
+                info!("Game controller disconnected: {}", controller.name());
+            }
+            controller.attached()
+        });
+    }
+
+    /// Translate a left stick movement into D-pad presses and releases.
+    fn stick_moved(&mut self, axis: Axis, value: i16) -> Vec<(Button, bool)> {
+        let (index, negative, positive) = match axis {
+            Axis::LeftX => (0, Button::DPadLeft, Button::DPadRight),
+            Axis::LeftY => (1, Button::DPadUp, Button::DPadDown),
+            _ => return Vec::new(),
+        };
+        let direction = if value <= -ANALOG_STICK_DEAD_ZONE {
+            Some(negative)
+        } else if value >= ANALOG_STICK_DEAD_ZONE {
+            Some(positive)
+        } else {
+            None
+        };
+
+        let mut changes = Vec::new();
+        let previous = self.stick_directions[index];
+        if previous != direction {
+            if let Some(button) = previous {
+                changes.push((button, false));
+            }
+            if let Some(button) = direction {
+                changes.push((button, true));
+            }
+            self.stick_directions[index] = direction;
+        }
+        changes
+    }
+}
+
+fn to_color(rgb: Rgb) -> Color {
+    Color::RGB(rgb.0, rgb.1, rgb.2)
+}
+
+fn to_rect(rect: ScreenRect) -> Rect {
+    Rect::new(rect.0, rect.1, rect.2, rect.3)
+}
+
+/// Plays the buzzer through an SDL audio queue. The sound is generated in steps as
+/// time passes, so the buzzer can be switched on and off at the exact emulated time.
+struct Sound {
+    queue: AudioQueue<f32>,
+    buzzer: Buzzer,
+    sample_rate: u128,
+    latency: u128,
+    samples_queued: u128,
+}
+
+impl Sound {
+    fn new(audio_subsystem: &sdl2::AudioSubsystem, tone: Tone) -> Result<Self, String> {
+        let desired_spec = AudioSpecDesired {
+            freq: Some(AUDIO_SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+        let queue = audio_subsystem.open_queue::<f32, _>(None, &desired_spec)?;
+        let sample_rate = queue.spec().freq;
+        queue.resume();
+
+        Ok(Sound {
+            queue,
+            buzzer: Buzzer::new(sample_rate, tone),
+            sample_rate: sample_rate as u128,
+            latency: (AUDIO_LATENCY_SECONDS * sample_rate as f32) as u128,
+            samples_queued: 0,
+        })
+    }
+
+    /// Start the sound of a new game, whose time starts from 0.
+    fn restart(&mut self) {
+        self.samples_queued = 0;
+    }
+
+    /// Queue the sound up to the current time, switching the buzzer on and off at the
+    /// given positions.
+    fn play(&mut self, start_time: Instant, changes: &[(f32, bool)]) {
+        let due = start_time.elapsed().as_micros() * self.sample_rate / 1_000_000 + self.latency;
+        let mut samples = vec![0.0; due.saturating_sub(self.samples_queued) as usize];
+        self.buzzer.render(&mut samples, changes);
+        self.samples_queued += samples.len() as u128;
+
+        // Samples are dropped rather than queued if the queue has filled up, e.g. because
+        // the window was blocked, so that the sound does not lag behind.
+        let queued = self.queue.size() as usize / std::mem::size_of::<f32>();
+        if queued < 2 * self.latency as usize {
+            self.queue.queue(&samples);
         }
     }
 }
@@ -0,0 +1,308 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::buzzer::{Tone, Waveform};
+use crate::config::{
+    AUDIO_SAMPLE_RATE, DEFAULT_BUZZER_FREQUENCY, DEFAULT_BUZZER_VOLUME, DEFAULT_FAST_FORWARD,
+    DEFAULT_KEY_HOLD_SECONDS, DEFAULT_RECORDING_DIRECTORY, DEFAULT_RECORDING_SCALE,
+    DEFAULT_ROM_DIRECTORY, DEFAULT_SCREENSHOT_DIRECTORY, DEFAULT_SPEED,
+};
+use crate::filter::Filter;
+use crate::interpreter::{Font, FontSet, MemoryMap, Quirks, StackConfig};
+use crate::palette::Palette;
+use crate::recording::{self, RecordingFormat};
+use crate::terminal::TerminalGraphics;
+use crate::viewport::{Effect, PixelAspect};
+
+/// Settings of the interpreter frontend. They are read from a TOML settings file,
+/// and can be overridden on the command line.
+#[derive(Clone, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub emulation: EmulationSettings,
+    pub display: DisplaySettings,
+    pub window: WindowSettings,
+    pub audio: AudioSettings,
+    pub screenshot: ScreenshotSettings,
+    pub recording: RecordingSettings,
+    pub terminal: TerminalSettings,
+    pub launcher: LauncherSettings,
+    pub database: DatabaseSettings,
+    pub watch: WatchSettings,
+    /// Where the program and the font are placed in memory, and how large it is.
+    pub memory: MemoryMap,
+    pub font: FontSettings,
+    /// The depth of the call stack, and what happens when a program exceeds it.
+    pub stack: StackConfig,
+    /// The behaviours of the platform that programs were written for. Without them,
+    /// they are taken from the ROM database, or else left at their defaults.
+    pub quirks: Option<Quirks>,
+}
+
+/// How the interpreter runs.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmulationSettings {
+    /// The speed of the instructions, as multiple of their timing on the COSMAC VIP.
+    /// The timers always count down at 60 Hz.
+    pub speed: f32,
+    /// The speed while fast-forwarding, as multiple of the normal speed.
+    pub fast_forward: f32,
+    /// Run this many instructions per frame, all taking the same time, instead of
+    /// timing every instruction like the COSMAC VIP. Without it, it is taken from
+    /// the ROM database if the program is known.
+    pub instructions_per_frame: Option<u32>,
+}
+
+impl Default for EmulationSettings {
+    fn default() -> Self {
+        EmulationSettings {
+            speed: DEFAULT_SPEED,
+            fast_forward: DEFAULT_FAST_FORWARD,
+            instructions_per_frame: None,
+        }
+    }
+}
+
+/// How the screen of the interpreter is drawn.
+#[derive(Clone, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplaySettings {
+    /// The colours of the pixels. Without them, they are taken from the ROM database
+    /// or the Octo options, or else the classic palette is used.
+    pub palette: Option<Palette>,
+    /// The filter against flicker that the display is shown through.
+    pub filter: Filter,
+    /// Only show the display when it is settled, instead of on every frame.
+    pub draw_on_vblank: bool,
+}
+
+/// Size and look of the window.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowSettings {
+    /// The size of a CHIP-8 pixel in the window at start. Without it, the window
+    /// opens with the size it had when it was last closed.
+    pub scale: Option<u32>,
+    /// Whether to start in fullscreen. Without it, the window opens in the mode it
+    /// had when it was last closed.
+    pub fullscreen: Option<bool>,
+    /// Only scale the display by whole numbers, so all pixels have the same size.
+    pub integer_scaling: bool,
+    /// The shape of a CHIP-8 pixel, as ratio of width to height.
+    pub pixel_aspect: PixelAspect,
+    pub effect: Effect,
+    /// Open the window with the position and size it had when it was last closed.
+    pub remember_geometry: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            scale: None,
+            fullscreen: None,
+            integer_scaling: true,
+            pixel_aspect: PixelAspect::default(),
+            effect: Effect::None,
+            remember_geometry: true,
+        }
+    }
+}
+
+/// How the buzzer sounds.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioSettings {
+    /// Frequency of the tone in Hz.
+    pub frequency: f32,
+    pub waveform: Waveform,
+    /// Volume from 0 (silent) to 1.
+    pub volume: f32,
+    pub mute: bool,
+}
+
+impl AudioSettings {
+    /// Check that the tone can be played at the sample rate of the buzzer, which
+    /// needs the frequency to be below half of it.
+    pub fn validate(&self) -> Result<(), String> {
+        let max_frequency = AUDIO_SAMPLE_RATE as f32 / 2.0;
+        if !(self.frequency > 0.0 && self.frequency < max_frequency) {
+            return Err(format!(
+                "The tone frequency of {} Hz is not between 0 and {} Hz",
+                self.frequency, max_frequency
+            ));
+        }
+        Ok(())
+    }
+
+    /// The tone that the buzzer plays.
+    pub fn tone(&self) -> Tone {
+        Tone {
+            frequency: self.frequency,
+            waveform: self.waveform,
+            volume: if self.mute {
+                0.0
+            } else {
+                self.volume.clamp(0.0, 1.0)
+            },
+        }
+    }
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            frequency: DEFAULT_BUZZER_FREQUENCY,
+            waveform: Waveform::Square,
+            volume: DEFAULT_BUZZER_VOLUME,
+            mute: false,
+        }
+    }
+}
+
+/// Where and how screenshots are saved.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScreenshotSettings {
+    /// The directory that screenshots are saved in.
+    pub directory: PathBuf,
+    /// The size of a CHIP-8 pixel in the screenshot.
+    pub scale: u32,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        ScreenshotSettings {
+            directory: PathBuf::from(DEFAULT_SCREENSHOT_DIRECTORY),
+            scale: 1,
+        }
+    }
+}
+
+/// Where and how recordings of the display are saved.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordingSettings {
+    /// The directory that recordings are saved in.
+    pub directory: PathBuf,
+    /// The file format of recordings.
+    pub format: RecordingFormat,
+    /// The size of a CHIP-8 pixel in the recording.
+    pub scale: u32,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        RecordingSettings {
+            directory: PathBuf::from(DEFAULT_RECORDING_DIRECTORY),
+            format: RecordingFormat::Gif,
+            scale: DEFAULT_RECORDING_SCALE,
+        }
+    }
+}
+
+impl Settings {
+    /// Load the settings from a TOML file. Settings that are not part of the file
+    /// keep their default.
+    pub fn from_file(path: &Path) -> Result<Settings, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read settings {}: {}", path.display(), e))?;
+        toml::from_str(&contents)
+            .map_err(|e| format!("Could not parse settings {}: {}", path.display(), e))
+    }
+
+    /// Check the settings that can not be used as they are.
+    pub fn validate(&self) -> Result<(), String> {
+        self.audio.validate()?;
+        recording::check_scale(self.recording.scale)
+    }
+}
+
+/// How the interpreter runs in the terminal.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerminalSettings {
+    pub graphics: TerminalGraphics,
+    /// Time in seconds after which a keypad key counts as released, unless the
+    /// terminal repeats it.
+    pub key_hold: f32,
+    /// Ring the terminal bell when the buzzer starts.
+    pub bell: bool,
+}
+
+impl Default for TerminalSettings {
+    fn default() -> Self {
+        TerminalSettings {
+            graphics: TerminalGraphics::default(),
+            key_hold: DEFAULT_KEY_HOLD_SECONDS,
+            bell: true,
+        }
+    }
+}
+
+/// The launcher that picks the ROM in the window.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LauncherSettings {
+    /// The directory whose ROMs are listed.
+    pub directory: PathBuf,
+}
+
+impl Default for LauncherSettings {
+    fn default() -> Self {
+        LauncherSettings {
+            directory: PathBuf::from(DEFAULT_ROM_DIRECTORY),
+        }
+    }
+}
+
+/// The database of known programs, which settings are taken from by the hash of a ROM.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSettings {
+    pub enabled: bool,
+    /// A directory with `programs.json` and `sha1-hashes.json` of the chip-8-database.
+    /// Without it, the copy built into the interpreter is used.
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        DatabaseSettings {
+            enabled: true,
+            directory: None,
+        }
+    }
+}
+
+/// Loading the ROM again when its file changes, while it is developed.
+#[derive(Clone, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchSettings {
+    pub enabled: bool,
+    /// Keep the state of the interpreter if the changed program can continue from
+    /// it, instead of starting it from the beginning.
+    pub keep_state: bool,
+}
+
+/// The font that programs draw digits with.
+#[derive(Clone, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontSettings {
+    /// One of the built-in fonts.
+    pub set: FontSet,
+    /// A file with a custom font, used instead of the built-in one.
+    pub file: Option<PathBuf>,
+}
+
+impl FontSettings {
+    /// The font from the file if there is one, or else the built-in font.
+    pub fn font(&self) -> Result<Font, String> {
+        match &self.file {
+            Some(file) => Font::from_file(file),
+            None => Ok(self.set.font()),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::frontend::for_interpreter;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod frontend;
+
+/// How the display is drawn in the terminal.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum TerminalGraphics {
+    /// Kitty graphics or sixel if the terminal is known to support them, otherwise half blocks.
+    #[default]
+    Auto,
+    /// Two pixels per character cell, with the upper half block character `▀`.
+    HalfBlock,
+    /// Eight pixels per character cell, with Unicode braille patterns. Pixels are
+    /// only on or off, without the shades of the flicker filters.
+    Braille,
+    /// Sixel images, supported by e.g. xterm, mlterm and foot.
+    Sixel,
+    /// The image protocol of the kitty terminal.
+    Kitty,
+}
+
+impl TerminalGraphics {
+    /// Pick the graphics that the running terminal supports, if set to `Auto`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn resolve(self) -> TerminalGraphics {
+        if self != TerminalGraphics::Auto {
+            return self;
+        }
+        let term = env::var("TERM").unwrap_or_default();
+        if env::var_os("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" {
+            TerminalGraphics::Kitty
+        } else if term.contains("sixel") || term.starts_with("mlterm") || term.starts_with("foot") {
+            TerminalGraphics::Sixel
+        } else {
+            TerminalGraphics::HalfBlock
+        }
+    }
+}
+
+impl FromStr for TerminalGraphics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(TerminalGraphics::Auto),
+            "halfblock" => Ok(TerminalGraphics::HalfBlock),
+            "braille" => Ok(TerminalGraphics::Braille),
+            "sixel" => Ok(TerminalGraphics::Sixel),
+            "kitty" => Ok(TerminalGraphics::Kitty),
+            _ => Err(format!(
+                "Unknown terminal graphics: {} (available: auto, halfblock, braille, sixel, kitty)",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TerminalGraphics {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for TerminalGraphics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TerminalGraphics::Auto => write!(f, "auto"),
+            TerminalGraphics::HalfBlock => write!(f, "halfblock"),
+            TerminalGraphics::Braille => write!(f, "braille"),
+            TerminalGraphics::Sixel => write!(f, "sixel"),
+            TerminalGraphics::Kitty => write!(f, "kitty"),
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::io::{self, Stdout, Write};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Color, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use log::{info, warn};
+
+use crate::config::{DEFAULT_REFRESH_RATE, SCREEN_X, SCREEN_Y, TERMINAL_IMAGE_SCALE};
+use crate::emulator;
+use crate::filter::{DisplayFilter, Intensities};
+use crate::frontend::Frontend;
+use crate::interpreter::{DirtyRect, Framebuffer, Interpreter};
+use crate::keymap::{Action, Binding, Keymap};
+use crate::logger;
+use crate::palette::{Palette, Rgb};
+use crate::recording::{self, Recording};
+use crate::screenshot;
+use crate::settings::Settings;
+use crate::terminal::TerminalGraphics;
+
+/// Run the interpreter in the terminal. Terminals only report key presses, so a
+/// key counts as released once it has not been repeated for the key hold time.
+pub fn for_interpreter(
+    interpreter: &mut Interpreter,
+    keymap: &Keymap,
+    settings: &Settings,
+    step_mode: bool,
+) -> Result<(), String> {
+    let raw_terminal = RawTerminal::enter()?;
+    let mut frontend = TerminalFrontend {
+        settings,
+        key_bindings: KeyBindings::new(keymap),
+        graphics: settings.terminal.graphics.resolve(),
+        key_hold: Duration::from_secs_f32(settings.terminal.key_hold.max(0.0)),
+        out: io::stdout(),
+        palette: settings.display.palette.clone().unwrap_or_default(),
+        display_filter: DisplayFilter::new(settings.display.filter),
+        shown_framebuffer: *interpreter.framebuffer(),
+        buzzer_changes: Vec::new(),
+        recording: None,
+        released_at: HashMap::new(),
+        buzzer_on: false,
+        last_output: Vec::new(),
+        start_time: Instant::now(),
+    };
+    let result = emulator::run(interpreter, &mut frontend, settings, step_mode);
+    if let Some(active) = frontend.recording.take() {
+        recording::finish(active);
+    }
+    drop(raw_terminal);
+    result
+}
+
+/// The interpreter in a terminal, drawn with characters or inline images.
+struct TerminalFrontend<'a> {
+    settings: &'a Settings,
+    key_bindings: KeyBindings,
+    graphics: TerminalGraphics,
+    key_hold: Duration,
+    out: Stdout,
+    palette: Palette,
+    display_filter: DisplayFilter,
+    shown_framebuffer: Framebuffer,
+    buzzer_changes: Vec<(f32, bool)>,
+    recording: Option<Recording>,
+    /// The time at which every held action counts as released.
+    released_at: HashMap<Action, Instant>,
+    buzzer_on: bool,
+    last_output: Vec<u8>,
+    start_time: Instant,
+}
+
+impl<'a> Frontend for TerminalFrontend<'a> {
+    fn present(
+        &mut self,
+        framebuffer: &Framebuffer,
+        dirty: Option<DirtyRect>,
+        advanced: bool,
+    ) -> Result<(), String> {
+        self.shown_framebuffer = *framebuffer;
+        // Frames are only recorded while the interpreter runs.
+        if let Some(active) = self.recording.as_mut().filter(|_| advanced) {
+            if let Err(e) = active.add_frame(framebuffer, &self.palette, &self.buzzer_changes) {
+                warn!("{}", e);
+                self.recording = None;
+            }
+        }
+        let (intensities, _) = self.display_filter.apply(framebuffer, dirty);
+        let output =
+            render(self.graphics, intensities, &self.palette).map_err(|e| e.to_string())?;
+        // Only changes are sent, which matters over slow connections.
+        if output != self.last_output {
+            self.out.write_all(&output).map_err(|e| e.to_string())?;
+            self.last_output = output;
+        }
+        self.out.flush().map_err(|e| e.to_string())
+    }
+
+    /// Ring the bell whenever the buzzer starts.
+    fn set_buzzer(&mut self, changes: &[(f32, bool)]) {
+        self.buzzer_changes = changes.to_vec();
+        for (_, on) in changes {
+            if *on && !self.buzzer_on && self.settings.terminal.bell {
+                let _ = self.out.write_all(b"\x07");
+            }
+            self.buzzer_on = *on;
+        }
+    }
+
+    fn poll_input(&mut self) -> Result<Vec<(Action, bool)>, String> {
+        let mut inputs = Vec::new();
+        while event::poll(Duration::from_secs(0)).map_err(|e| e.to_string())? {
+            let key = match event::read().map_err(|e| e.to_string())? {
+                Event::Key(key) => key,
+                Event::Resize(..) => {
+                    // The terminal may have lost the display, so it is drawn again.
+                    self.last_output.clear();
+                    continue;
+                }
+                _ => continue,
+            };
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                inputs.push((Action::Exit, true));
+                continue;
+            }
+            match self.key_bindings.key_action(key) {
+                Some(Action::CyclePalette) => {
+                    self.palette = self.palette.next_preset();
+                    self.last_output.clear();
+                    info!("Palette: {}", self.palette);
+                }
+                Some(Action::Record) => {
+                    recording::toggle(&mut self.recording, self.settings, &self.palette)
+                }
+                Some(Action::Screenshot) => match screenshot::save(
+                    &self.settings.screenshot.directory,
+                    &self.shown_framebuffer,
+                    &self.palette,
+                    self.settings.screenshot.scale,
+                ) {
+                    Ok(path) => info!("Saved screenshot {}", path.display()),
+                    Err(e) => warn!("{}", e),
+                },
+                Some(action) => {
+                    // Repeats of a held key do not count as new presses.
+                    let held = self
+                        .released_at
+                        .insert(action, Instant::now() + self.key_hold)
+                        .is_some();
+                    if !held {
+                        inputs.push((action, true));
+                    }
+                }
+                None => {}
+            }
+        }
+
+        let now = Instant::now();
+        let released: Vec<Action> = self
+            .released_at
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(action, _)| *action)
+            .collect();
+        for action in released {
+            self.released_at.remove(&action);
+            inputs.push((action, false));
+        }
+        Ok(inputs)
+    }
+
+    fn wall_time(&self) -> u128 {
+        self.start_time.elapsed().as_micros()
+    }
+
+    fn wait(&mut self) {
+        sleep(Duration::new(0, 1_000_000_000u32 / DEFAULT_REFRESH_RATE));
+    }
+}
+
+/// Puts the terminal into raw mode on an alternate screen, and restores it when dropped.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> Result<Self, String> {
+        terminal::enable_raw_mode().map_err(|e| e.to_string())?;
+        execute!(
+            io::stdout(),
+            EnterAlternateScreen,
+            Hide,
+            Clear(ClearType::All)
+        )
+        .map_err(|e| e.to_string())?;
+        logger::set_raw_terminal(true);
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        logger::set_raw_terminal(false);
+        let _ = execute!(io::stdout(), ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Lookup of the action bound to a key. Terminals only report the symbol of a key,
+/// so bindings to a key position use the key at that position on a US QWERTY keyboard.
+struct KeyBindings {
+    keys: HashMap<String, Action>,
+}
+
+impl KeyBindings {
+    fn new(keymap: &Keymap) -> Self {
+        let mut bindings: Vec<_> = keymap.bindings().collect();
+        // Bindings from the keymap file take precedence over default bindings, then
+        // bindings to a key symbol over bindings to a position.
+        bindings.sort_by_key(|(_, binding)| {
+            (
+                keymap.is_explicit(binding),
+                matches!(binding, Binding::Key(_)),
+            )
+        });
+        let mut keys = HashMap::new();
+        for (action, binding) in bindings {
+            match binding {
+                Binding::Key(name) | Binding::Scancode(name) => {
+                    keys.insert(name.to_uppercase(), action)
+                }
+            };
+        }
+        KeyBindings { keys }
+    }
+
+    fn key_action(&self, key: KeyEvent) -> Option<Action> {
+        let name = match key.code {
+            KeyCode::Char(' ') => "SPACE".to_string(),
+            KeyCode::Char(c) => c.to_uppercase().to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            KeyCode::Backspace => "BACKSPACE".to_string(),
+            KeyCode::Enter => "RETURN".to_string(),
+            KeyCode::Esc => "ESCAPE".to_string(),
+            KeyCode::Tab => "TAB".to_string(),
+            KeyCode::Left => "LEFT".to_string(),
+            KeyCode::Right => "RIGHT".to_string(),
+            KeyCode::Up => "UP".to_string(),
+            KeyCode::Down => "DOWN".to_string(),
+            KeyCode::Home => "HOME".to_string(),
+            KeyCode::End => "END".to_string(),
+            KeyCode::PageUp => "PAGEUP".to_string(),
+            KeyCode::PageDown => "PAGEDOWN".to_string(),
+            KeyCode::Delete => "DELETE".to_string(),
+            KeyCode::Insert => "INSERT".to_string(),
+            _ => return None,
+        };
+        self.keys.get(&name).copied()
+    }
+}
+
+/// The escape sequences that draw the display at the top left of the terminal.
+fn render(
+    graphics: TerminalGraphics,
+    intensities: &Intensities,
+    palette: &Palette,
+) -> crossterm::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    queue!(out, MoveTo(0, 0))?;
+    match graphics {
+        TerminalGraphics::Auto | TerminalGraphics::HalfBlock => {
+            render_half_blocks(&mut out, intensities, palette)?
+        }
+        TerminalGraphics::Braille => render_braille(&mut out, intensities, palette)?,
+        TerminalGraphics::Sixel => render_sixel(&mut out, intensities, palette)?,
+        TerminalGraphics::Kitty => render_kitty(&mut out, intensities, palette)?,
+    }
+    Ok(out)
+}
+
+fn render_half_blocks(
+    out: &mut Vec<u8>,
+    intensities: &Intensities,
+    palette: &Palette,
+) -> crossterm::Result<()> {
+    let color = |intensity: f32| palette.background().mix(palette.foreground(), intensity);
+    for rows in intensities.chunks(2) {
+        let mut last = None;
+        for x in 0..SCREEN_X {
+            let top = color(rows[0][x]);
+            let bottom = color(rows.get(1).map_or(0.0, |row| row[x]));
+            if last != Some((top, bottom)) {
+                queue!(
+                    out,
+                    SetForegroundColor(to_color(top)),
+                    SetBackgroundColor(to_color(bottom))
+                )?;
+                last = Some((top, bottom));
+            }
+            out.extend_from_slice("▀".as_bytes());
+        }
+        queue!(out, ResetColor)?;
+        out.extend_from_slice(b"\r\n");
+    }
+    Ok(())
+}
+
+fn render_braille(
+    out: &mut Vec<u8>,
+    intensities: &Intensities,
+    palette: &Palette,
+) -> crossterm::Result<()> {
+    // The dot of each pixel of a 2 by 4 cell, by row and column.
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    for rows in intensities.chunks(4) {
+        queue!(
+            out,
+            SetForegroundColor(to_color(palette.foreground())),
+            SetBackgroundColor(to_color(palette.background()))
+        )?;
+        for x in (0..SCREEN_X).step_by(2) {
+            let mut pattern = 0;
+            for (row, dots) in rows.iter().zip(DOTS.iter()) {
+                for (dx, dot) in dots.iter().enumerate() {
+                    if row[x + dx] >= 0.5 {
+                        pattern |= dot;
+                    }
+                }
+            }
+            let c = std::char::from_u32(0x2800 + pattern).expect("Invalid braille pattern");
+            out.extend_from_slice(c.to_string().as_bytes());
+        }
+        queue!(out, ResetColor)?;
+        out.extend_from_slice(b"\r\n");
+    }
+    Ok(())
+}
+
+fn render_sixel(
+    out: &mut Vec<u8>,
+    intensities: &Intensities,
+    palette: &Palette,
+) -> crossterm::Result<()> {
+    let scale = TERMINAL_IMAGE_SCALE;
+    let (width, height) = (SCREEN_X * scale, SCREEN_Y * scale);
+    let percent = |value: u8| value as u32 * 100 / 255;
+    let (background, foreground) = (palette.background(), palette.foreground());
+    write!(
+        out,
+        "\x1bPq\"1;1;{};{}#0;2;{};{};{}#1;2;{};{};{}",
+        width,
+        height,
+        percent(background.0),
+        percent(background.1),
+        percent(background.2),
+        percent(foreground.0),
+        percent(foreground.1),
+        percent(foreground.2)
+    )?;
+    // Every sixel covers a column of six pixels of a band.
+    for band in (0..height).step_by(6) {
+        for (color, on) in [(0, false), (1, true)].iter() {
+            write!(out, "#{}", color)?;
+            let mut sixels = Vec::with_capacity(width);
+            for x in 0..width {
+                let mut bits = 0;
+                for dy in 0..6 {
+                    let y = band + dy;
+                    if y < height && (intensities[y / scale][x / scale] >= 0.5) == *on {
+                        bits |= 1 << dy;
+                    }
+                }
+                sixels.push(63 + bits as u8);
+            }
+            write_sixel_runs(out, &sixels)?;
+            out.push(b'$');
+        }
+        out.push(b'-');
+    }
+    out.extend_from_slice(b"\x1b\\");
+    Ok(())
+}
+
+/// Write sixels with repeated runs compressed as `!count sixel`.
+fn write_sixel_runs(out: &mut Vec<u8>, sixels: &[u8]) -> io::Result<()> {
+    let mut i = 0;
+    while i < sixels.len() {
+        let run = sixels[i..].iter().take_while(|s| **s == sixels[i]).count();
+        if run > 3 {
+            write!(out, "!{}", run)?;
+            out.push(sixels[i]);
+        } else {
+            out.resize(out.len() + run, sixels[i]);
+        }
+        i += run;
+    }
+    Ok(())
+}
+
+fn render_kitty(
+    out: &mut Vec<u8>,
+    intensities: &Intensities,
+    palette: &Palette,
+) -> crossterm::Result<()> {
+    let scale = TERMINAL_IMAGE_SCALE;
+    let mut rgb = Vec::with_capacity(SCREEN_X * SCREEN_Y * scale * scale * 3);
+    for row in intensities.iter() {
+        for _ in 0..scale {
+            for intensity in row.iter() {
+                let color = palette.background().mix(palette.foreground(), *intensity);
+                for _ in 0..scale {
+                    rgb.extend_from_slice(&[color.0, color.1, color.2]);
+                }
+            }
+        }
+    }
+    let payload = base64(&rgb);
+    // The image replaces the one of the previous frame, which has the same id.
+    let chunks: Vec<&[u8]> = payload.chunks(4096).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        if i == 0 {
+            write!(
+                out,
+                "\x1b_Ga=T,i=1,f=24,s={},v={},C=1,q=2,m={};",
+                SCREEN_X * scale,
+                SCREEN_Y * scale,
+                more
+            )?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+    Ok(())
+}
+
+fn base64(data: &[u8]) -> Vec<u8> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = Vec::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(value >> (18 - 6 * i) & 0x3F) as usize]);
+            } else {
+                encoded.push(b'=');
+            }
+        }
+    }
+    encoded
+}
+
+fn to_color(rgb: Rgb) -> Color {
+    Color::Rgb {
+        r: rgb.0,
+        g: rgb.1,
+        b: rgb.2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn keymap_file_bindings_take_precedence_over_defaults() {
+        // F12 takes a screenshot by default.
+        let path = std::env::temp_dir().join(format!("chip8-terminal-{}.toml", std::process::id()));
+        fs::write(&path, "[keypad]\n1 = \"scancode:F12\"\n").unwrap();
+        let keymap = Keymap::from_file(&path, None);
+        fs::remove_file(&path).unwrap();
+        let bindings = KeyBindings::new(&keymap.unwrap());
+        let f12 = KeyEvent::new(KeyCode::F(12), KeyModifiers::NONE);
+        assert_eq!(bindings.key_action(f12), Some(Action::Keypad(1)));
+        let w = KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE);
+        assert_eq!(bindings.key_action(w), Some(Action::Keypad(4)));
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+
+#[cfg(feature = "sdl")]
+use crate::config::{SCREEN_X, SCREEN_Y};
+
+/// The shape of a CHIP-8 pixel on the screen, as ratio of width to height.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PixelAspect {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for PixelAspect {
+    fn default() -> Self {
+        PixelAspect {
+            width: 1,
+            height: 1,
+        }
+    }
+}
+
+impl FromStr for PixelAspect {
+    type Err = String;
+
+    /// Parse a ratio like `1:1` or `2:3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid pixel aspect ratio: {} (expected e.g. 2:3)", s);
+        let mut parts = s.splitn(2, ':');
+        let mut next = || -> Result<u32, String> {
+            parts
+                .next()
+                .and_then(|p| p.trim().parse().ok())
+                .filter(|p| *p > 0)
+                .ok_or_else(invalid)
+        };
+        Ok(PixelAspect {
+            width: next()?,
+            height: next()?,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for PixelAspect {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for PixelAspect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.width, self.height)
+    }
+}
+
+/// An effect that imitates the look of old screens.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Effect {
+    #[default]
+    None,
+    /// Dark lines between the rows of pixels.
+    Scanlines,
+    /// Dark lines between the rows and columns of pixels.
+    Grid,
+}
+
+impl FromStr for Effect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Effect::None),
+            "scanlines" => Ok(Effect::Scanlines),
+            "grid" => Ok(Effect::Grid),
+            _ => Err(format!(
+                "Unknown effect: {} (available: none, scanlines, grid)",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Effect {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Where the display is drawn inside the window. The display is scaled to fill the
+/// window as far as possible while keeping its aspect ratio, with bars on the sides
+/// that are left over.
+#[cfg(feature = "sdl")]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    x: i32,
+    y: i32,
+    pixel_width: f32,
+    pixel_height: f32,
+}
+
+/// A rectangle on the screen, as x, y, width and height.
+#[cfg(feature = "sdl")]
+pub type ScreenRect = (i32, i32, u32, u32);
+
+#[cfg(feature = "sdl")]
+impl Viewport {
+    /// Fit the display into a window of the given size. With integer scaling, a
+    /// CHIP-8 pixel covers a whole number of screen pixels, so all pixels are the
+    /// same size.
+    pub fn fit(width: u32, height: u32, aspect: PixelAspect, integer_scaling: bool) -> Self {
+        let unscaled_width = (SCREEN_X as u32 * aspect.width) as f32;
+        let unscaled_height = (SCREEN_Y as u32 * aspect.height) as f32;
+        let mut scale = (width as f32 / unscaled_width).min(height as f32 / unscaled_height);
+        if integer_scaling && scale >= 1.0 {
+            scale = scale.floor();
+        }
+
+        let display_width = unscaled_width * scale;
+        let display_height = unscaled_height * scale;
+        Viewport {
+            x: ((width as f32 - display_width) / 2.0) as i32,
+            y: ((height as f32 - display_height) / 2.0) as i32,
+            pixel_width: aspect.width as f32 * scale,
+            pixel_height: aspect.height as f32 * scale,
+        }
+    }
+
+    /// The area that the CHIP-8 pixel at x and y covers on the screen.
+    pub fn pixel_rect(&self, x: usize, y: usize) -> ScreenRect {
+        let left = self.x + (x as f32 * self.pixel_width) as i32;
+        let right = self.x + ((x + 1) as f32 * self.pixel_width) as i32;
+        let top = self.y + (y as f32 * self.pixel_height) as i32;
+        let bottom = self.y + ((y + 1) as f32 * self.pixel_height) as i32;
+        (left, top, (right - left) as u32, (bottom - top) as u32)
+    }
+
+    /// The area that the whole display covers on the screen.
+    pub fn display_rect(&self) -> ScreenRect {
+        let (right, bottom, _, _) = self.pixel_rect(SCREEN_X, SCREEN_Y);
+        (
+            self.x,
+            self.y,
+            (right - self.x) as u32,
+            (bottom - self.y) as u32,
+        )
+    }
+
+    /// The part of a pixel's area that stays lit with the given effect. The rest of
+    /// the area is drawn darker.
+    pub fn lit_rect(rect: ScreenRect, effect: Effect) -> ScreenRect {
+        let (x, y, width, height) = rect;
+        // Lines are only drawn where pixels are big enough to still be recognizable.
+        let gap = |size: u32| if size >= 3 { (size / 4).max(1) } else { 0 };
+        match effect {
+            Effect::None => rect,
+            Effect::Scanlines => (x, y, width, height - gap(height)),
+            Effect::Grid => (x, y, width - gap(width), height - gap(height)),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::WATCH_INTERVAL_MILLIS;
+use crate::rom;
+
+/// Watches the file of a ROM for changes, to load it again while it is developed.
+/// The modification time of the file is checked regularly, and a change is only
+/// reported once it has not changed again for one check, so that a ROM that is
+/// still being written is not loaded.
+pub struct RomWatcher {
+    file: PathBuf,
+    /// The modification time of the file when the ROM was last loaded.
+    loaded: Option<SystemTime>,
+    /// A newer modification time, which is reported if it is still the same on the next check.
+    changed: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl RomWatcher {
+    /// Watch the file of the ROM at the path, if it is read from one.
+    pub fn new(rom: &Path) -> Option<Self> {
+        let file = rom::file(rom)?;
+        Some(RomWatcher {
+            loaded: modified(&file),
+            file,
+            changed: None,
+            last_check: Instant::now(),
+        })
+    }
+
+    /// Whether the file has changed since the ROM was loaded, or since the last time
+    /// this was true.
+    pub fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < Duration::from_millis(WATCH_INTERVAL_MILLIS) {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        let modified = modified(&self.file);
+        if modified.is_none() || modified == self.loaded {
+            self.changed = None;
+            return false;
+        }
+        if modified == self.changed {
+            self.loaded = modified;
+            self.changed = None;
+            return true;
+        }
+        self.changed = modified;
+        false
+    }
+}
+
+fn modified(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,238 @@
+//! The interpreter in the browser. Built with the `web` feature for the
+//! `wasm32-unknown-unknown` target, the `chip8` library exports `WebEmulator` to
+//! JavaScript. The page in `web/` draws it on a canvas and plays the buzzer.
+
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::config::WEB_MAX_CATCH_UP_MICROS;
+use crate::database::RomDatabase;
+use crate::emulator::Emulator;
+use crate::frontend::Frontend;
+use crate::interpreter::{DirtyRect, Framebuffer, Interpreter};
+use crate::keymap::{Action, Binding, Keymap};
+use crate::palette::Palette;
+use crate::rom::Rom;
+use crate::settings::Settings;
+
+/// An interpreter with a loaded program, driven by the animation frames of the page.
+#[wasm_bindgen]
+pub struct WebEmulator {
+    interpreter: Interpreter,
+    emulator: Emulator,
+    frontend: WebFrontend,
+    settings: Settings,
+}
+
+#[wasm_bindgen]
+impl WebEmulator {
+    /// Load the program, or the program of an Octo cartridge, into a new interpreter.
+    #[wasm_bindgen(constructor)]
+    pub fn new(program: &[u8]) -> Result<WebEmulator, JsValue> {
+        let rom = Rom::from_bytes(program.to_vec()).map_err(|e| JsValue::from_str(&e))?;
+        let mut settings = Settings::default();
+        rom.apply(&mut settings);
+        if let Some(rom_info) = RomDatabase::load(&settings).lookup(&rom.program) {
+            rom_info.apply(&mut settings);
+        }
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .load_program_bytes(&rom.program)
+            .map_err(|e| JsValue::from_str(&e))?;
+        interpreter.set_quirks(settings.quirks.unwrap_or_default());
+        interpreter.set_stack_config(settings.stack);
+        Ok(WebEmulator {
+            emulator: Emulator::new(&interpreter, false),
+            frontend: WebFrontend::new(&Keymap::default(), &settings),
+            interpreter,
+            settings,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.interpreter.framebuffer().width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.interpreter.framebuffer().height()
+    }
+
+    /// Set the colours of the display, in the format of `--palette`.
+    pub fn set_palette(&mut self, palette: &str) -> Result<(), JsValue> {
+        self.frontend.palette = palette.parse().map_err(|e: String| JsValue::from_str(&e))?;
+        Ok(())
+    }
+
+    /// Set the speed of the instructions, as multiple of their timing on the COSMAC VIP.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.settings.emulation.speed = speed;
+    }
+
+    /// Handle a `keydown` event with its `code` and `key`. Return value is whether the
+    /// key is bound, so that the page can prevent its default action.
+    pub fn key_down(&mut self, code: &str, key: &str, repeat: bool) -> bool {
+        self.frontend.key_event(code, key, true, repeat)
+    }
+
+    /// Handle a `keyup` event with its `code` and `key`.
+    pub fn key_up(&mut self, code: &str, key: &str) -> bool {
+        self.frontend.key_event(code, key, false, false)
+    }
+
+    /// Run the interpreter up to the given time in milliseconds, as passed to
+    /// `requestAnimationFrame` callbacks.
+    pub fn frame(&mut self, time: f64) -> Result<(), JsValue> {
+        self.frontend.advance_to((time * 1000.0) as u128);
+        self.emulator
+            .frame(&mut self.interpreter, &mut self.frontend, &self.settings)
+            .map(|_| ())
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// The display as RGBA pixels, row by row, for an `ImageData`.
+    pub fn pixels(&self) -> Vec<u8> {
+        self.frontend.pixels.clone()
+    }
+
+    /// Whether the buzzer sounds at the end of the last frame.
+    pub fn buzzer_on(&self) -> bool {
+        self.frontend.buzzer_on
+    }
+
+    /// Frequency of the buzzer tone in Hz.
+    pub fn tone_frequency(&self) -> f32 {
+        self.settings.audio.tone().frequency
+    }
+
+    /// Waveform of the buzzer tone: square, sine, triangle or noise.
+    pub fn tone_waveform(&self) -> String {
+        self.settings.audio.tone().waveform.to_string()
+    }
+
+    /// Volume of the buzzer tone, from 0 to 1.
+    pub fn tone_volume(&self) -> f32 {
+        self.settings.audio.tone().volume
+    }
+}
+
+/// The canvas, keyboard and buzzer of the page, as seen by the emulator.
+struct WebFrontend {
+    /// Actions by key, named like `KeyboardEvent.key` in upper case.
+    keys: HashMap<String, Action>,
+    /// Actions by key position, named like `KeyboardEvent.code` without prefix.
+    positions: HashMap<String, Action>,
+    actions: Vec<(Action, bool)>,
+    palette: Palette,
+    pixels: Vec<u8>,
+    buzzer_on: bool,
+    wall_time: u128,
+    last_time: Option<u128>,
+}
+
+impl WebFrontend {
+    fn new(keymap: &Keymap, settings: &Settings) -> Self {
+        let mut keys = HashMap::new();
+        let mut positions = HashMap::new();
+        for (action, binding) in keymap.bindings() {
+            match binding {
+                Binding::Key(name) => keys.insert(name.to_uppercase(), action),
+                Binding::Scancode(name) => positions.insert(name.to_uppercase(), action),
+            };
+        }
+        let framebuffer = Framebuffer::new();
+        WebFrontend {
+            keys,
+            positions,
+            actions: Vec::new(),
+            palette: settings.display.palette.clone().unwrap_or_default(),
+            pixels: vec![0; framebuffer.width() * framebuffer.height() * 4],
+            buzzer_on: false,
+            wall_time: 0,
+            last_time: None,
+        }
+    }
+
+    /// Advance the wall time to the time of the page, catching up on at most
+    /// `WEB_MAX_CATCH_UP_MICROS` at once.
+    fn advance_to(&mut self, time: u128) {
+        if let Some(last_time) = self.last_time {
+            self.wall_time += time.saturating_sub(last_time).min(WEB_MAX_CATCH_UP_MICROS);
+        }
+        self.last_time = Some(time);
+    }
+
+    fn key_event(&mut self, code: &str, key: &str, pressed: bool, repeat: bool) -> bool {
+        // Bindings to a key symbol take precedence over bindings to a position.
+        let key = match key {
+            " " => "SPACE".to_string(),
+            "Enter" => "RETURN".to_string(),
+            key => key.to_uppercase(),
+        };
+        let position = ["Key", "Digit", "Arrow"]
+            .iter()
+            .find_map(|prefix| code.strip_prefix(prefix))
+            .unwrap_or(code)
+            .to_uppercase();
+        let action = match self
+            .keys
+            .get(&key)
+            .or_else(|| self.positions.get(&position))
+        {
+            Some(action) => *action,
+            None => return false,
+        };
+
+        match action {
+            // The page has no files to save screenshots or recordings into, the
+            // browser switches to fullscreen and closes the page itself, and the page
+            // picks the ROM.
+            Action::Screenshot
+            | Action::Record
+            | Action::ToggleFullscreen
+            | Action::Exit
+            | Action::Launcher => return false,
+            _ if repeat => {}
+            Action::CyclePalette => {
+                if pressed {
+                    self.palette = self.palette.next_preset();
+                }
+            }
+            action => self.actions.push((action, pressed)),
+        }
+        true
+    }
+}
+
+impl Frontend for WebFrontend {
+    fn present(
+        &mut self,
+        framebuffer: &Framebuffer,
+        _dirty: Option<DirtyRect>,
+        _advanced: bool,
+    ) -> Result<(), String> {
+        let background = self.palette.background();
+        let foreground = self.palette.foreground();
+        for (pixel, on) in self
+            .pixels
+            .chunks_mut(4)
+            .zip(framebuffer.pixel_rows().flatten())
+        {
+            let color = if on { foreground } else { background };
+            pixel.copy_from_slice(&[color.0, color.1, color.2, 0xFF]);
+        }
+        Ok(())
+    }
+
+    fn set_buzzer(&mut self, changes: &[(f32, bool)]) {
+        self.buzzer_on = changes.last().is_some_and(|(_, on)| *on);
+    }
+
+    fn poll_input(&mut self) -> Result<Vec<(Action, bool)>, String> {
+        Ok(self.actions.drain(..).collect())
+    }
+
+    fn wall_time(&self) -> u128 {
+        self.wall_time
+    }
+}
@@ -0,0 +1,135 @@
+use chip8::emulator::Emulator;
+use chip8::frontend::NullFrontend;
+use chip8::interpreter::{Interpreter, StackConfig, StackFaultAction};
+use chip8::keymap::Action;
+use chip8::settings::Settings;
+
+/// Sets the delay timer to 0xFF, then adds 1 to I every second instruction.
+const COUNTER: [u8; 10] = [
+    0x60, 0xFF, // V0 = 0xFF
+    0xF0, 0x15, // delay = V0
+    0x60, 0x01, // V0 = 1
+    0xF0, 0x1E, // I += V0
+    0x12, 0x06, // jump to I += V0
+];
+
+struct Run {
+    interpreter: Interpreter,
+    emulator: Emulator,
+    frontend: NullFrontend,
+    settings: Settings,
+}
+
+impl Run {
+    fn new(instructions_per_frame: u32) -> Self {
+        let mut settings = Settings::default();
+        settings.emulation.instructions_per_frame = Some(instructions_per_frame);
+        let mut interpreter = Interpreter::new();
+        interpreter.load_program_bytes(&COUNTER).unwrap();
+        Run {
+            emulator: Emulator::new(&interpreter, false),
+            interpreter,
+            frontend: NullFrontend::new(u64::MAX),
+            settings,
+        }
+    }
+
+    fn frames(&mut self, frames: usize) {
+        for _ in 0..frames {
+            let running = self
+                .emulator
+                .frame(&mut self.interpreter, &mut self.frontend, &self.settings)
+                .unwrap();
+            assert!(running);
+        }
+    }
+
+    fn press(&mut self, action: Action) {
+        self.frontend.queue_input(action, true);
+        self.frames(1);
+    }
+
+    /// The number of instructions executed so far, once the counter loop runs.
+    fn instructions(&self) -> u64 {
+        self.interpreter.cpu().i as u64 * 2 + 2
+    }
+
+    fn timer_ticks(&self) -> u8 {
+        0xFF - self.interpreter.cpu().delay
+    }
+}
+
+#[test]
+fn runs_the_instructions_per_frame_and_ticks_the_timers_once() {
+    for &instructions_per_frame in &[10, 1000, 10000, 20000, 30000] {
+        let mut run = Run::new(instructions_per_frame);
+        run.frames(2);
+        assert_eq!(run.instructions(), 2 * instructions_per_frame as u64);
+        assert_eq!(run.timer_ticks(), 2);
+    }
+}
+
+#[test]
+fn runs_nothing_while_paused() {
+    let mut run = Run::new(100);
+    run.frames(1);
+    run.press(Action::Pause);
+    run.frames(3);
+    assert_eq!(run.instructions(), 100);
+    assert_eq!(run.timer_ticks(), 1);
+
+    run.press(Action::Pause);
+    run.frames(1);
+    assert_eq!(run.instructions(), 300);
+    assert_eq!(run.timer_ticks(), 3);
+}
+
+#[test]
+fn advances_a_single_frame_while_paused() {
+    let mut run = Run::new(100);
+    run.frames(1);
+    run.press(Action::Pause);
+    run.press(Action::FrameAdvance);
+    assert_eq!(run.instructions(), 200);
+    assert_eq!(run.timer_ticks(), 2);
+    run.frames(2);
+    assert_eq!(run.instructions(), 200);
+}
+
+#[test]
+fn fast_forward_runs_several_frames_at_once() {
+    let mut run = Run::new(100);
+    run.settings.emulation.fast_forward = 4.0;
+    run.frames(1);
+    run.press(Action::FastForward);
+    assert_eq!(run.instructions(), 500);
+    assert_eq!(run.timer_ticks(), 5);
+
+    run.frontend.queue_input(Action::FastForward, false);
+    run.frames(1);
+    assert_eq!(run.instructions(), 600);
+    assert_eq!(run.timer_ticks(), 6);
+}
+
+#[test]
+fn stack_faults_fail_or_break_into_step_mode() {
+    // Return with an empty stack.
+    let underflow = [0x00, 0xEE];
+    for &(on_fault, fails) in &[
+        (StackFaultAction::Error, true),
+        (StackFaultAction::Break, false),
+    ] {
+        let mut run = Run::new(100);
+        run.interpreter.set_stack_config(StackConfig {
+            depth: 12,
+            on_fault,
+        });
+        run.interpreter.load_program_bytes(&underflow).unwrap();
+        let result = run
+            .emulator
+            .frame(&mut run.interpreter, &mut run.frontend, &run.settings);
+        assert_eq!(result.is_err(), fails);
+        // The interpreter stops before the faulting instruction.
+        assert_eq!(run.interpreter.program_counter(), 0x200);
+    }
+}
@@ -0,0 +1,84 @@
+use chip8::octo;
+use chip8::rom::Rom;
+
+/// A cartridge with the payload after its length, spread over frames of the given
+/// number of pixels.
+fn cartridge(payload: &str, frame_pixels: usize) -> Vec<u8> {
+    let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+    bytes.extend(payload.bytes());
+    encode(&bytes, frame_pixels)
+}
+
+/// A GIF image with the bytes in the low four bits of the colour indices, two pixels
+/// per byte with the high half first, like Octo writes them.
+fn encode(bytes: &[u8], frame_pixels: usize) -> Vec<u8> {
+    // Octo draws a label with the high bits; the payload must survive them.
+    let indices = bytes
+        .iter()
+        .flat_map(|byte| vec![0x10 | byte >> 4, 0x20 | byte & 0xF])
+        .collect::<Vec<u8>>();
+    let palette = (0..=255u8).flat_map(|i| vec![i, i, i]).collect::<Vec<u8>>();
+
+    let mut image = Vec::new();
+    {
+        let width = 16;
+        let height = (frame_pixels / width) as u16;
+        let mut encoder = gif::Encoder::new(&mut image, width as u16, height, &palette).unwrap();
+        for chunk in indices.chunks(frame_pixels) {
+            let mut pixels = chunk.to_vec();
+            pixels.resize(frame_pixels, 0);
+            let frame = gif::Frame::from_indexed_pixels(width as u16, height, &pixels, None);
+            encoder.write_frame(&frame).unwrap();
+        }
+    }
+    image
+}
+
+#[test]
+fn program_and_options_are_read_over_all_frames() {
+    let payload = r##"{"options":{"tickrate":20,"shiftQuirks":true,"clipQuirks":true,"fillColor":"#FFCC00","backgroundColor":"#000000"},"program":[96,255,18,0]}"##;
+    let image = cartridge(payload, 64);
+    assert!(octo::is_cartridge(&image));
+
+    let rom = Rom::from_bytes(image).unwrap();
+    assert_eq!(rom.program, vec![0x60, 0xFF, 0x12, 0x00]);
+    let options = rom.options.unwrap();
+    assert_eq!(options.tickrate, Some(20));
+    let quirks = options.quirks().unwrap();
+    assert!(quirks.shift);
+    assert!(!quirks.wrap);
+    assert!(!quirks.jump);
+    assert!(options.palette().unwrap().is_ok());
+}
+
+#[test]
+fn options_are_optional() {
+    let (program, options) = octo::read_cartridge(&cartridge(r#"{"program":[0]}"#, 256)).unwrap();
+    assert_eq!(program, vec![0]);
+    assert!(options.quirks().is_none());
+}
+
+#[test]
+fn source_code_is_assembled() {
+    let payload = r#"{"options":{"tickrate":7},"program":": main\n  loop again\n"}"#;
+    let (program, options) = octo::read_cartridge(&cartridge(payload, 256)).unwrap();
+    assert_eq!(program, vec![0x12, 0x02, 0x12, 0x02]);
+    assert_eq!(options.tickrate, Some(7));
+}
+
+#[test]
+fn source_code_errors_name_the_line() {
+    let payload = r#"{"program":": main\n  v0 := key\n  v1 +="}"#;
+    let error = octo::read_cartridge(&cartridge(payload, 256))
+        .err()
+        .unwrap();
+    assert!(error.contains("line 3"), "{}", error);
+}
+
+#[test]
+fn cut_off_payload_is_rejected() {
+    let mut bytes = 1000u32.to_be_bytes().to_vec();
+    bytes.extend(br#"{"program":[0]}"#);
+    let error = octo::read_cartridge(&encode(&bytes, 256)).err().unwrap();
+    assert!(error.contains("cut off"), "{}", error);
+}
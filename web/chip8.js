@@ -0,0 +1,109 @@
+// Runs the interpreter built with `wasm-pack build --target web --out-dir web/pkg`
+// on the canvas of index.html.
+import init, { WebEmulator } from "./pkg/chip8.js";
+
+const canvas = document.getElementById("display");
+const context = canvas.getContext("2d");
+const status = document.getElementById("status");
+const parameters = new URLSearchParams(window.location.search);
+
+let emulator = null;
+let buzzer = null;
+
+// The buzzer is an oscillator that is faded in and out, like the tone of the
+// native frontends. Browsers only allow sound after a user interaction, so it is
+// created on the first key press.
+function startBuzzer() {
+  if (buzzer || !emulator) {
+    return;
+  }
+  const audio = new AudioContext();
+  const oscillator = audio.createOscillator();
+  const waveform = emulator.tone_waveform();
+  // WebAudio has no noise oscillator, so noise falls back to a square wave.
+  oscillator.type = waveform === "noise" ? "square" : waveform;
+  oscillator.frequency.value = emulator.tone_frequency();
+  const gain = audio.createGain();
+  gain.gain.value = 0;
+  oscillator.connect(gain).connect(audio.destination);
+  oscillator.start();
+  buzzer = { audio, gain, on: false };
+}
+
+function updateBuzzer() {
+  if (!buzzer || buzzer.on === emulator.buzzer_on()) {
+    return;
+  }
+  buzzer.on = emulator.buzzer_on();
+  const volume = buzzer.on ? emulator.tone_volume() : 0;
+  buzzer.gain.gain.setTargetAtTime(volume, buzzer.audio.currentTime, 0.002);
+}
+
+function frame(time) {
+  try {
+    emulator.frame(time);
+  } catch (e) {
+    status.textContent = e;
+    emulator = null;
+    return;
+  }
+  const image = new ImageData(
+    new Uint8ClampedArray(emulator.pixels()),
+    emulator.width(),
+    emulator.height(),
+  );
+  context.putImageData(image, 0, 0);
+  updateBuzzer();
+  window.requestAnimationFrame(frame);
+}
+
+function start(program) {
+  const running = emulator !== null;
+  try {
+    emulator = new WebEmulator(new Uint8Array(program));
+    if (parameters.has("palette")) {
+      emulator.set_palette(parameters.get("palette"));
+    }
+    if (parameters.has("speed")) {
+      emulator.set_speed(parseFloat(parameters.get("speed")));
+    }
+  } catch (e) {
+    status.textContent = e;
+    return;
+  }
+  status.textContent = "";
+  canvas.focus();
+  if (!running) {
+    window.requestAnimationFrame(frame);
+  }
+}
+
+document.addEventListener("keydown", (event) => {
+  startBuzzer();
+  if (emulator && emulator.key_down(event.code, event.key, event.repeat)) {
+    event.preventDefault();
+  }
+});
+
+document.addEventListener("keyup", (event) => {
+  if (emulator && emulator.key_up(event.code, event.key)) {
+    event.preventDefault();
+  }
+});
+
+document.getElementById("rom-file").addEventListener("change", async (event) => {
+  const file = event.target.files[0];
+  if (file) {
+    start(await file.arrayBuffer());
+  }
+});
+
+await init();
+if (parameters.has("rom")) {
+  const response = await fetch(parameters.get("rom"));
+  if (response.ok) {
+    start(await response.arrayBuffer());
+  } else {
+    status.textContent = `Could not load ${parameters.get("rom")}: ${response.status}`;
+  }
+}